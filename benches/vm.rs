@@ -0,0 +1,69 @@
+//! Compares `Eval::eval` with the bytecode VM on recursive functions of the
+//! prelude. Run with `cargo bench`.
+
+use arith::{
+    module,
+    source::SourceFile,
+    syntax::{Context, TyContext},
+};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Prelude functions and the numbers they are applied to.
+const CALLS: &[(&str, &[usize])] = &[
+    ("plus", &[30, 30]),
+    ("times", &[12, 12]),
+    ("eq", &[40, 40]),
+    ("fact", &[5]),
+    ("fib", &[12]),
+];
+
+/// The best of a few runs, which is the least disturbed by other work.
+fn time(mut f: impl FnMut()) -> Duration {
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!(
+        "{:<16} {:>12} {:>12} {:>9}",
+        "call", "eval", "vm", "speedup"
+    );
+    for (fun, args) in CALLS {
+        let args: Vec<_> = args.iter().map(|n| n.to_string()).collect();
+        let name = format!("{} {}", fun, args.join(" "));
+
+        let tyctx = &mut TyContext::new();
+        let program = module::load(
+            Rc::new(SourceFile::new(name.clone(), name.clone())),
+            tyctx,
+            true,
+        )
+        .unwrap_or_else(|d| d.abort());
+        program.check_defs(tyctx).unwrap_or_else(|d| d.abort());
+        let body = program.body().unwrap_or_else(|d| d.abort());
+
+        let eval = program.eval();
+        let term = program.link(body, tyctx);
+        let slow = time(|| {
+            eval.eval(&term, &mut Context::default(), tyctx);
+        });
+        let chunk = program.compile(body);
+        let fast = time(|| {
+            chunk.run(&eval, tyctx).unwrap();
+        });
+        println!(
+            "{:<16} {:>12.2?} {:>12.2?} {:>8.0}x",
+            name,
+            slow,
+            fast,
+            slow.as_secs_f64() / fast.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! A language server for arith, speaking LSP over stdio. It publishes
+//! diagnostics, shows the type of the term under the cursor and the doc
+//! comments of definitions on hover, jumps from a variable to its lambda or
+//! definition and formats documents.
+
+use arith::{
+    cst::{self, Element, Node, NodeKind},
+    err::{self, Diagnostic, Severity},
+    format,
+    json::Json,
+    module, pretty,
+    source::SourceFile,
+    span::Span,
+    syntax::{TyContext, TypeMap},
+};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+use std::rc::Rc;
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+#[derive(Default)]
+struct Server {
+    docs: HashMap<String, Rc<SourceFile>>,
+    shutdown: bool,
+}
+
+fn main() {
+    let mut server = Server::default();
+    let mut input = io::stdin().lock();
+    loop {
+        match read_message(&mut input) {
+            Ok(Some(msg)) => server.handle(&msg),
+            Ok(None) => break,
+            Err(e) => send(&error(Json::Null, PARSE_ERROR, &e)),
+        }
+    }
+    exit(if server.shutdown { 0 } else { 1 });
+}
+
+impl Server {
+    fn handle(&mut self, msg: &Json) {
+        let id = msg.get("id").cloned();
+        let params = msg.get("params").unwrap_or(&Json::Null);
+        let method = match msg.get("method").and_then(Json::as_str) {
+            Some(method) => method,
+            // A response to a request of ours; we never send any.
+            None => return,
+        };
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "exit" => exit(if self.shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                let doc = params.get("textDocument");
+                let text = doc.and_then(|d| d.get("text")).and_then(Json::as_str);
+                if let (Some(uri), Some(text)) = (uri(params), text) {
+                    self.open(uri, text);
+                }
+                return;
+            }
+            "textDocument/didChange" => {
+                // We ask for full syncs, so the last change has the whole text.
+                let text = match params.get("contentChanges") {
+                    Some(Json::Array(changes)) => changes.last().and_then(|c| c.get("text")),
+                    _ => None,
+                };
+                if let (Some(uri), Some(text)) = (uri(params), text.and_then(Json::as_str)) {
+                    self.open(uri, text);
+                }
+                return;
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = uri(params) {
+                    self.docs.remove(uri);
+                    publish(uri, vec![]);
+                }
+                return;
+            }
+            "textDocument/hover" => self.at_position(params, hover),
+            "textDocument/definition" => self.at_position(params, definition),
+            "textDocument/formatting" => match uri(params).and_then(|uri| self.docs.get(uri)) {
+                Some(src) => Ok(formatting(src)),
+                None => Err("Unknown document".to_owned()),
+            },
+            _ => {
+                if let Some(id) = id {
+                    let msg = format!("Unknown method: {}", method);
+                    send(&error(id, METHOD_NOT_FOUND, &msg));
+                }
+                return;
+            }
+        };
+
+        if let Some(id) = id {
+            match result {
+                Ok(result) => send(&Json::object([
+                    ("jsonrpc", "2.0".into()),
+                    ("id", id),
+                    ("result", result),
+                ])),
+                Err(msg) => send(&error(id, INVALID_PARAMS, &msg)),
+            }
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: &str) {
+        // Named by its path, so that its imports can be found.
+        let name = uri.strip_prefix("file://").map_or(uri.to_owned(), decode);
+        let src = Rc::new(SourceFile::new(name, text));
+        let diagnostics = match check(src.clone()) {
+            Ok(_) => vec![],
+            Err(d) => vec![diagnostic(&src, uri, &d)],
+        };
+        self.docs.insert(uri.to_owned(), src);
+        publish(uri, diagnostics);
+    }
+
+    /// Runs a request that takes a `textDocument` and a `position`.
+    fn at_position(
+        &self,
+        params: &Json,
+        f: impl FnOnce(&str, &Rc<SourceFile>, usize) -> Json,
+    ) -> Result<Json, String> {
+        let uri = uri(params).ok_or("Missing textDocument")?;
+        let src = self.docs.get(uri).ok_or("Unknown document")?;
+        let pos = params.get("position").ok_or("Missing position")?;
+        let line = pos
+            .get("line")
+            .and_then(Json::as_u64)
+            .ok_or("Missing line")?;
+        let col = pos.get("character").and_then(Json::as_u64);
+        let col = col.ok_or("Missing character")?;
+        Ok(f(uri, src, src.offset(line as usize, col as usize)))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                // Full document sync
+                ("textDocumentSync", 1usize.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("documentFormattingProvider", true.into()),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", "arith-lsp".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+/// Parses and type checks a document and the files it imports. A document
+/// without a term at its end is a module, and fine.
+fn check(src: Rc<SourceFile>) -> err::Result<()> {
+    let mut tyctx = TyContext::new();
+    let program = module::load(src, &mut tyctx, true)?;
+    let ctx = program.check_defs(&mut tyctx)?;
+    if let Some(body) = &program.body {
+        program.eval().type_of(body, &ctx, &mut tyctx)?;
+    }
+    Ok(())
+}
+
+fn diagnostic(src: &SourceFile, uri: &str, d: &Diagnostic) -> Json {
+    let (primary, secondary) = d.labels().split_first().unwrap();
+    let mut message = d.message().to_owned();
+    // An error in an imported file is shown at the top of the document.
+    let (span, secondary) = if d.file().id == src.id {
+        (primary.span, secondary)
+    } else {
+        message = format!("In `{}`: {}", d.file().name, message);
+        (Span::dummy(), &[][..])
+    };
+    if !primary.msg.is_empty() {
+        message = format!("{}: {}", message, primary.msg);
+    }
+    for note in d.notes() {
+        message = format!("{}\n{}", message, note);
+    }
+    let related = secondary
+        .iter()
+        .map(|label| {
+            Json::object([
+                ("location", location(src, uri, label.span)),
+                ("message", label.msg.clone().into()),
+            ])
+        })
+        .collect();
+    let severity = match d.severity() {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    Json::object([
+        ("range", range(src, span)),
+        ("severity", Json::Number(severity as f64)),
+        ("code", d.code().map_or(Json::Null, Json::from)),
+        ("source", "arith".into()),
+        ("message", message.into()),
+        ("relatedInformation", Json::Array(related)),
+    ])
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) {
+    send(&Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([
+                ("uri", uri.into()),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ]));
+}
+
+/// The type of the innermost term around `offset`, in a code block, then
+/// the doc comments of the definition the name there refers to.
+fn hover(_: &str, src: &Rc<SourceFile>, offset: usize) -> Json {
+    let mut tyctx = TyContext::new();
+    let program = match module::load(src.clone(), &mut tyctx, true) {
+        Ok(program) => program,
+        Err(_) => return Json::Null,
+    };
+    // Terms checked before a type error still have their types.
+    let mut types = TypeMap::for_file(src.id);
+    let _ = program.type_map(&mut tyctx, &mut types);
+    let (span, ty) = match types.type_at(offset) {
+        Some(found) => found,
+        None => return Json::Null,
+    };
+    let ty = tyctx.display(types.context_at(offset).unwrap(), ty);
+    let mut value = format!("```arith\n{}\n```", ty);
+    let docs = docs_at(src, offset);
+    if !docs.is_empty() {
+        value = format!("{}\n\n{}", value, docs.join("\n"));
+    }
+    Json::object([
+        (
+            "contents",
+            Json::object([("kind", "markdown".into()), ("value", value.into())]),
+        ),
+        ("range", range(src, span)),
+    ])
+}
+
+/// The doc comments of the definition the name at `offset` refers to.
+fn docs_at(src: &Rc<SourceFile>, offset: usize) -> Vec<&str> {
+    let root = match cst::parse(src.clone()) {
+        Ok(root) => root,
+        Err(_) => return vec![],
+    };
+    let binder = match binder_of(&root, src, offset, &mut vec![]) {
+        Some(binder) => binder,
+        None => return vec![],
+    };
+    let def = root.children.iter().find_map(|child| match child {
+        Element::Node(node) if node.kind == NodeKind::Def => match &node.children[1] {
+            Element::Token(name) if name.span.lo == binder.lo => Some(node),
+            _ => None,
+        },
+        _ => None,
+    });
+    def.map_or(vec![], |def| def.docs(src))
+}
+
+/// The binder of the variable at `offset`.
+fn definition(uri: &str, src: &Rc<SourceFile>, offset: usize) -> Json {
+    let root = match cst::parse(src.clone()) {
+        Ok(root) => root,
+        Err(_) => return Json::Null,
+    };
+    match binder_of(&root, src, offset, &mut vec![]) {
+        Some(span) => location(src, uri, span),
+        None => Json::Null,
+    }
+}
+
+/// Looks for the name at `offset`, with the lambda parameters and
+/// definitions in scope pushed onto `scope`, innermost last.
+fn binder_of<'a>(
+    node: &Node,
+    src: &'a SourceFile,
+    offset: usize,
+    scope: &mut Vec<(&'a str, Span)>,
+) -> Option<Span> {
+    let text = |span: Span| &src.text[span.lo..span.hi];
+    match node.kind {
+        NodeKind::Root => {
+            for child in &node.children {
+                let Element::Node(child) = child else {
+                    continue;
+                };
+                if let Some(found) = binder_of(child, src, offset, scope) {
+                    return Some(found);
+                }
+                // A definition is in scope after it.
+                if let (NodeKind::Def, Element::Token(name)) = (child.kind, &child.children[1]) {
+                    scope.push((text(name.span), name.span));
+                }
+            }
+            return None;
+        }
+        NodeKind::Name => {
+            let token = node.first_token()?;
+            if offset < token.span.lo || offset > token.span.hi {
+                return None;
+            }
+            let name = text(token.span);
+            let binder = scope.iter().rev().find(|&&(n, _)| n == name);
+            return binder.map(|&(_, span)| span);
+        }
+        NodeKind::Lambda => {
+            let param = match &node.children[1] {
+                Element::Token(token) => token.span,
+                Element::Node(_) => unreachable!(),
+            };
+            let (body, _) = node.children.split_last().unwrap();
+            let Element::Node(body) = body else {
+                unreachable!()
+            };
+            scope.push((text(param), param));
+            let found = binder_of(body, src, offset, scope);
+            scope.pop();
+            return found;
+        }
+        _ => {}
+    }
+    node.children.iter().find_map(|child| match child {
+        Element::Node(child) => binder_of(child, src, offset, scope),
+        Element::Token(_) => None,
+    })
+}
+
+/// Replaces the whole document with its formatted text.
+fn formatting(src: &Rc<SourceFile>) -> Json {
+    match format::format(src.clone(), pretty::DEFAULT_WIDTH) {
+        Ok(text) if text != src.text => Json::Array(vec![Json::object([
+            (
+                "range",
+                range(
+                    src,
+                    Span {
+                        lo: 0,
+                        hi: src.text.len(),
+                        file: src.id,
+                    },
+                ),
+            ),
+            ("newText", text.into()),
+        ])]),
+        _ => Json::Array(vec![]),
+    }
+}
+
+/// Decodes the `%XX` escapes of a URI path.
+fn decode(path: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(decoded) if b == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn uri(params: &Json) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+fn location(src: &SourceFile, uri: &str, span: Span) -> Json {
+    Json::object([("uri", uri.into()), ("range", range(src, span))])
+}
+
+fn range(src: &SourceFile, span: Span) -> Json {
+    Json::object([
+        ("start", position(src, span.lo)),
+        ("end", position(src, span.hi)),
+    ])
+}
+
+fn position(src: &SourceFile, offset: usize) -> Json {
+    let loc = src.loc(offset);
+    Json::object([
+        ("line", loc.line.into()),
+        ("character", loc.utf16_col.into()),
+    ])
+}
+
+fn error(id: Json, code: i32, msg: &str) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([("code", Json::Number(code as f64)), ("message", msg.into())]),
+        ),
+    ])
+}
+
+/// Reads one message framed by a `Content-Length` header. Returns `None` at
+/// the end of the input.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Json>, String> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let len = len.ok_or("Missing Content-Length header")?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body).map_err(|e| e.to_string())?;
+    let body = String::from_utf8(body).map_err(|e| e.to_string())?;
+    Json::parse(&body).map(Some)
+}
+
+fn send(msg: &Json) {
+    let body = msg.to_string();
+    let mut out = io::stdout().lock();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    out.flush().unwrap();
+}
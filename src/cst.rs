@@ -0,0 +1,625 @@
+//! A lossless concrete syntax tree, used by the formatter. Unlike `Parser`,
+//! it keeps every token along with the whitespace and comments before it,
+//! and leaves names and types unresolved.
+
+use crate::{
+    err,
+    lexer::{Lexer, Token, TokenKind, TokenKind::*},
+    source::SourceFile,
+    span::Span,
+};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    /// The items of a file, then the term at its end if there is one, then
+    /// the end of the input.
+    Root,
+    /// `import "path";`
+    Import,
+    /// `use module::name;`
+    Use,
+    /// `def name = t;` or `def name: T = t;`
+    Def,
+    /// `type Name = T;` or `type Name;`. Inside a term it is followed by the
+    /// term in its scope, at the top of a file it is not.
+    TypeDecl,
+
+    // Terms
+    /// `succ t`, `pred t` or `iszero t`.
+    Prefix,
+    If,
+    Lambda,
+    TyLambda,
+    Binary,
+    Call,
+    /// A type application `t [T]`.
+    Inst,
+    Proj,
+    Paren,
+    Record,
+    Field,
+    Literal,
+    Name,
+
+    // Types
+    TyArrow,
+    TyRec,
+    TyAll,
+    TyAbs,
+    TyApp,
+    TyRecord,
+    TyField,
+    TyParen,
+    TyName,
+    /// The `::K <: T` after a type variable.
+    TyParam,
+    Kind,
+}
+
+#[derive(Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+#[derive(Debug)]
+pub enum Element {
+    Node(Node),
+    Token(SyntaxToken),
+}
+
+#[derive(Debug)]
+pub struct SyntaxToken {
+    pub kind: TokenKind,
+    pub span: Span,
+    /// The whitespace and comments before the token, from the start of its
+    /// line or the end of the previous token's trailing trivia.
+    pub trivia: Vec<Token>,
+    /// The whitespace and comments after the token on the same line.
+    pub trailing: Vec<Token>,
+}
+
+impl Node {
+    /// The text of the `///` comments right before the node, one line each.
+    pub fn docs<'a>(&self, src: &'a SourceFile) -> Vec<&'a str> {
+        let Some(token) = self.first_token() else {
+            return vec![];
+        };
+        let mut docs = vec![];
+        for t in &token.trivia {
+            match t.kind {
+                TokenKind::DocComment => {
+                    let text = &src.text[t.span.lo + 3..t.span.hi];
+                    docs.push(text.strip_prefix(' ').unwrap_or(text));
+                }
+                // A blank line or another comment detaches the docs above it.
+                TokenKind::Whitespace
+                    if src.text[t.span.lo..t.span.hi].matches('\n').count() < 2 => {}
+                _ => docs.clear(),
+            }
+        }
+        docs
+    }
+
+    pub fn first_token(&self) -> Option<&SyntaxToken> {
+        self.children.iter().find_map(|child| match child {
+            Element::Node(node) => node.first_token(),
+            Element::Token(token) => Some(token),
+        })
+    }
+
+    /// Writes the source text the node was parsed from.
+    pub fn write_text(&self, src: &SourceFile, buf: &mut String) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.write_text(src, buf),
+                Element::Token(token) => {
+                    for t in &token.trivia {
+                        buf.push_str(&src.text[t.span.lo..t.span.hi]);
+                    }
+                    buf.push_str(&src.text[token.span.lo..token.span.hi]);
+                    for t in &token.trailing {
+                        buf.push_str(&src.text[t.span.lo..t.span.hi]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parses a whole source file. The grammar is the one `Parser` accepts
+/// for a module: imports, definitions and an optional term.
+pub fn parse(src: Rc<SourceFile>) -> err::Result<Node> {
+    let mut p = CstParser::new(src)?;
+    let mut children = vec![];
+    while let Some(item) = p.item(children.iter().any(is_def_or_type))? {
+        children.push(Element::Node(item));
+    }
+    if p.peek() != Eof {
+        children.push(Element::Node(p.expr()?));
+    }
+    p.expect(Eof, "Expected end of input", &mut children)?;
+    Ok(Node {
+        kind: NodeKind::Root,
+        children,
+    })
+}
+
+fn is_def_or_type(element: &Element) -> bool {
+    matches!(element, Element::Node(node) if matches!(node.kind, NodeKind::Def | NodeKind::TypeDecl))
+}
+
+struct CstParser {
+    /// The remaining tokens, in reverse order. The last one is `Eof`.
+    tokens: Vec<SyntaxToken>,
+    src: Rc<SourceFile>,
+}
+
+impl CstParser {
+    fn new(src: Rc<SourceFile>) -> err::Result<Self> {
+        let mut lexer = Lexer::new(src.clone()).with_trivia();
+        let mut tokens = vec![];
+        let mut trivia = vec![];
+        let mut same_line = false;
+        loop {
+            let token = lexer.next_token()?;
+            if token.kind.is_trivia() {
+                let text = &src.text[token.span.lo..token.span.hi];
+                if token.kind == Whitespace && text.contains('\n') {
+                    same_line = false;
+                }
+                match tokens.last_mut() {
+                    Some(SyntaxToken { trailing, .. }) if same_line => trailing.push(token),
+                    _ => trivia.push(token),
+                }
+                continue;
+            }
+            tokens.push(SyntaxToken {
+                kind: token.kind,
+                span: token.span,
+                trivia: std::mem::take(&mut trivia),
+                trailing: vec![],
+            });
+            same_line = true;
+            if token.kind == Eof {
+                break;
+            }
+        }
+        tokens.reverse();
+        Ok(Self { tokens, src })
+    }
+
+    /// Parses an import, a definition or a type declaration at the top of
+    /// a file, if there is one. Imports must come first.
+    fn item(&mut self, after_defs: bool) -> err::Result<Option<Node>> {
+        let mut children = vec![];
+        let kind = match self.peek() {
+            Import | Use if after_defs => bail!(
+                &self.src,
+                self.span(),
+                A0005,
+                "Imports must come before definitions"
+            ),
+            Import => {
+                self.bump(&mut children);
+                self.expect(Str, "Expected a path after 'import'", &mut children)?;
+                self.expect(Semi, "Expected ';' after import", &mut children)?;
+                NodeKind::Import
+            }
+            Use => {
+                self.bump(&mut children);
+                let msg = "Expected a module name after 'use'";
+                self.expect(Ident, msg, &mut children)?;
+                let msg = "Expected '::' after the module name";
+                self.expect(ColonColon, msg, &mut children)?;
+                self.expect(Ident, "Expected a name after '::'", &mut children)?;
+                self.expect(Semi, "Expected ';' after use", &mut children)?;
+                NodeKind::Use
+            }
+            Def => {
+                self.bump(&mut children);
+                self.expect(Ident, "Expected a name after 'def'", &mut children)?;
+                if self.eat(Colon, &mut children) {
+                    children.push(Element::Node(self.ty()?));
+                }
+                self.expect(Eq, "Expected ':' or '=' after the name", &mut children)?;
+                children.push(Element::Node(self.expr()?));
+                self.expect(Semi, "Expected ';' after definition", &mut children)?;
+                NodeKind::Def
+            }
+            Type => {
+                self.type_decl_into(&mut children)?;
+                NodeKind::TypeDecl
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(Node { kind, children }))
+    }
+
+    /// `type Name = T;` or `type Name;`.
+    fn type_decl_into(&mut self, children: &mut Vec<Element>) -> err::Result<()> {
+        self.bump(children);
+        self.expect(Ident, "Expected a name after 'type'", children)?;
+        if !self.eat(Semi, children) {
+            self.expect(Eq, "Expected '=' or ';' after type name", children)?;
+            children.push(Element::Node(self.ty()?));
+            self.expect(Semi, "Expected ';' after type alias", children)?;
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self) -> err::Result<Node> {
+        let mut children = vec![];
+        let kind = match self.peek() {
+            Type => {
+                self.type_decl_into(&mut children)?;
+                children.push(Element::Node(self.expr()?));
+                NodeKind::TypeDecl
+            }
+            Succ | Pred | IsZero => {
+                self.bump(&mut children);
+                children.push(Element::Node(self.expr()?));
+                NodeKind::Prefix
+            }
+            If => {
+                self.bump(&mut children);
+                children.push(Element::Node(self.expr()?));
+                self.expect(OpenBrace, "Expected '{' after If condition", &mut children)?;
+                children.push(Element::Node(self.expr()?));
+                self.expect(CloseBrace, "Expected '}'", &mut children)?;
+                self.expect(Else, "Expected 'else'", &mut children)?;
+                self.expect(OpenBrace, "Expected '{' after else", &mut children)?;
+                children.push(Element::Node(self.expr()?));
+                self.expect(CloseBrace, "Expected '}'", &mut children)?;
+                NodeKind::If
+            }
+            Pipe => {
+                self.bump(&mut children);
+                let msg = "Expected an indentifier for Lambda parameter";
+                self.expect(Ident, msg, &mut children)?;
+                let kind = if self.eat(Colon, &mut children) {
+                    children.push(Element::Node(self.ty()?));
+                    self.expect(Pipe, "Expected '|' after Lambda parameter", &mut children)?;
+                    NodeKind::Lambda
+                } else {
+                    if let Some(param) = self.ty_param()? {
+                        children.push(Element::Node(param));
+                    }
+                    self.expect(Pipe, "Expected '|' after type parameter", &mut children)?;
+                    NodeKind::TyLambda
+                };
+                children.push(Element::Node(self.expr()?));
+                kind
+            }
+            _ => return self.sum(),
+        };
+        Ok(Node { kind, children })
+    }
+
+    fn sum(&mut self) -> err::Result<Node> {
+        let mut lhs = self.product()?;
+        while matches!(self.peek(), Plus | Minus) {
+            let mut children = vec![Element::Node(lhs)];
+            self.bump(&mut children);
+            children.push(Element::Node(self.product()?));
+            lhs = Node {
+                kind: NodeKind::Binary,
+                children,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> err::Result<Node> {
+        let mut lhs = self.call()?;
+        while matches!(self.peek(), Star | Slash) {
+            let mut children = vec![Element::Node(lhs)];
+            self.bump(&mut children);
+            children.push(Element::Node(self.call()?));
+            lhs = Node {
+                kind: NodeKind::Binary,
+                children,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn call(&mut self) -> err::Result<Node> {
+        let mut term = self.proj()?;
+        loop {
+            let kind = if self.peek() == OpenBracket {
+                NodeKind::Inst
+            } else if self.at_atom() {
+                NodeKind::Call
+            } else {
+                return Ok(term);
+            };
+            let mut children = vec![Element::Node(term)];
+            if kind == NodeKind::Inst {
+                self.bump(&mut children);
+                children.push(Element::Node(self.ty()?));
+                let msg = "Expected ']' after type argument";
+                self.expect(CloseBracket, msg, &mut children)?;
+            } else {
+                children.push(Element::Node(self.proj()?));
+            }
+            term = Node { kind, children };
+        }
+    }
+
+    fn proj(&mut self) -> err::Result<Node> {
+        let mut term = self.atom()?;
+        while self.peek() == Dot {
+            let mut children = vec![Element::Node(term)];
+            self.bump(&mut children);
+            self.expect(Ident, "Expected a field name after '.'", &mut children)?;
+            term = Node {
+                kind: NodeKind::Proj,
+                children,
+            };
+        }
+        Ok(term)
+    }
+
+    fn at_atom(&self) -> bool {
+        match self.peek() {
+            True | False | Zero | Number | Float | Str | Ident | OpenParen => true,
+            OpenBrace => self.at_record(),
+            _ => false,
+        }
+    }
+
+    /// See `Parser::at_record`.
+    fn at_record(&self) -> bool {
+        match self.nth(1) {
+            CloseBrace => true,
+            Ident => self.nth(2) == Eq,
+            _ => false,
+        }
+    }
+
+    fn atom(&mut self) -> err::Result<Node> {
+        let mut children = vec![];
+        let kind = match self.peek() {
+            True | False | Zero | Number | Float | Str => {
+                self.bump(&mut children);
+                NodeKind::Literal
+            }
+            Ident => {
+                self.bump(&mut children);
+                NodeKind::Name
+            }
+            OpenParen => {
+                self.bump(&mut children);
+                children.push(Element::Node(self.expr()?));
+                self.expect(CloseParen, "Expected ')'", &mut children)?;
+                NodeKind::Paren
+            }
+            OpenBrace if self.at_record() => {
+                self.bump(&mut children);
+                let mut first = true;
+                while !self.eat(CloseBrace, &mut children) {
+                    if !first {
+                        let msg = "Expected ',' between record fields";
+                        self.expect(Comma, msg, &mut children)?;
+                    }
+                    first = false;
+                    let mut field = vec![];
+                    self.expect(Ident, "Expected a field name", &mut field)?;
+                    self.expect(Eq, "Expected '=' after field name", &mut field)?;
+                    field.push(Element::Node(self.expr()?));
+                    children.push(Element::Node(Node {
+                        kind: NodeKind::Field,
+                        children: field,
+                    }));
+                }
+                NodeKind::Record
+            }
+            kind => bail!(
+                &self.src,
+                self.span(),
+                A0005,
+                "Unexpected token: {:?}",
+                kind
+            ),
+        };
+        Ok(Node { kind, children })
+    }
+
+    fn ty(&mut self) -> err::Result<Node> {
+        let mut children = vec![];
+        let kind = match self.peek() {
+            Pipe => {
+                self.bump(&mut children);
+                children.push(Element::Node(self.ty()?));
+                self.expect(Pipe, "Expected '|' after parameter type", &mut children)?;
+                children.push(Element::Node(self.ty()?));
+                NodeKind::TyArrow
+            }
+            Mu => {
+                self.bump(&mut children);
+                let msg = "Expected a type variable after 'mu'";
+                self.expect(Ident, msg, &mut children)?;
+                let msg = "Expected '.' after recursive type variable";
+                self.expect(Dot, msg, &mut children)?;
+                children.push(Element::Node(self.ty()?));
+                NodeKind::TyRec
+            }
+            Forall | Backslash => {
+                let (kind, name_msg, dot_msg) = if self.peek() == Forall {
+                    let dot_msg = "Expected '.' after universal type variable";
+                    (
+                        NodeKind::TyAll,
+                        "Expected a type variable after 'forall'",
+                        dot_msg,
+                    )
+                } else {
+                    let dot_msg = "Expected '.' after type operator parameter";
+                    (
+                        NodeKind::TyAbs,
+                        "Expected a type variable after '\\'",
+                        dot_msg,
+                    )
+                };
+                self.bump(&mut children);
+                self.expect(Ident, name_msg, &mut children)?;
+                let param = match kind {
+                    NodeKind::TyAll => self.ty_param()?,
+                    _ => self.kind_param()?,
+                };
+                if let Some(param) = param {
+                    children.push(Element::Node(param));
+                }
+                self.expect(Dot, dot_msg, &mut children)?;
+                children.push(Element::Node(self.ty()?));
+                kind
+            }
+            _ => {
+                let mut ty = self.ty_atom()?;
+                while matches!(self.peek(), Ident | OpenParen | OpenBrace) {
+                    let arg = self.ty_atom()?;
+                    ty = Node {
+                        kind: NodeKind::TyApp,
+                        children: vec![Element::Node(ty), Element::Node(arg)],
+                    };
+                }
+                return Ok(ty);
+            }
+        };
+        Ok(Node { kind, children })
+    }
+
+    fn ty_atom(&mut self) -> err::Result<Node> {
+        let mut children = vec![];
+        let kind = match self.peek() {
+            OpenBrace => {
+                self.bump(&mut children);
+                let mut first = true;
+                while !self.eat(CloseBrace, &mut children) {
+                    if !first {
+                        let msg = "Expected ',' between record fields";
+                        self.expect(Comma, msg, &mut children)?;
+                    }
+                    first = false;
+                    let mut field = vec![];
+                    self.expect(Ident, "Expected a field name", &mut field)?;
+                    self.expect(Colon, "Expected ':' after field name", &mut field)?;
+                    field.push(Element::Node(self.ty()?));
+                    children.push(Element::Node(Node {
+                        kind: NodeKind::TyField,
+                        children: field,
+                    }));
+                }
+                NodeKind::TyRecord
+            }
+            OpenParen => {
+                self.bump(&mut children);
+                children.push(Element::Node(self.ty()?));
+                self.expect(CloseParen, "Expected ')'", &mut children)?;
+                NodeKind::TyParen
+            }
+            Ident => {
+                self.bump(&mut children);
+                NodeKind::TyName
+            }
+            _ => bail!(&self.src, self.span(), A0005, "Expected a type"),
+        };
+        Ok(Node { kind, children })
+    }
+
+    /// Parses `::K <: T`, where both parts are optional.
+    fn ty_param(&mut self) -> err::Result<Option<Node>> {
+        let mut children = vec![];
+        if self.eat(ColonColon, &mut children) {
+            children.push(Element::Node(self.kind()?));
+        }
+        if self.eat(Lt, &mut children) {
+            let msg = "Expected '<:' before the bound of type variable";
+            self.expect(Colon, msg, &mut children)?;
+            children.push(Element::Node(self.ty()?));
+        }
+        Ok(Self::param(children))
+    }
+
+    /// Parses an optional `::K`.
+    fn kind_param(&mut self) -> err::Result<Option<Node>> {
+        let mut children = vec![];
+        if self.eat(ColonColon, &mut children) {
+            children.push(Element::Node(self.kind()?));
+        }
+        Ok(Self::param(children))
+    }
+
+    fn param(children: Vec<Element>) -> Option<Node> {
+        if children.is_empty() {
+            None
+        } else {
+            Some(Node {
+                kind: NodeKind::TyParam,
+                children,
+            })
+        }
+    }
+
+    fn kind(&mut self) -> err::Result<Node> {
+        let mut children = vec![];
+        self.kind_into(&mut children)?;
+        Ok(Node {
+            kind: NodeKind::Kind,
+            children,
+        })
+    }
+
+    /// Kinds are kept as a flat list of tokens.
+    fn kind_into(&mut self, children: &mut Vec<Element>) -> err::Result<()> {
+        if self.eat(OpenParen, children) {
+            self.kind_into(children)?;
+            self.expect(CloseParen, "Expected ')'", children)?;
+        } else if !self.eat(Star, children) {
+            bail!(&self.src, self.span(), A0005, "Expected a kind");
+        }
+        if self.eat(FatArrow, children) {
+            self.kind_into(children)?;
+        }
+        Ok(())
+    }
+
+    fn expect(
+        &mut self,
+        kind: TokenKind,
+        msg: &str,
+        children: &mut Vec<Element>,
+    ) -> err::Result<()> {
+        if self.eat(kind, children) {
+            return Ok(());
+        }
+
+        bail!(&self.src, self.span(), A0005, msg);
+    }
+
+    fn eat(&mut self, kind: TokenKind, children: &mut Vec<Element>) -> bool {
+        if self.peek() == kind {
+            self.bump(children);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bump(&mut self, children: &mut Vec<Element>) {
+        children.push(Element::Token(self.tokens.pop().unwrap()));
+    }
+
+    fn peek(&self) -> TokenKind {
+        self.nth(0)
+    }
+
+    fn nth(&self, n: usize) -> TokenKind {
+        self.tokens.iter().rev().nth(n).map_or(Eof, |t| t.kind)
+    }
+
+    fn span(&self) -> Span {
+        self.tokens.last().map_or(Span::dummy(), |t| t.span)
+    }
+}
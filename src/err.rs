@@ -1,100 +1,530 @@
-use crate::span::Span;
+pub mod codes;
+
+use crate::{
+    json,
+    source::{self, SourceFile},
+    span::Span,
+};
+use std::cell::Cell;
+use std::fmt::{self, Write};
 use std::rc::Rc;
 
+/// Returns an error with the given code and message from the enclosing
+/// function.
 #[macro_export]
-macro_rules! quit {
-    () => {
-        std::process::exit(1);
-    };
-    ($src:expr, $span:expr, $msg:expr) => {{
+macro_rules! bail {
+    ($src:expr, $span:expr, $code:ident, $msg:expr) => {{
         let msg = $msg.to_owned();
-        let d = $crate::err::Diagnostic::new($src, msg, $span);
-        d.report();
-        quit!();
+        return Err($crate::err::Diagnostic::new($src, msg, $span)
+            .with_code($crate::err::codes::$code))
     }};
-    ($src:expr, $span:expr, $msg:expr,) => {
-        quit!($src, $span, $msg);
+    ($src:expr, $span:expr, $code:ident, $msg:expr,) => {
+        bail!($src, $span, $code, $msg)
     };
-    ($src:expr, $span:expr, $fmt:expr, $($arg:tt)+) => {{
-        quit!($src, $span, format!($fmt, $($arg)+))
+    ($src:expr, $span:expr, $code:ident, $fmt:expr, $($arg:tt)+) => {{
+        bail!($src, $span, $code, format!($fmt, $($arg)+))
     }};
 }
 
+pub type Result<T> = std::result::Result<T, Diagnostic>;
+
+/// How `Diagnostic::report` prints diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    /// The caret rendering of `Diagnostic::render`.
+    Human,
+    /// One JSON object per line, see `Diagnostic::to_json`.
+    Json,
+}
+
+impl std::str::FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("Unknown error format: {}", s)),
+        }
+    }
+}
+
+thread_local! {
+    static ERROR_FORMAT: Cell<ErrorFormat> = const { Cell::new(ErrorFormat::Human) };
+}
+
+pub fn set_error_format(format: ErrorFormat) {
+    ERROR_FORMAT.with(|f| f.set(format));
+}
+
+pub fn error_format() -> ErrorFormat {
+    ERROR_FORMAT.with(|f| f.get())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Note {
+    Note,
+    Help,
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Note::Note => f.write_str("note"),
+            Note::Help => f.write_str("help"),
+        }
+    }
+}
+
+pub struct Label {
+    pub span: Span,
+    pub msg: String,
+    pub primary: bool,
+}
+
+/// An error or warning about a piece of source, rendered in the style of
+/// rustc:
+///
+/// ```text
+/// error[A0017]: Parameter type mismatch
+///  --> main.arith:1:16
+///   |
+/// 1 | (|x: Nat| x) true
+///   | ------------ ^^^^ expected Nat
+///   | |
+///   | this function
+///   |
+///   = note: ...
+/// ```
 pub struct Diagnostic {
-    src: Rc<String>,
+    src: Rc<SourceFile>,
+    severity: Severity,
+    code: Option<&'static str>,
     msg: String,
-    span: Span,
+    labels: Vec<Label>,
+    /// Notes and helps, in the order they are shown.
+    notes: Vec<(Note, String)>,
 }
 
 impl Diagnostic {
-    pub fn new(src: &Rc<String>, msg: String, span: Span) -> Self {
+    pub fn new(src: &Rc<SourceFile>, msg: String, span: Span) -> Self {
         Self {
             src: src.clone(),
+            severity: Severity::Error,
+            code: None,
             msg,
-            span,
+            labels: vec![Label {
+                span,
+                msg: String::new(),
+                primary: true,
+            }],
+            notes: vec![],
         }
     }
 
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Sets the error code, see `codes`.
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Sets the text shown next to the primary span.
+    pub fn with_primary_label(mut self, msg: impl Into<String>) -> Self {
+        self.labels[0].msg = msg.into();
+        self
+    }
+
+    /// Adds a secondary span, underlined with `-`.
+    pub fn with_label(mut self, span: Span, msg: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            msg: msg.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push((Note::Note, note.into()));
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.notes.push((Note::Help, help.into()));
+        self
+    }
+
+    pub fn code(&self) -> Option<&'static str> {
+        self.code
+    }
+
+    /// The file the error is in.
+    pub fn file(&self) -> &Rc<SourceFile> {
+        &self.src
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    /// The labelled spans, the primary one first.
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    /// The notes and helps, each prefixed with `note: ` or `help: `.
+    pub fn notes(&self) -> impl Iterator<Item = String> + '_ {
+        self.notes
+            .iter()
+            .map(|(kind, note)| format!("{}: {}", kind, note))
+    }
+
     pub fn report(&self) {
-        if self.span.hi > self.src.len() {
-            self.report_out_of_bounds()
-        } else {
-            self.report_in_bounds()
+        match error_format() {
+            ErrorFormat::Human => eprint!("{}", self.render()),
+            ErrorFormat::Json => eprintln!("{}", self.to_json()),
+        }
+    }
+
+    /// Reports the diagnostic and exits with the status for the phase of
+    /// its error code.
+    pub fn abort(&self) -> ! {
+        self.report();
+        let phase = self.code.and_then(codes::phase);
+        std::process::exit(phase.map_or(1, codes::Phase::exit_code));
+    }
+
+    /// Serializes the diagnostic as a single line of JSON:
+    ///
+    /// ```text
+    /// {"message": "...", "severity": "error", "code": "A0017",
+    ///  "spans": [{"file_name": "main.arith", "lo": 13, "hi": 17,
+    ///             "line_start": 1, "column_start": 14,
+    ///             "line_end": 1, "column_end": 18, "primary": true,
+    ///             "label": "..."}],
+    ///  "notes": [], "helps": [], "rendered": "..."}
+    /// ```
+    ///
+    /// Offsets are in bytes, lines and columns are one-based and columns
+    /// count characters.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write!(
+            out,
+            "{{\"message\":{},\"severity\":{},\"code\":{},\"spans\":[",
+            json::quote(&self.msg),
+            json::quote(&self.severity.to_string()),
+            self.code.map_or("null".to_owned(), json::quote),
+        )
+        .unwrap();
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let start = label.span.start(&self.src);
+            let end = label.span.end(&self.src);
+            write!(
+                out,
+                "{{\"file_name\":{},\"lo\":{},\"hi\":{},\"line_start\":{},\
+                 \"column_start\":{},\"line_end\":{},\"column_end\":{},\"primary\":{},\
+                 \"label\":{}}}",
+                json::quote(&self.src.name),
+                label.span.lo,
+                label.span.hi.max(label.span.lo),
+                start.line + 1,
+                start.col + 1,
+                end.line + 1,
+                end.col + 1,
+                label.primary,
+                json::quote(&label.msg),
+            )
+            .unwrap();
+        }
+        out.push_str("],\"notes\":");
+        self.json_notes(&mut out, Note::Note);
+        out.push_str(",\"helps\":");
+        self.json_notes(&mut out, Note::Help);
+        write!(out, ",\"rendered\":{}}}", json::quote(&self.render())).unwrap();
+        out
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        match self.code {
+            Some(code) => writeln!(out, "{}[{}]: {}", self.severity, code, self.msg),
+            None => writeln!(out, "{}: {}", self.severity, self.msg),
+        }
+        .unwrap();
+
+        let labels: Vec<_> = self.labels.iter().map(|l| self.locate(l)).collect();
+        let line = labels[0].start.0;
+        let col = self.labels[0].span.start(&self.src).col;
+        let last_line = labels.iter().map(|l| l.end.0).max().unwrap_or(line);
+        let width = (last_line + 1).to_string().len();
+        let gutter = " ".repeat(width);
+
+        writeln!(
+            out,
+            "{}--> {}:{}:{}",
+            gutter,
+            self.src.name,
+            line + 1,
+            col + 1
+        )
+        .unwrap();
+        writeln!(out, "{} |", gutter).unwrap();
+        self.render_snippet(&mut out, &labels, &gutter);
+
+        if !self.notes.is_empty() {
+            writeln!(out, "{} |", gutter).unwrap();
         }
+        for (kind, note) in &self.notes {
+            writeln!(out, "{} = {}: {}", gutter, kind, note).unwrap();
+        }
+        if let Some(code) = self.code {
+            writeln!(
+                out,
+                "For more information about this error, try `arith --explain {}`.",
+                code
+            )
+            .unwrap();
+        }
+        out
     }
 
-    fn report_in_bounds(&self) {
-        let start = self.line_start(self.span.lo);
-        let end = self.line_end(self.span.hi);
-        let line_count = self.line_count(start, end);
-        if line_count == 1 {
-            println!("{}", &self.src[start..end]);
-            let mut buf = String::with_capacity(end - start);
-            for _ in start..self.span.lo {
-                buf.push(' ');
+    fn json_notes(&self, out: &mut String, kind: Note) {
+        out.push('[');
+        let notes = self.notes.iter().filter(|(k, _)| *k == kind);
+        for (i, (_, note)) in notes.enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&json::quote(note));
+        }
+        out.push(']');
+    }
+
+    fn render_snippet(&self, out: &mut String, labels: &[Located<'_>], gutter: &str) {
+        let multi: Vec<_> = labels.iter().filter(|l| l.start.0 != l.end.0).collect();
+        let single: Vec<_> = labels.iter().filter(|l| l.start.0 == l.end.0).collect();
+        let lanes = if multi.is_empty() { 0 } else { multi.len() + 1 };
+
+        let mut lines: Vec<usize> = vec![];
+        for label in labels {
+            lines.push(label.start.0);
+            lines.push(label.end.0);
+            if label.end.0 - label.start.0 <= MAX_MULTILINE {
+                lines.extend(label.start.0..label.end.0);
+            } else {
+                lines.push(label.start.0 + 1);
+                lines.push(label.end.0 - 1);
+            }
+        }
+        lines.sort_unstable();
+        lines.dedup();
+
+        let mut prev = None;
+        for &line in &lines {
+            if matches!(prev, Some(prev) if prev + 1 < line) {
+                writeln!(out, "...").unwrap();
+            }
+            prev = Some(line);
+
+            // The source line, with the lanes of multi-line labels that pass
+            // through it.
+            let mut row = Row::new(lanes);
+            for (lane, label) in multi.iter().enumerate() {
+                if label.start.0 < line && line <= label.end.0 {
+                    row.put(lane, '|');
+                }
+            }
+            let text = source::expand_tabs(self.src.line(line));
+            writeln!(
+                out,
+                "{:>w$} | {}{}",
+                line + 1,
+                row.lanes(),
+                text,
+                w = gutter.len()
+            )
+            .unwrap();
+
+            // Lanes continue below the line if the label ends further down.
+            let active = |row: &mut Row| {
+                for (lane, label) in multi.iter().enumerate() {
+                    if label.start.0 <= line && line < label.end.0 {
+                        row.put(lane, '|');
+                    }
+                }
+            };
+
+            let mut on_line: Vec<_> = single.iter().filter(|l| l.start.0 == line).collect();
+            on_line.sort_by_key(|l| l.start.1);
+            if let Some((last, rest)) = on_line.split_last() {
+                let mut row = Row::new(lanes);
+                active(&mut row);
+                for label in &on_line {
+                    let hi = label.end.1.max(label.start.1 + 1);
+                    for col in label.start.1..hi {
+                        row.put_col(col, label.marker());
+                    }
+                }
+                row.push_str(&format!(" {}", last.label.msg));
+                row.write(out, gutter);
+
+                // Labels to the left of the last one hang their messages below
+                // it, connected with `|`.
+                if rest.iter().any(|l| !l.label.msg.is_empty()) {
+                    let mut row = Row::new(lanes);
+                    active(&mut row);
+                    for label in rest {
+                        row.put_col(label.start.1, '|');
+                    }
+                    row.write(out, gutter);
+                    for (i, label) in rest.iter().enumerate().rev() {
+                        let mut row = Row::new(lanes);
+                        active(&mut row);
+                        for label in &rest[..i] {
+                            row.put_col(label.start.1, '|');
+                        }
+                        row.put_str(label.start.1, &label.label.msg);
+                        row.write(out, gutter);
+                    }
+                }
             }
-            for _ in self.span.lo..self.span.hi {
-                buf.push('^');
+
+            for (lane, label) in multi.iter().enumerate() {
+                if label.start.0 == line {
+                    let mut row = Row::new(lanes);
+                    active(&mut row);
+                    row.put(lane, ' ');
+                    for i in lane + 1..lanes + label.start.1 {
+                        row.put(i, '_');
+                    }
+                    row.put_col(label.start.1, label.marker());
+                    row.write(out, gutter);
+                }
+                if label.end.0 == line {
+                    let mut row = Row::new(lanes);
+                    active(&mut row);
+                    row.put(lane, '|');
+                    let end = label.end.1.saturating_sub(1);
+                    for i in lane + 1..lanes + end {
+                        row.put(i, '_');
+                    }
+                    row.put_col(end, label.marker());
+                    row.push_str(&format!(" {}", label.label.msg));
+                    row.write(out, gutter);
+                }
             }
-            buf.push(' ');
-            buf.push_str(&self.msg);
-            println!("{}", buf);
+        }
+    }
+
+    /// Finds the zero-based line and display column of both ends of a label.
+    /// A span past the end points just after the last character.
+    fn locate<'a>(&self, label: &'a Label) -> Located<'a> {
+        let start = label.span.start(&self.src);
+        let end = label.span.end(&self.src);
+        Located {
+            label,
+            start: (start.line, start.display_col),
+            end: (end.line, end.display_col),
+        }
+    }
+}
+
+impl fmt::Debug for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+/// Multi-line labels spanning more lines than this only show their first
+/// and last two lines.
+const MAX_MULTILINE: usize = 4;
+
+struct Located<'a> {
+    label: &'a Label,
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+impl Located<'_> {
+    fn marker(&self) -> char {
+        if self.label.primary {
+            '^'
         } else {
-            println!("{}", &self.src[start..end]);
-            println!(" {}", self.msg);
+            '-'
+        }
+    }
+}
+
+/// One annotation row below a source line. The first `lanes` characters hold
+/// the vertical lines of multi-line labels, source columns follow.
+struct Row {
+    lanes: usize,
+    chars: Vec<char>,
+}
+
+impl Row {
+    fn new(lanes: usize) -> Self {
+        Self {
+            lanes,
+            chars: vec![' '; lanes],
         }
     }
 
-    fn report_out_of_bounds(&self) {
-        let start = self.line_start(self.span.lo);
-        let end = self.line_end(self.span.hi);
-        println!("{}", &self.src[start..end]);
-        let mut buf = String::with_capacity(end - start);
-        for _ in start..self.src.len() {
-            buf.push(' ');
+    fn put(&mut self, i: usize, c: char) {
+        if self.chars.len() <= i {
+            self.chars.resize(i + 1, ' ');
+        }
+        self.chars[i] = c;
+    }
+
+    fn put_col(&mut self, col: usize, c: char) {
+        self.put(self.lanes + col, c);
+    }
+
+    fn put_str(&mut self, col: usize, s: &str) {
+        for (i, c) in s.chars().enumerate() {
+            self.put_col(col + i, c);
         }
-        buf.push_str("^ ");
-        buf.push_str(&self.msg);
-        println!("{}", buf);
     }
 
-    fn line_start(&self, from: usize) -> usize {
-        let from = from.min(self.src.len() - 1);
-        self.src[..from]
-            .rfind('\n')
-            .map(|n| n + 1)
-            .unwrap_or_default()
+    fn push_str(&mut self, s: &str) {
+        self.chars.extend(s.chars());
     }
 
-    fn line_end(&self, from: usize) -> usize {
-        let from = from.min(self.src.len() - 1);
-        self.src[from..]
-            .find('\n')
-            .map(|n| from + n)
-            .unwrap_or(self.src.len())
+    fn lanes(&self) -> String {
+        self.chars[..self.lanes].iter().collect()
     }
 
-    fn line_count(&self, start: usize, end: usize) -> usize {
-        self.src[start..end].bytes().filter(|&n| n == b'\n').count() + 1
+    fn write(&self, out: &mut String, gutter: &str) {
+        let row: String = self.chars.iter().collect();
+        writeln!(out, "{} | {}", gutter, row.trim_end()).unwrap();
     }
 }
@@ -0,0 +1,374 @@
+//! Error codes and their long-form explanations, shown by `arith --explain`.
+//!
+//! Codes are never reused or renumbered.
+
+macro_rules! register_codes {
+    ($($code:ident($phase:ident): $explanation:expr,)*) => {
+        $(pub const $code: &str = stringify!($code);)*
+
+        static CODES: &[(&str, Phase, &str)] =
+            &[$((stringify!($code), Phase::$phase, $explanation),)*];
+    };
+}
+
+/// The stage of the pipeline an error comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Syntax,
+    Type,
+    Runtime,
+}
+
+impl Phase {
+    /// The exit status of the command line driver after an error.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Phase::Syntax => 1,
+            Phase::Type => 2,
+            Phase::Runtime => 3,
+        }
+    }
+}
+
+/// Every error code, in order.
+pub fn all() -> impl Iterator<Item = &'static str> {
+    CODES.iter().map(|&(code, ..)| code)
+}
+
+/// The explanation of `code`, if it is a known error code.
+pub fn explain(code: &str) -> Option<&'static str> {
+    lookup(code).map(|&(_, _, explanation)| explanation)
+}
+
+pub fn phase(code: &str) -> Option<Phase> {
+    lookup(code).map(|&(_, phase, _)| phase)
+}
+
+fn lookup(code: &str) -> Option<&'static (&'static str, Phase, &'static str)> {
+    CODES.iter().find(|(c, ..)| c.eq_ignore_ascii_case(code))
+}
+
+register_codes! {
+    // Lexer
+    A0001(Syntax): r#"A character that cannot start any token was found.
+
+Erroneous example:
+
+    succ 0 @ 0
+
+Only ASCII letters, digits, whitespace and the punctuation of the language
+can appear outside of string literals.
+"#,
+    A0002(Syntax): r#"A string literal is missing its closing quote.
+
+Erroneous example:
+
+    "hello
+
+Add a `"` at the end of the string:
+
+    "hello"
+"#,
+    A0003(Syntax): r#"A string literal contains an unknown escape sequence.
+
+Erroneous example:
+
+    "C:\dir"
+
+The supported escapes are `\n`, `\t`, `\r`, `\0`, `\\`, `\"` and `\u{...}`.
+Escape the backslash to write it literally:
+
+    "C:\\dir"
+"#,
+    A0004(Syntax): r#"A `\u` escape is not of the form `\u{XXXX}` or does not name a
+Unicode scalar value.
+
+Erroneous example:
+
+    "\u0041"
+    "\u{d800}"
+    "\u{0000041}"
+
+Put between one and six hex digits in braces:
+
+    "\u{41}"
+"#,
+    // Parser
+    A0005(Syntax): r#"The parser found a token where it expected something else.
+
+Erroneous example:
+
+    (|x: Nat| x 0
+
+Check the reported position for a missing delimiter or operand:
+
+    (|x: Nat| x) 0
+"#,
+    A0006(Syntax): r#"A float literal could not be parsed.
+
+Float literals are written as `digits.digits`, e.g. `0.5`.
+"#,
+    A0007(Syntax): r#"A Nat literal other than `0` was used.
+
+This error is no longer emitted: Nat literals such as `2` are allowed, and
+only ones too large for a Nat are an error, A0033. Older versions rejected
+them:
+
+    succ 2
+
+and required Nats to be built from `0` and `succ`:
+
+    succ succ succ 0
+"#,
+    A0008(Syntax): r#"A variable was used that is not in scope.
+
+Erroneous example:
+
+    |x: Nat| y
+
+Variables are bound by functions, `|x: T| body`, and are only visible in the
+body:
+
+    |x: Nat| |y: Nat| y
+"#,
+    A0009(Syntax): r#"A type name was used that is not defined.
+
+Erroneous example:
+
+    |x: Int| x
+
+Use one of the builtin types `Bool`, `Nat`, `Top`, `String` and `Float`, a
+bound type variable, or define the name first:
+
+    type Int; |x: Int| x
+    type N = Nat; |x: N| x
+"#,
+    A0010(Syntax): r#"A type name was defined twice.
+
+Erroneous example:
+
+    type N = Nat; type N = Bool; 0
+
+Type names are global, so each one can only be defined once in the whole
+program, even in different files, and the builtin types cannot be
+redefined.
+"#,
+    A0011(Syntax): r#"A type was declared inside a type abstraction.
+
+Erroneous example:
+
+    |X| type Y = X; |y: Y| y
+
+Aliases are global, so they cannot refer to type variables. Declare them
+before the abstraction, or write the type out:
+
+    |X| |y: X| y
+"#,
+    A0012(Syntax): r#"A recursive type is not contractive.
+
+Erroneous example:
+
+    type T = mu X. X;
+
+The body of `mu X. T` must be a type constructor, such as an arrow or a
+record, before `X` is used, so that unfolding it makes progress:
+
+    type NatStream = mu X. {head: Nat, tail: |Top| X};
+"#,
+    // Type checker
+    A0013(Type): r#"The condition of an `if` is not a `Bool`.
+
+Erroneous example:
+
+    if 0 { true } else { false }
+
+Use a boolean condition, e.g. `iszero`:
+
+    if iszero 0 { true } else { false }
+"#,
+    A0014(Type): r#"The arms of an `if` have different types.
+
+Erroneous example:
+
+    if true { 0 } else { false }
+
+Both arms must have the same type, since either may be the result.
+"#,
+    A0015(Type): r#"`succ`, `pred` or `iszero` was applied to something other than a
+`Nat`.
+
+Erroneous example:
+
+    succ true
+"#,
+    A0016(Type): r#"An arithmetic operator was applied to something other than a
+`Float`.
+
+Erroneous example:
+
+    1.5 + 0
+
+`+`, `-`, `*` and `/` are only defined on floats:
+
+    1.5 + 0.0
+"#,
+    A0017(Type): r#"A function was applied to an argument of the wrong type.
+
+Erroneous example:
+
+    (|x: Nat| x) true
+
+The type of the argument must be a subtype of the parameter type.
+"#,
+    A0018(Type): r#"Something that is not a function was applied to an argument.
+
+Erroneous example:
+
+    0 true
+
+Only values of arrow type `|T| U` can be called.
+"#,
+    A0019(Type): r#"A type argument was given to something that is not a type
+abstraction.
+
+Erroneous example:
+
+    (|x: Nat| x) [Nat]
+
+Only values of universal type `forall X. T` take type arguments.
+"#,
+    A0020(Type): r#"A type argument does not satisfy the bound of the type parameter.
+
+Erroneous example:
+
+    (|X <: {a: Nat}| |x: X| x.a) [Nat]
+
+The type argument must be a subtype of the bound:
+
+    (|X <: {a: Nat}| |x: X| x.a) [{a: Nat, b: Bool}]
+"#,
+    A0021(Type): r#"A field was projected out of something that is not a record.
+
+Erroneous example:
+
+    (succ 0).a
+"#,
+    A0022(Type): r#"A record does not have the projected field.
+
+Erroneous example:
+
+    {a = 0}.b
+"#,
+    A0023(Type): r#"A type is ill-kinded.
+
+Erroneous example:
+
+    |x: Nat Bool| x
+
+Type operators must be applied to arguments of the kind they expect, and
+the types of terms must be of kind `*`:
+
+    type Pair = \X. \Y. {fst: X, snd: Y};
+    |x: Pair Nat Bool| x.fst
+"#,
+    // Evaluation
+    A0024(Runtime): r#"Evaluation got stuck on a term that is not a value.
+
+The only way a program that type checks can get stuck is a Nat overflow,
+which is reported as A0031 instead. If a program that type checks causes
+this error, the interpreter has a bug. Please report it along with the
+program that caused it.
+"#,
+    A0025(Syntax): r#"A block comment is missing its closing `*/`.
+
+Erroneous example:
+
+    /* outer /* inner */
+    succ 0
+
+Block comments nest, so each `/*` needs its own `*/`:
+
+    /* outer /* inner */ */
+    succ 0
+"#,
+    // Modules
+    A0026(Syntax): r#"Files import each other in a cycle.
+
+Erroneous example, in `a.arith`:
+
+    import "b.arith";
+    def one = succ 0;
+
+and in `b.arith`:
+
+    import "a.arith";
+    def two = succ one;
+
+A file can only use the definitions of files loaded before it. Move the
+definitions both files need into a third file that both import.
+"#,
+    A0027(Syntax): r#"An imported file could not be read.
+
+Erroneous example:
+
+    import "missing.arith";
+
+Paths in `import` are relative to the directory of the importing file, and
+`use m::name;` loads `m.arith` from that directory.
+"#,
+    A0028(Syntax): r#"A `use` names something the module does not define.
+
+Erroneous example, where `nat.arith` only defines `double`:
+
+    use nat::triple;
+
+Only the `def`s of the module itself can be imported, not the ones it imports
+from other files.
+"#,
+    A0029(Type): r#"A definition does not have the type it is declared with.
+
+Erroneous example:
+
+    def one: Bool = succ 0;
+
+The type after the name must be a supertype of the type of the definition:
+
+    def one: Nat = succ 0;
+"#,
+    A0030(Syntax): r#"A name is defined or imported twice in the same file.
+
+Erroneous example:
+
+    def one = succ 0;
+    def one = 0;
+
+Each definition and import of a file must have its own name. Names defined
+in other files can be reused as long as they are not imported.
+"#,
+    A0031(Runtime): r#"A Nat became too large.
+
+Erroneous example:
+
+    succ 18446744073709551615
+
+Nats are 64-bit, so the largest one is 18446744073709551615, and taking its
+successor stops evaluation with this error. `pred 0` is `0`, so Nats never go
+below zero.
+"#,
+    A0032(Type): r#"A variable refers to a binding that has no type.
+
+Term variables are type checked in a context of bindings, and a variable
+must refer to one that gives it a type. The context can also hold type
+variables and names that are only bound for printing, and checking a
+variable that refers to one of those is a bug in the program that built the
+term, not in the source it came from.
+"#,
+    A0033(Syntax): r#"A Nat literal is too large.
+
+Erroneous example:
+
+    18446744073709551616
+
+Nats are 64-bit, so the largest one is 18446744073709551615.
+"#,
+}
@@ -0,0 +1,331 @@
+//! The source formatter behind `arith fmt`. It lays out the concrete syntax
+//! tree with the pretty printer's `Doc`s, so `if`/`else` blocks, lambdas and
+//! applications break the same way printed values do. Comments are kept.
+
+use crate::{
+    cst::{self, Element, Node, NodeKind, SyntaxToken},
+    err,
+    lexer::TokenKind,
+    pretty::Doc,
+    source::SourceFile,
+};
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Formats a whole source file to fit in `width` columns where possible.
+pub fn format(src: Rc<SourceFile>, width: usize) -> err::Result<String> {
+    let root = cst::parse(src.clone())?;
+    debug_assert_eq!(
+        {
+            let mut buf = String::new();
+            root.write_text(&src, &mut buf);
+            buf
+        },
+        src.text,
+        "the syntax tree is lossless"
+    );
+    let f = Formatter {
+        src: &src,
+        hoisted: Cell::new(None),
+    };
+    Ok(f.node(&root).render(width))
+}
+
+struct Formatter<'a> {
+    src: &'a SourceFile,
+    /// The start of the token whose leading comments were already written
+    /// before the item it begins, so they do not break the item's groups.
+    hoisted: Cell<Option<usize>>,
+}
+
+impl Formatter<'_> {
+    fn node(&self, node: &Node) -> Doc {
+        let c = |i: usize| self.element(&node.children[i]);
+        let sp = || Doc::text(" ");
+        match node.kind {
+            NodeKind::Root => {
+                let (eof, items) = node.children.split_last().unwrap();
+                let Element::Token(eof) = eof else {
+                    unreachable!()
+                };
+                let mut doc = Doc::nil();
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        doc = doc.append(Doc::hardline());
+                        if self.blank_line_between(&items[i - 1], item) {
+                            doc = doc.append(Doc::hardline());
+                        }
+                    }
+                    if let Some(first) = item_first_token(item) {
+                        doc = doc.append(self.leading_comments(first));
+                        self.hoisted.set(Some(first.span.lo));
+                    }
+                    doc = doc.append(self.element(item));
+                }
+                for (comment, _) in self.comments(eof) {
+                    doc = doc.append(Doc::hardline()).append(comment);
+                }
+                doc.append(Doc::hardline())
+            }
+            NodeKind::Import => c(0).append(sp()).append(c(1)).append(c(2)),
+            NodeKind::Use => c(0)
+                .append(sp())
+                .append(c(1))
+                .append(c(2))
+                .append(c(3))
+                .append(c(4)),
+            NodeKind::Def => {
+                // `def name: T =`, then the body and `;`
+                let n = node.children.len();
+                let mut head = c(0).append(sp()).append(c(1));
+                if n == 7 {
+                    head = head.append(c(2)).append(sp()).append(c(3));
+                }
+                let head = head.append(sp()).append(c(n - 3));
+                self.abs(head, c(n - 2)).append(c(n - 1))
+            }
+            NodeKind::TypeDecl => {
+                // Only a declaration inside a term has a body.
+                let (body, decl) = match node.children.split_last().unwrap() {
+                    (Element::Node(body), decl) => (Some(body), decl),
+                    (Element::Token(_), _) => (None, &node.children[..]),
+                };
+                let mut doc = Doc::nil();
+                for (i, child) in decl.iter().enumerate() {
+                    let is = |kind| matches!(child, Element::Token(t) if t.kind == kind);
+                    if i > 0 && !is(TokenKind::Semi) {
+                        doc = doc.append(sp());
+                    }
+                    doc = doc.append(self.element(child));
+                }
+                match body {
+                    Some(body) => doc.append(Doc::hardline()).append(self.node(body)),
+                    None => doc,
+                }
+            }
+            NodeKind::Prefix => c(0).append(sp()).append(c(1)),
+            NodeKind::If => c(0)
+                .append(sp())
+                .append(c(1))
+                .append(sp())
+                .append(c(2))
+                .append(Doc::line().append(c(3)).nest(2))
+                .append(Doc::line())
+                .append(c(4))
+                .append(sp())
+                .append(c(5))
+                .append(sp())
+                .append(c(6))
+                .append(Doc::line().append(c(7)).nest(2))
+                .append(Doc::line())
+                .append(c(8))
+                .group(),
+            NodeKind::Lambda => {
+                let param = c(0).append(c(1)).append(c(2)).append(sp()).append(c(3));
+                self.abs(param.append(c(4)), c(5))
+            }
+            NodeKind::TyLambda => {
+                let (body, param) = node.children.split_last().unwrap();
+                let param = param
+                    .iter()
+                    .fold(Doc::nil(), |doc, child| doc.append(self.element(child)));
+                self.abs(param, self.element(body))
+            }
+            NodeKind::Binary => c(0)
+                .append(sp())
+                .append(c(1))
+                .append(Doc::line().append(c(2)).nest(2))
+                .group(),
+            NodeKind::Call | NodeKind::Inst => {
+                // The arguments of a curried call are laid out together.
+                let mut args = vec![];
+                let mut head = node;
+                while matches!(head.kind, NodeKind::Call | NodeKind::Inst) {
+                    let (first, rest) = head.children.split_first().unwrap();
+                    let arg = rest
+                        .iter()
+                        .fold(Doc::nil(), |doc, child| doc.append(self.element(child)));
+                    args.push(arg);
+                    match first {
+                        Element::Node(node) => head = node,
+                        Element::Token(_) => unreachable!(),
+                    }
+                }
+                let args = args
+                    .into_iter()
+                    .rev()
+                    .fold(Doc::nil(), |doc, arg| doc.append(Doc::line()).append(arg));
+                self.node(head).append(args.nest(2)).group()
+            }
+            NodeKind::Record | NodeKind::TyRecord if node.children.len() == 2 => c(0).append(c(1)),
+            NodeKind::Record => {
+                let last = node.children.len() - 1;
+                let mut fields = Doc::nil();
+                for child in &node.children[1..last] {
+                    fields = fields.append(self.element(child));
+                    if matches!(child, Element::Token(_)) {
+                        fields = fields.append(Doc::line());
+                    }
+                }
+                c(0).append(Doc::softline().append(fields).nest(2))
+                    .append(Doc::softline())
+                    .append(c(last))
+                    .group()
+            }
+            NodeKind::TyRecord => {
+                let mut doc = Doc::nil();
+                for child in &node.children {
+                    doc = doc.append(self.element(child));
+                    if matches!(child, Element::Token(t) if t.kind == TokenKind::Comma) {
+                        doc = doc.append(sp());
+                    }
+                }
+                doc
+            }
+            NodeKind::Field => c(0).append(sp()).append(c(1)).append(sp()).append(c(2)),
+            NodeKind::TyField => c(0).append(c(1)).append(sp()).append(c(2)),
+            NodeKind::Paren => c(0).append(c(1).nest(1)).append(c(2)),
+            NodeKind::TyArrow => c(0).append(c(1)).append(c(2)).append(sp()).append(c(3)),
+            NodeKind::TyApp => c(0).append(sp()).append(c(1)),
+            NodeKind::TyRec | NodeKind::TyAll | NodeKind::TyAbs => {
+                // `mu X. T`, `forall X::K <: B. T` and `\X::K. T`
+                let mut doc = Doc::nil();
+                for (i, child) in node.children.iter().enumerate() {
+                    let attached = match child {
+                        Element::Token(t) => t.kind == TokenKind::Dot,
+                        Element::Node(n) => n.kind == NodeKind::TyParam,
+                    };
+                    if i == 1 && node.kind != NodeKind::TyAbs || i > 1 && !attached {
+                        doc = doc.append(sp());
+                    }
+                    doc = doc.append(self.element(child));
+                }
+                doc
+            }
+            NodeKind::TyParam => {
+                let mut doc = Doc::nil();
+                for child in &node.children {
+                    match child {
+                        Element::Token(t) if t.kind == TokenKind::Lt => {
+                            doc = doc.append(sp()).append(self.token(t));
+                        }
+                        Element::Token(t) if t.kind == TokenKind::Colon => {
+                            doc = doc.append(self.token(t)).append(sp());
+                        }
+                        _ => doc = doc.append(self.element(child)),
+                    }
+                }
+                doc
+            }
+            NodeKind::Kind => {
+                let mut doc = Doc::nil();
+                for child in &node.children {
+                    match child {
+                        Element::Token(t) if t.kind == TokenKind::FatArrow => {
+                            doc = doc.append(sp()).append(self.token(t)).append(sp());
+                        }
+                        _ => doc = doc.append(self.element(child)),
+                    }
+                }
+                doc
+            }
+            NodeKind::Proj
+            | NodeKind::Literal
+            | NodeKind::Name
+            | NodeKind::TyParen
+            | NodeKind::TyName => node
+                .children
+                .iter()
+                .fold(Doc::nil(), |doc, child| doc.append(self.element(child))),
+        }
+    }
+
+    /// Definitions are set apart by blank lines, and so are the imports
+    /// from what follows them.
+    fn blank_line_between(&self, prev: &Element, next: &Element) -> bool {
+        let kind = |element: &Element| match element {
+            Element::Node(node) => Some(node.kind),
+            Element::Token(_) => None,
+        };
+        let is_import = |kind| matches!(kind, Some(NodeKind::Import | NodeKind::Use));
+        let (prev, next) = (kind(prev), kind(next));
+        prev == Some(NodeKind::Def)
+            || next == Some(NodeKind::Def)
+            || is_import(prev) && !is_import(next)
+    }
+
+    fn abs(&self, param: Doc, body: Doc) -> Doc {
+        param.append(Doc::line().append(body).nest(2)).group()
+    }
+
+    fn element(&self, element: &Element) -> Doc {
+        match element {
+            Element::Node(node) => self.node(node),
+            Element::Token(token) => self.token(token),
+        }
+    }
+
+    /// The token as written, between the comments around it.
+    fn token(&self, token: &SyntaxToken) -> Doc {
+        let mut doc = if self.hoisted.get() == Some(token.span.lo) {
+            Doc::nil()
+        } else {
+            self.leading_comments(token)
+        };
+        doc = doc.append(Doc::text(self.text(token.span.lo, token.span.hi)));
+        for t in &token.trailing {
+            let text = self.text(t.span.lo, t.span.hi);
+            doc = match t.kind {
+                TokenKind::Whitespace => doc,
+                _ if text.starts_with("//") => doc.append(Doc::line_suffix(format!(" {}", text))),
+                _ => doc.append(Doc::text(format!(" {}", text))),
+            };
+        }
+        doc
+    }
+
+    fn leading_comments(&self, token: &SyntaxToken) -> Doc {
+        let mut doc = Doc::nil();
+        for (comment, newline) in self.comments(token) {
+            let sep = if newline {
+                Doc::hardline()
+            } else {
+                Doc::text(" ")
+            };
+            doc = doc.append(comment).append(sep);
+        }
+        doc
+    }
+
+    /// The comments before `token`, and whether each one must be followed by
+    /// a line break: line comments always are, block comments only if they
+    /// were in the source.
+    fn comments(&self, token: &SyntaxToken) -> Vec<(Doc, bool)> {
+        let mut comments = vec![];
+        let mut trivia = token.trivia.iter().peekable();
+        while let Some(t) = trivia.next() {
+            if !matches!(t.kind, TokenKind::Comment | TokenKind::DocComment) {
+                continue;
+            }
+            let text = self.text(t.span.lo, t.span.hi);
+            let newline = text.starts_with("//")
+                || trivia.peek().is_some_and(|next| {
+                    next.kind == TokenKind::Whitespace
+                        && self.text(next.span.lo, next.span.hi).contains('\n')
+                });
+            comments.push((Doc::text(text), newline));
+        }
+        comments
+    }
+
+    fn text(&self, lo: usize, hi: usize) -> &str {
+        &self.src.text[lo..hi]
+    }
+}
+
+fn item_first_token(element: &Element) -> Option<&SyntaxToken> {
+    match element {
+        Element::Node(node) => node.first_token(),
+        Element::Token(token) => Some(token),
+    }
+}
@@ -1,7 +1,7 @@
 mod symbol;
 mod token;
 
-use crate::span::Span;
+use crate::{err, source::SourceFile, span::Span};
 use std::collections::HashMap;
 use std::rc::Rc;
 pub use symbol::Symbol;
@@ -9,37 +9,53 @@ pub use token::{Token, TokenKind};
 use TokenKind::*;
 
 lazy_static! {
-    static ref KEYWORDS: HashMap<Symbol, TokenKind> = {
+    // Keyed by `&str` rather than `Symbol`: symbols are interned per thread.
+    static ref KEYWORDS: HashMap<&'static str, TokenKind> = {
         let mut map = HashMap::new();
-        map.insert(Symbol::intern("true"), TokenKind::True);
-        map.insert(Symbol::intern("false"), TokenKind::False);
-        map.insert(Symbol::intern("if"), TokenKind::If);
-        map.insert(Symbol::intern("else"), TokenKind::Else);
-        map.insert(Symbol::intern("succ"), TokenKind::Succ);
-        map.insert(Symbol::intern("pred"), TokenKind::Pred);
-        map.insert(Symbol::intern("iszero"), TokenKind::IsZero);
+        map.insert("true", TokenKind::True);
+        map.insert("false", TokenKind::False);
+        map.insert("if", TokenKind::If);
+        map.insert("else", TokenKind::Else);
+        map.insert("succ", TokenKind::Succ);
+        map.insert("pred", TokenKind::Pred);
+        map.insert("iszero", TokenKind::IsZero);
+        map.insert("mu", TokenKind::Mu);
+        map.insert("forall", TokenKind::Forall);
+        map.insert("type", TokenKind::Type);
+        map.insert("let", TokenKind::Let);
+        map.insert("def", TokenKind::Def);
+        map.insert("import", TokenKind::Import);
+        map.insert("use", TokenKind::Use);
         map
     };
 }
 
+#[derive(Clone)]
 pub struct Lexer {
-    src: Rc<String>,
+    src: Rc<SourceFile>,
     start: usize,
     pos: usize,
-    line: usize,
+    trivia: bool,
 }
 
 impl Lexer {
-    pub fn new(src: Rc<String>) -> Self {
+    pub fn new(src: Rc<SourceFile>) -> Self {
         Self {
             src,
             start: 0,
             pos: 0,
-            line: 1,
+            trivia: false,
         }
     }
 
-    pub fn next_token(&mut self) -> Token {
+    /// Makes `next_token` return whitespace and comments as tokens instead
+    /// of skipping them, so that the tokens cover all of the source.
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
+    pub fn next_token(&mut self) -> err::Result<Token> {
         while !self.eof() {
             self.start = self.pos;
             let kind = match self.next_char() {
@@ -47,41 +63,190 @@ impl Lexer {
                 b')' => CloseParen,
                 b'{' => OpenBrace,
                 b'}' => CloseBrace,
+                b'[' => OpenBracket,
+                b']' => CloseBracket,
                 b'+' => Plus,
                 b'-' => Minus,
                 b'*' => Star,
+                b'/' if self.peek_char() == b'/' => match self.line_comment() {
+                    kind if self.trivia => kind,
+                    _ => continue,
+                },
+                b'/' if self.peek_char() == b'*' => {
+                    self.block_comment()?;
+                    if !self.trivia {
+                        continue;
+                    }
+                    Comment
+                }
                 b'/' => Slash,
                 b',' => Comma,
                 b'.' => Dot,
                 b';' => Semi,
+                b':' if self.eat(b':') => ColonColon,
                 b':' => Colon,
                 b'!' => Not,
+                b'=' if self.eat(b'>') => FatArrow,
                 b'=' => Eq,
                 b'>' => Gt,
                 b'<' => Lt,
                 b'|' => Pipe,
-                b'\n' => {
-                    self.line += 1;
-                    continue;
+                b'\\' => Backslash,
+                b'"' => return self.string(),
+                c if c.is_ascii_digit() => self.number(),
+                c if c.is_ascii_whitespace() && self.trivia => {
+                    self.eat_while(|c| c.is_ascii_whitespace());
+                    Whitespace
                 }
-                b'0' => Zero,
                 c if c.is_ascii_whitespace() => continue,
                 c if c.is_ascii_alphabetic() => self.ident(),
-                _ => quit!(&self.src, self.span(), "Unknown character"),
+                _ => {
+                    // Cover the whole character, not just its first byte.
+                    while !self.src.text.is_char_boundary(self.pos) {
+                        self.pos += 1;
+                    }
+                    bail!(&self.src, self.span(), A0001, "Unknown character")
+                }
             };
-            return self.token(kind);
+            return Ok(self.token(kind));
         }
 
-        self.token(Eof)
+        self.start = self.pos;
+        Ok(self.token(Eof))
     }
 
     fn ident(&mut self) -> TokenKind {
         self.eat_while(|c| c.is_ascii_alphanumeric() || c == b'_');
-        let symbol = self.symbol();
         KEYWORDS
-            .get(&symbol)
+            .get(&self.src.text[self.start..self.pos])
             .copied()
-            .unwrap_or_else(|| TokenKind::Ident)
+            .unwrap_or(TokenKind::Ident)
+    }
+
+    /// Lexes the rest of a `//` comment, up to the end of the line. A `///`
+    /// comment documents the term after it.
+    fn line_comment(&mut self) -> TokenKind {
+        while !self.eof() && !matches!(self.peek_char(), b'\n' | b'\r') {
+            self.advance();
+        }
+        let text = &self.src.text[self.start..self.pos];
+        if text.starts_with("///") && !text.starts_with("////") {
+            DocComment
+        } else {
+            Comment
+        }
+    }
+
+    /// Lexes the rest of a `/* */` comment. Block comments nest.
+    fn block_comment(&mut self) -> err::Result<()> {
+        self.advance();
+        let mut depth = 1;
+        while depth > 0 {
+            if self.eof() {
+                let open = Span {
+                    lo: self.start,
+                    hi: self.start + 2,
+                    file: self.src.id,
+                };
+                bail!(&self.src, open, A0025, "Unterminated block comment");
+            }
+            if self.peek_char() == b'/' && self.peek_next_char() == b'*' {
+                self.advance();
+                depth += 1;
+            } else if self.peek_char() == b'*' && self.peek_next_char() == b'/' {
+                self.advance();
+                depth -= 1;
+            }
+            self.advance();
+        }
+        Ok(())
+    }
+
+    /// Lexes `digits` or `digits.digits`. A lone `0` is the Nat zero.
+    fn number(&mut self) -> TokenKind {
+        self.eat_while(|c| c.is_ascii_digit());
+        if self.peek_char() == b'.' && self.peek_next_char().is_ascii_digit() {
+            self.advance();
+            self.eat_while(|c| c.is_ascii_digit());
+            Float
+        } else if &self.src.text[self.start..self.pos] == "0" {
+            Zero
+        } else {
+            Number
+        }
+    }
+
+    /// Lexes a string literal. The token's symbol holds the contents with
+    /// escape sequences resolved.
+    fn string(&mut self) -> err::Result<Token> {
+        let mut buf = String::new();
+        loop {
+            let c = match self.src.text[self.pos..].chars().next() {
+                Some(c) => c,
+                None => bail!(&self.src, self.span(), A0002, "Unterminated string literal"),
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => break,
+                '\\' => {
+                    let lo = self.pos - 1;
+                    let c = match self.next_char() {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'0' => '\0',
+                        b'\\' => '\\',
+                        b'"' => '"',
+                        b'u' => self.unicode_escape(lo)?,
+                        _ => bail!(
+                            &self.src,
+                            self.span_from(lo),
+                            A0003,
+                            "Unknown escape sequence"
+                        ),
+                    };
+                    buf.push(c);
+                }
+                c => buf.push(c),
+            }
+        }
+
+        Ok(Token {
+            kind: Str,
+            span: self.span(),
+            symbol: Symbol::intern(&buf),
+        })
+    }
+
+    /// Lexes the rest of `\u{XXXX}` with one to six hex digits, starting at
+    /// `lo`.
+    fn unicode_escape(&mut self, lo: usize) -> err::Result<char> {
+        if !self.eat(b'{') {
+            bail!(
+                &self.src,
+                self.span_from(lo),
+                A0004,
+                "Expected '{' after \\u"
+            );
+        }
+        let start = self.pos;
+        self.eat_while(|c| c.is_ascii_hexdigit());
+        let digits = &self.src.text[start..self.pos];
+        let c = match digits.len() {
+            1..=6 => u32::from_str_radix(digits, 16)
+                .ok()
+                .and_then(char::from_u32),
+            _ => None,
+        };
+        match c {
+            Some(c) if self.eat(b'}') => Ok(c),
+            _ => bail!(
+                &self.src,
+                self.span_from(lo),
+                A0004,
+                "Invalid unicode escape sequence"
+            ),
+        }
     }
 
     fn token(&self, kind: TokenKind) -> Token {
@@ -91,15 +256,19 @@ impl Lexer {
     }
 
     fn symbol(&self) -> Symbol {
-        let s = &self.src[self.start..self.pos];
+        let s = &self.src.text[self.start..self.pos];
         Symbol::intern(s)
     }
 
     fn span(&self) -> Span {
+        self.span_from(self.start)
+    }
+
+    fn span_from(&self, lo: usize) -> Span {
         Span {
-            lo: self.start,
+            lo,
             hi: self.pos,
-            line: self.line,
+            file: self.src.id,
         }
     }
 
@@ -109,14 +278,33 @@ impl Lexer {
         }
     }
 
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek_char() == c {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
     fn peek_char(&self) -> u8 {
         self.src
+            .text
             .as_bytes()
             .get(self.pos)
             .copied()
             .unwrap_or_default()
     }
 
+    fn peek_next_char(&self) -> u8 {
+        self.src
+            .text
+            .as_bytes()
+            .get(self.pos + 1)
+            .copied()
+            .unwrap_or_default()
+    }
+
     fn next_char(&mut self) -> u8 {
         let c = self.peek_char();
         self.advance();
@@ -130,6 +318,6 @@ impl Lexer {
     }
 
     fn eof(&self) -> bool {
-        self.pos >= self.src.len()
+        self.pos >= self.src.text.len()
     }
 }
@@ -39,7 +39,7 @@ fn with_interner<T>(f: impl FnOnce(&mut Interner) -> T) -> T {
         static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
     }
 
-    INTERNER.with(|i| f(&mut *i.borrow_mut()))
+    INTERNER.with(|i| f(&mut i.borrow_mut()))
 }
 
 #[derive(Default)]
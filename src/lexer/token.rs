@@ -1,7 +1,7 @@
 use super::Symbol;
 use crate::span::Span;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
@@ -19,15 +19,26 @@ pub enum TokenKind {
     Succ,
     Pred,
     IsZero,
+    Mu,
+    Forall,
+    Type,
+    Let,
+    Def,
+    Import,
+    Use,
 
     // Other Identifier
     Ident,
     Number,
+    Float,
+    Str,
 
     OpenParen,
     CloseParen,
     OpenBrace,
     CloseBrace,
+    OpenBracket,
+    CloseBracket,
     Plus,
     Minus,
     Star,
@@ -36,11 +47,29 @@ pub enum TokenKind {
     Dot,
     Semi,
     Colon,
+    ColonColon,
     Not,
     Gt,
     Lt,
     Eq,
     Pipe,
+    Backslash,
+    FatArrow,
+
+    // Trivia, only produced by `Lexer::with_trivia`
+    Whitespace,
+    Comment,
+    /// A `///` comment.
+    DocComment,
 
     Eof,
 }
+
+impl TokenKind {
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Whitespace | TokenKind::Comment | TokenKind::DocComment
+        )
+    }
+}
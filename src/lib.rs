@@ -2,9 +2,17 @@
 extern crate lazy_static;
 
 #[macro_use]
-mod err;
+pub mod err;
 
+pub mod cst;
+pub mod format;
+pub mod json;
 pub mod lexer;
+pub mod module;
 pub mod parser;
+pub mod pretty;
+pub mod repl;
+pub mod source;
 pub mod span;
 pub mod syntax;
+pub mod vm;
@@ -1,36 +1,356 @@
 use arith::{
-    parser::Parser,
-    syntax::{Context, Eval, TyContext},
+    err::{self, codes, ErrorFormat},
+    format, module, pretty,
+    repl::{self, Repl},
+    source::SourceFile,
+    syntax::{Binding, Context, SubtypingMode, TyContext},
 };
+use std::fmt::Write as _;
+use std::io::{BufRead, Read, Write};
+use std::process::exit;
 use std::rc::Rc;
 
+const USAGE: &str = "\
+usage: arith [COMMAND] [OPTIONS] [FILE]
+       arith --explain CODE
+
+Reads the program from FILE, or from stdin if FILE is missing or `-`. Files it
+imports are found relative to FILE, or to the current directory for stdin.
+
+Commands:
+    run      Type check and evaluate, print the value and its type (default)
+    check    Type check, print the type
+    parse    Print the syntax tree
+    eval     Type check and evaluate, print the value
+    repl     Start an interactive session
+    disasm   Type check, print the bytecode the program compiles to
+    fmt      Format FILE in place, or stdin to stdout
+
+Options:
+    --error-format=human|json    How to print errors
+    --trace                      Print every evaluation step and where its
+                                 redex is (eval only)
+    --vm                         Evaluate by compiling to bytecode, which is
+                                 much faster (run and eval only)
+    --derivation[=text|latex]    Print the typing derivation (check only)
+    --width=N                    Wrap printed values and formatted code at N
+                                 columns (default 80)
+    --check                      Only check that the input is formatted (fmt only)
+    --no-prelude                 Leave out the definitions of the prelude, such
+                                 as `plus`, `eq` and `fact`
+    --subtyping=kernel|full      How bounded universal types are compared: kernel
+                                 requires equal bounds, full compares them
+                                 contravariantly but may not terminate
+                                 (default kernel)
+
+Exit status:
+    0 on success, 1 on a syntax error, 2 on a type error, 3 on a runtime
+    error, 4 if `fmt --check` finds unformatted input, 64 on bad usage, 66 if
+    the input cannot be read and 73 if the formatted file cannot be written.";
+
+const EXIT_USAGE: i32 = 64;
+const EXIT_NO_INPUT: i32 = 66;
+const EXIT_CANT_WRITE: i32 = 73;
+const EXIT_UNFORMATTED: i32 = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DerivationFormat {
+    Text,
+    Latex,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Run,
+    Check,
+    Parse,
+    Eval,
+    Repl,
+    Disasm,
+    Fmt,
+}
+
 fn main() {
-    let s = r#"
-        if true { |x: Bool| true } else { |y: Bool| false }
-    "#;
+    let mut command = None;
+    let mut path = None;
+    let mut trace = false;
+    let mut vm = false;
+    let mut derivation = None;
+    let mut width = pretty::DEFAULT_WIDTH;
+    let mut check = false;
+    let mut prelude = true;
+    let mut subtyping = SubtypingMode::Kernel;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--explain" {
+            match args.next() {
+                Some(code) => explain(&code),
+                None => usage("Expected an error code after --explain"),
+            }
+        } else if let Some(code) = arg.strip_prefix("--explain=") {
+            explain(code);
+        } else if let Some(format) = arg.strip_prefix("--error-format=") {
+            match format.parse::<ErrorFormat>() {
+                Ok(format) => err::set_error_format(format),
+                Err(e) => usage(&e),
+            }
+        } else if arg == "--trace" {
+            trace = true;
+        } else if arg == "--vm" {
+            vm = true;
+        } else if arg == "--derivation" || arg == "--derivation=text" {
+            derivation = Some(DerivationFormat::Text);
+        } else if arg == "--derivation=latex" {
+            derivation = Some(DerivationFormat::Latex);
+        } else if let Some(format) = arg.strip_prefix("--derivation=") {
+            usage(&format!("Unknown derivation format: {}", format));
+        } else if let Some(n) = arg.strip_prefix("--width=") {
+            match n.parse() {
+                Ok(n) => width = n,
+                Err(_) => usage(&format!("Invalid width: {}", n)),
+            }
+        } else if arg == "--check" {
+            check = true;
+        } else if arg == "--no-prelude" {
+            prelude = false;
+        } else if let Some(mode) = arg.strip_prefix("--subtyping=") {
+            match mode.parse() {
+                Ok(mode) => subtyping = mode,
+                Err(e) => usage(&e),
+            }
+        } else if arg == "--help" || arg == "-h" {
+            println!("{}", USAGE);
+            exit(0);
+        } else if arg.starts_with('-') && arg != "-" {
+            usage(&format!("Unknown option: {}", arg));
+        } else if command.is_none() && path.is_none() && parse_command(&arg).is_some() {
+            command = parse_command(&arg);
+        } else if path.replace(arg).is_some() {
+            usage("Expected a single input file");
+        }
+    }
 
-    let src = Rc::new(s.to_owned());
+    let command = command.unwrap_or(Command::Run);
+    if trace && command != Command::Eval {
+        usage("--trace can only be used with `eval`");
+    }
+    if vm && command != Command::Run && command != Command::Eval {
+        usage("--vm can only be used with `run` and `eval`");
+    }
+    if vm && trace {
+        usage("--vm cannot be used with --trace");
+    }
+    if derivation.is_some() && command != Command::Check {
+        usage("--derivation can only be used with `check`");
+    }
+    if check && command != Command::Fmt {
+        usage("--check can only be used with `fmt`");
+    }
+    if !prelude && command == Command::Fmt {
+        usage("--no-prelude cannot be used with `fmt`");
+    }
+
+    if command == Command::Repl {
+        if path.is_some() {
+            usage("`repl` does not take an input file");
+        }
+        return run_repl(prelude, subtyping);
+    }
+    if command == Command::Fmt {
+        return run_fmt(path.as_deref(), check, width);
+    }
+
+    let src = Rc::new(read_source(path.as_deref()));
     let tyctx = &mut TyContext::new();
+    tyctx.subtyping = subtyping;
+    let program = module::load(src, tyctx, prelude).unwrap_or_else(|d| d.abort());
+    let eval = program.eval();
 
-    let mut p = Parser::new(src.clone());
-    let t = Rc::new(p.parse_expr(tyctx));
+    if command == Command::Parse {
+        let buf = &mut String::new();
+        let mut ctx = Context::default();
+        for (i, def) in program.defs.iter().enumerate() {
+            // The prelude is not part of the program as written.
+            if i >= program.prelude {
+                writeln!(buf, "def {}", def.name).unwrap();
+                eval.dump(&def.term, &ctx, tyctx, buf);
+            }
+            ctx = ctx.add_binding(def.name, Binding::Name);
+        }
+        if let Some(body) = &program.body {
+            eval.dump(body, &ctx, tyctx, buf);
+        }
+        print!("{}", buf);
+        return;
+    }
 
-    let eval = Eval::new(src);
+    let ctx = &program.check_defs(tyctx).unwrap_or_else(|d| d.abort());
+    let t = program.body().unwrap_or_else(|d| d.abort());
+    if let Some(format) = derivation {
+        let mut derivation = eval.derive(t, ctx, tyctx).unwrap_or_else(|d| d.abort());
+        derivation.hide_outer(program.prelude);
+        match format {
+            DerivationFormat::Text => print!("{}", derivation.to_text()),
+            DerivationFormat::Latex => print!("{}", derivation.to_latex()),
+        }
+        return;
+    }
 
-    let ctx = &mut Context::default();
-    let ty = eval.type_of(&t, ctx, tyctx);
-    tyctx.print(ty);
+    let ty = eval.type_of(t, ctx, tyctx).unwrap_or_else(|d| d.abort());
+    if command == Command::Check {
+        println!("{}", tyctx.display(ctx, ty));
+        return;
+    }
 
+    if command == Command::Disasm {
+        let buf = &mut String::new();
+        program.compile(t).disassemble(tyctx, buf);
+        print!("{}", buf);
+        return;
+    }
+
+    let ty = tyctx.display(ctx, ty);
     let ctx = &mut Context::default();
+    let value = if vm {
+        let chunk = program.compile(t);
+        let value = chunk.run(&eval, tyctx).unwrap_or_else(|d| d.abort());
+        value.to_term(&chunk, &eval, tyctx)
+    } else if trace {
+        let t = program.link(t, tyctx);
+        let buf = &mut String::new();
+        eval.print(&t, ctx, buf);
+        println!("   {}", buf);
+        let steps = eval.trace(&t, ctx, tyctx);
+        for step in &steps {
+            let buf = &mut String::new();
+            eval.print_step(step, ctx, buf);
+            // Redexes keep the span of the source they were written in.
+            if let Some(src) = program.files.iter().find(|src| src.id == step.redex.file) {
+                let loc = step.redex.start(src);
+                write!(buf, " at {}:{}:{}", src.name, loc.line + 1, loc.col + 1).unwrap();
+            }
+            println!("{}", buf);
+        }
+        steps.last().map_or(t, |step| step.term.clone())
+    } else {
+        eval.eval(&program.link(t, tyctx), ctx, tyctx)
+    };
 
-    let buf = &mut String::new();
-    eval.print(&t, ctx, buf);
-    println!("{}", buf);
+    if !value.is_val(ctx) {
+        eval.stuck(&value).abort();
+    }
 
-    let t = eval.eval(&t, ctx);
+    let value = pretty::pretty(&value, ctx, tyctx, width);
+    match command {
+        Command::Run => println!("{} : {}", value, ty),
+        _ if !trace => println!("{}", value),
+        _ => {}
+    }
+}
 
-    let ctx = &mut Context::default();
-    let buf = &mut String::new();
-    eval.print(&t, ctx, buf);
-    println!("{}", buf);
+fn run_repl(prelude: bool, subtyping: SubtypingMode) {
+    println!("arith {}, type :help for help", env!("CARGO_PKG_VERSION"));
+    let mut repl = if prelude {
+        Repl::new()
+    } else {
+        Repl::without_prelude()
+    };
+    repl.set_subtyping(subtyping);
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                eprintln!("error: Could not read stdin: {}", e);
+                exit(EXIT_NO_INPUT);
+            }
+            None => break println!(),
+        };
+        match line.trim() {
+            ":quit" | ":q" => break,
+            ":help" | ":h" => println!("{}", repl::HELP),
+            line => match repl.eval_line(line) {
+                Ok(out) if out.is_empty() => {}
+                Ok(out) => println!("{}", out),
+                Err(d) => d.report(),
+            },
+        }
+    }
+}
+
+/// Formats the file in place, or prints the formatted input if it came from
+/// stdin. With `check`, only reports whether formatting would change it.
+fn run_fmt(path: Option<&str>, check: bool, width: usize) {
+    let src = Rc::new(read_source(path));
+    let formatted = format::format(src.clone(), width).unwrap_or_else(|d| d.abort());
+    if check {
+        if formatted != src.text {
+            eprintln!("error: {} is not formatted", src.name);
+            exit(EXIT_UNFORMATTED);
+        }
+        return;
+    }
+    match path {
+        None | Some("-") => print!("{}", formatted),
+        Some(path) if formatted != src.text => {
+            if let Err(e) = std::fs::write(path, formatted) {
+                eprintln!("error: Could not write {}: {}", path, e);
+                exit(EXIT_CANT_WRITE);
+            }
+        }
+        Some(_) => {}
+    }
+}
+
+fn parse_command(arg: &str) -> Option<Command> {
+    match arg {
+        "run" => Some(Command::Run),
+        "check" => Some(Command::Check),
+        "parse" => Some(Command::Parse),
+        "eval" => Some(Command::Eval),
+        "repl" => Some(Command::Repl),
+        "disasm" => Some(Command::Disasm),
+        "fmt" => Some(Command::Fmt),
+        _ => None,
+    }
+}
+
+fn read_source(path: Option<&str>) -> SourceFile {
+    match path {
+        None | Some("-") => {
+            let mut s = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut s) {
+                eprintln!("error: Could not read stdin: {}", e);
+                exit(EXIT_NO_INPUT);
+            }
+            SourceFile::new("<stdin>", s)
+        }
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(s) => SourceFile::new(path, s),
+            Err(e) => {
+                eprintln!("error: Could not read {}: {}", path, e);
+                exit(EXIT_NO_INPUT);
+            }
+        },
+    }
+}
+
+fn explain(code: &str) -> ! {
+    match codes::explain(code) {
+        Some(explanation) => {
+            print!("{}", explanation);
+            exit(0);
+        }
+        None => {
+            eprintln!("error: {} is not a valid error code", code);
+            exit(EXIT_USAGE);
+        }
+    }
+}
+
+fn usage(msg: &str) -> ! {
+    eprintln!("error: {}\n\n{}", msg, USAGE);
+    exit(EXIT_USAGE);
 }
@@ -0,0 +1,365 @@
+//! Programs split across files. A file starts with its imports,
+//!
+//! ```text
+//! import "lib/nat.arith";
+//! use bool::not;
+//! ```
+//!
+//! which bring in every definition of `lib/nat.arith` and only `not` from
+//! `bool.arith`, both relative to the importing file. Type declarations and
+//! `def`s follow, in any order, each one able to use the ones before it. The
+//! file the program starts from ends with the term to evaluate, or defines
+//! it as `main`.
+//!
+//! Every program also sees the definitions of the prelude, `PRELUDE`, unless
+//! it is loaded without it. Its own definitions and imports shadow them.
+//!
+//! Type declarations are not scoped like `def`s: like the builtin types,
+//! they are global, so a file sees the types of every file loaded before
+//! it, imported or not, and a type name can only be declared once in the
+//! whole program.
+
+use crate::{
+    err::{self, codes, Diagnostic},
+    lexer::Symbol,
+    parser::{Def, Import, Parser},
+    source::SourceFile,
+    span::Span,
+    syntax::{Binding, Context, Eval, Term, TermKind, TyContext, TypeId, TypeMap},
+    vm::{self, Chunk},
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The source of the prelude: boolean and arithmetic functions.
+pub const PRELUDE: &str = include_str!("prelude.arith");
+
+pub struct Program {
+    /// Every file of the program, the root first.
+    pub files: Vec<Rc<SourceFile>>,
+    /// The definitions of all files. Each one comes after the definitions
+    /// it can refer to, and its free variables index into the ones before.
+    pub defs: Vec<Def>,
+    /// How many of the definitions, at the start, come from the prelude.
+    pub prelude: usize,
+    /// The term at the end of the root file, or a reference to its `main`
+    /// definition, if it has either.
+    pub body: Option<Rc<Term>>,
+}
+
+impl Program {
+    /// An evaluator for the terms of all files of the program.
+    pub fn eval(&self) -> Eval {
+        Eval::new(self.files[0].clone()).with_files(self.files.clone())
+    }
+
+    /// The file `span` is in.
+    pub fn file(&self, span: Span) -> &Rc<SourceFile> {
+        self.files
+            .iter()
+            .find(|src| src.id == span.file)
+            .unwrap_or(&self.files[0])
+    }
+
+    /// The term to evaluate, or an error at the end of the root file if
+    /// there is none.
+    pub fn body(&self) -> err::Result<&Rc<Term>> {
+        match &self.body {
+            Some(body) => Ok(body),
+            None => {
+                let root = &self.files[0];
+                let end = root.text.len();
+                let span = Span {
+                    lo: end,
+                    hi: end,
+                    file: root.id,
+                };
+                bail!(
+                    root,
+                    span,
+                    A0005,
+                    "Expected a term to evaluate or a `main` definition"
+                );
+            }
+        }
+    }
+
+    /// Type checks the definitions in order, and returns the context that
+    /// binds them, in which the body is typed.
+    pub fn check_defs(&self, tyctx: &mut TyContext) -> err::Result<Context> {
+        let types = self.check_defs_into(tyctx, None)?;
+        Ok(self.context(&types))
+    }
+
+    /// The types of the definitions, which must type check.
+    pub fn def_types(&self, tyctx: &mut TyContext) -> err::Result<Vec<TypeId>> {
+        self.check_defs_into(tyctx, None)
+    }
+
+    /// Type checks the definitions and the body like `check_defs`, and
+    /// records the types of their subterms in `types`.
+    pub fn type_map(&self, tyctx: &mut TyContext, types: &mut TypeMap) -> err::Result<()> {
+        let ctx = self.context(&self.check_defs_into(tyctx, Some(&mut *types))?);
+        if let Some(body) = &self.body {
+            self.eval().type_map(body, &ctx, tyctx, types)?;
+        }
+        Ok(())
+    }
+
+    fn check_defs_into(
+        &self,
+        tyctx: &mut TyContext,
+        mut types: Option<&mut TypeMap>,
+    ) -> err::Result<Vec<TypeId>> {
+        let eval = self.eval();
+        let mut ctx = Context::default();
+        let mut tys = vec![];
+        for def in &self.defs {
+            let mut ty = match types.as_deref_mut() {
+                Some(types) => eval.type_map(&def.term, &ctx, tyctx, types)?,
+                None => eval.type_of(&def.term, &ctx, tyctx)?,
+            };
+            if let Some((declared, span)) = def.ty {
+                eval.check_declared(ty, declared, def.term.span, span, &ctx, tyctx)?;
+                ty = declared;
+            }
+            ctx = ctx.add_binding(def.name, Binding::Variable(ty));
+            tys.push(ty);
+        }
+        Ok(tys)
+    }
+
+    /// Binds each definition to its type in `types`.
+    fn context(&self, types: &[TypeId]) -> Context {
+        let mut ctx = Context::default();
+        for (def, &ty) in self.defs.iter().zip(types) {
+            ctx = ctx.add_binding(def.name, Binding::Variable(ty));
+        }
+        ctx
+    }
+
+    /// Evaluates the definitions in order and substitutes their values into
+    /// `body`, which gives a closed term. The definitions must type check.
+    pub fn link(&self, body: &Rc<Term>, tyctx: &mut TyContext) -> Rc<Term> {
+        close(&self.eval(), body, &self.values(tyctx))
+    }
+
+    /// Compiles the definitions and `body` to bytecode, which gives the
+    /// same value as `link` once run. The definitions must type check.
+    pub fn compile(&self, body: &Rc<Term>) -> Chunk {
+        let defs: Vec<_> = self.defs.iter().map(|def| def.term.clone()).collect();
+        vm::compile(&defs, body)
+    }
+
+    /// The closed values of the definitions, which must type check.
+    pub fn values(&self, tyctx: &mut TyContext) -> Vec<Rc<Term>> {
+        let eval = self.eval();
+        let mut values = vec![];
+        for def in &self.defs {
+            let term = close(&eval, &def.term, &values);
+            values.push(eval.eval(&term, &mut Context::default(), tyctx));
+        }
+        values
+    }
+}
+
+/// Substitutes the closed `values` for the free variables of `term`, the
+/// last value having index 0.
+fn close(eval: &Eval, term: &Rc<Term>, values: &[Rc<Term>]) -> Rc<Term> {
+    // Each value only needs weakening past the values before it.
+    let mut term = term.clone();
+    for (i, value) in values.iter().enumerate().rev() {
+        term = eval.subst_top(&term, eval.shift(value, i as i32));
+    }
+    term
+}
+
+/// Loads `root` and the files it imports, transitively, after the prelude
+/// if `prelude` is set. Only `root` may end with a term.
+pub fn load(root: Rc<SourceFile>, tyctx: &mut TyContext, prelude: bool) -> err::Result<Program> {
+    let mut loader = Loader {
+        tyctx,
+        files: vec![root.clone()],
+        defs: vec![],
+        prelude: vec![],
+        loaded: HashMap::new(),
+        stack: vec![],
+    };
+    if prelude {
+        let src = Rc::new(SourceFile::new("<prelude>", PRELUDE));
+        loader.files.push(src.clone());
+        loader.prelude = loader.load_file(src)?.0;
+    }
+    // The root is not on disk if it came from stdin.
+    if let Ok(path) = fs::canonicalize(&root.name) {
+        loader.stack.push((path, root.name.clone()));
+    }
+    let (exports, body) = loader.load_file(root)?;
+    // Without a term at the end, the program runs the root's `main`.
+    let main = Symbol::intern("main");
+    let body = body.or_else(|| {
+        let i = exports.into_iter().find(|&i| loader.defs[i].name == main)?;
+        let len = loader.defs.len();
+        Some(Term {
+            kind: TermKind::Var {
+                idx: (len - 1 - i) as u32,
+                len: len as u32,
+            },
+            span: loader.defs[i].span,
+        })
+    });
+    Ok(Program {
+        files: loader.files,
+        prelude: loader.prelude.len(),
+        defs: loader.defs,
+        body: body.map(Rc::new),
+    })
+}
+
+struct Loader<'a> {
+    tyctx: &'a mut TyContext,
+    files: Vec<Rc<SourceFile>>,
+    defs: Vec<Def>,
+    /// The definitions of the prelude, in scope in every other file.
+    prelude: Vec<usize>,
+    /// The definitions of each loaded file, by canonical path.
+    loaded: HashMap<PathBuf, Vec<usize>>,
+    /// The files being loaded, by canonical path and name, importers first.
+    stack: Vec<(PathBuf, String)>,
+}
+
+impl Loader<'_> {
+    /// Loads the files `src` imports, then its definitions. Returns the
+    /// indices of the definitions and the term at the end of the file.
+    fn load_file(&mut self, src: Rc<SourceFile>) -> err::Result<(Vec<usize>, Option<Term>)> {
+        let mut parser = Parser::new(src.clone())?;
+        let dir = Path::new(&src.name).parent().unwrap_or(Path::new(""));
+
+        // The names in scope in the file, with the definition each stands
+        // for and the span of the import or definition that introduced it.
+        let mut scope: HashMap<Symbol, (usize, Span)> = HashMap::new();
+        for import in parser.parse_imports()? {
+            let (imported, span) = match import {
+                Import::File { path, span } => {
+                    let path = path.as_str_with(|path| dir.join(path));
+                    (self.import(&src, &path, span)?, span)
+                }
+                Import::Name {
+                    module,
+                    name,
+                    span,
+                    name_span,
+                } => {
+                    let path = dir.join(format!("{}.arith", module));
+                    let exports = self.import(&src, &path, span)?;
+                    match exports.into_iter().find(|&i| self.defs[i].name == name) {
+                        Some(i) => (vec![i], span),
+                        None => bail!(
+                            &src,
+                            name_span,
+                            A0028,
+                            "`{}` is not defined in module `{}`",
+                            name,
+                            module
+                        ),
+                    }
+                }
+            };
+            for i in imported {
+                let name = self.defs[i].name;
+                match scope.get(&name) {
+                    // The same definition, imported again.
+                    Some(&(j, _)) if i == j => {}
+                    Some(&(_, first)) => {
+                        let msg = format!("`{}` is imported twice", name);
+                        return Err(Diagnostic::new(&src, msg, span)
+                            .with_code(codes::A0030)
+                            .with_label(first, "first imported here"));
+                    }
+                    None => {
+                        scope.insert(name, (i, span));
+                    }
+                }
+            }
+        }
+
+        // Definitions that are not imported stay in the list, so indices
+        // line up, but cannot be named.
+        let mut names = vec![Symbol::dummy(); self.defs.len()];
+        for &i in &self.prelude {
+            names[i] = self.defs[i].name;
+        }
+        for (&name, &(i, _)) in &scope {
+            names[i] = name;
+        }
+        let module = parser.with_names(names).parse_module(self.tyctx)?;
+        let start = self.defs.len();
+        for (i, def) in module.defs.iter().enumerate() {
+            if let Some(&(j, first)) = scope.get(&def.name) {
+                let (msg, label) = if j < start {
+                    (
+                        format!("`{}` is already imported", def.name),
+                        "imported here",
+                    )
+                } else {
+                    (
+                        format!("`{}` is defined twice", def.name),
+                        "first defined here",
+                    )
+                };
+                return Err(Diagnostic::new(&src, msg, def.span)
+                    .with_code(codes::A0030)
+                    .with_label(first, label));
+            }
+            scope.insert(def.name, (start + i, def.span));
+        }
+        self.defs.extend(module.defs);
+        Ok(((start..self.defs.len()).collect(), module.body))
+    }
+
+    /// Loads the file at `path`, imported from `src` at `span`, unless it
+    /// was loaded before. Returns the indices of its definitions.
+    fn import(&mut self, src: &Rc<SourceFile>, path: &Path, span: Span) -> err::Result<Vec<usize>> {
+        let unreadable = |e: std::io::Error| {
+            let msg = format!("Could not read `{}`", path.display());
+            Diagnostic::new(src, msg, span)
+                .with_code(codes::A0027)
+                .with_note(e.to_string())
+        };
+        let key = fs::canonicalize(path).map_err(unreadable)?;
+        if let Some(i) = self.stack.iter().position(|(p, _)| *p == key) {
+            let mut cycle: Vec<_> = self.stack[i..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .collect();
+            cycle.push(&self.stack[i].1);
+            return Err(Diagnostic::new(src, "Import cycle".to_owned(), span)
+                .with_code(codes::A0026)
+                .with_primary_label("imports a file that is still being loaded")
+                .with_note(format!("the cycle is {}", cycle.join(" -> "))));
+        }
+        if let Some(exports) = self.loaded.get(&key) {
+            return Ok(exports.clone());
+        }
+
+        let text = fs::read_to_string(&key).map_err(unreadable)?;
+        let name = path.display().to_string();
+        let file = Rc::new(SourceFile::new(name.clone(), text));
+        self.files.push(file.clone());
+        self.stack.push((key.clone(), name));
+        let (exports, body) = self.load_file(file.clone())?;
+        self.stack.pop();
+        if let Some(body) = body {
+            bail!(
+                &file,
+                body.span,
+                A0005,
+                "Only the file the program starts from can end with a term"
+            );
+        }
+        self.loaded.insert(key, exports.clone());
+        Ok(exports)
+    }
+}
@@ -1,23 +1,85 @@
 use crate::{
+    err::{self, codes, Diagnostic},
     lexer::{Lexer, Symbol, Token, TokenKind, TokenKind::*},
+    source::SourceFile,
     span::Span,
-    syntax::{Term, TermKind, TyContext},
+    syntax::{ArithOp, Binding, Context, Kind, Term, TermKind, TyContext, TypeId},
 };
 use std::rc::Rc;
 
+/// A line of REPL input, after any type declarations in it.
+pub enum Line {
+    Empty,
+    Let(Symbol, Term),
+    Term(Term),
+}
+
+/// An `import "path.arith";` or `use module::name;` at the top of a file.
+pub enum Import {
+    /// Imports every definition of the file at `path`, relative to the
+    /// importing file.
+    File { path: Symbol, span: Span },
+    /// Imports `name` from `module.arith`, next to the importing file.
+    Name {
+        module: Symbol,
+        name: Symbol,
+        span: Span,
+        name_span: Span,
+    },
+}
+
+impl Import {
+    pub fn span(&self) -> Span {
+        match *self {
+            Import::File { span, .. } | Import::Name { span, .. } => span,
+        }
+    }
+}
+
+/// A top-level `def name = t;` or `def name: T = t;`.
+pub struct Def {
+    pub name: Symbol,
+    /// The span of the name.
+    pub span: Span,
+    /// The declared type and its span.
+    pub ty: Option<(TypeId, Span)>,
+    pub term: Rc<Term>,
+}
+
+/// The definitions of a file, and the term at its end if there is one.
+pub struct Module {
+    pub defs: Vec<Def>,
+    pub body: Option<Term>,
+}
+
 pub struct Parser {
-    lexer: Lexer,
+    /// The tokens after `curr`, in reverse order.
+    tokens: Vec<Token>,
     curr: Token,
     prev: Token,
-    src: Rc<String>,
+    src: Rc<SourceFile>,
+    names: Vec<Symbol>,
+    /// The type variables in scope and their kinds, the innermost last.
+    ty_vars: Vec<(Symbol, Kind)>,
 }
 
 impl Parser {
-    pub fn new(src: Rc<String>) -> Self {
+    /// Lexes all of `src` up front, so lexical errors are reported before
+    /// parsing starts.
+    pub fn new(src: Rc<SourceFile>) -> err::Result<Self> {
         let mut lexer = Lexer::new(src.clone());
-        let curr = lexer.next_token();
-        Self {
-            lexer,
+        let mut tokens = vec![];
+        loop {
+            let token = lexer.next_token()?;
+            tokens.push(token);
+            if token.kind == Eof {
+                break;
+            }
+        }
+        tokens.reverse();
+        let curr = tokens.pop().unwrap();
+        Ok(Self {
+            tokens,
             curr,
             prev: Token {
                 kind: Eof,
@@ -25,108 +87,638 @@ impl Parser {
                 symbol: Symbol::dummy(),
             },
             src,
+            names: vec![],
+            ty_vars: vec![],
+        })
+    }
+
+    /// Makes `names` available as free term variables, the last one having
+    /// index 0.
+    pub fn with_names(mut self, names: Vec<Symbol>) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Parses `type` declarations followed by `let x = t`, a term, or
+    /// nothing, up to the end of the input.
+    pub fn parse_line(&mut self, tcx: &mut TyContext) -> err::Result<Line> {
+        while self.eat(Type) {
+            self.parse_alias(tcx)?;
         }
+        let line = if self.curr.kind == Eof {
+            Line::Empty
+        } else if self.eat(Let) {
+            self.consume(Ident, "Expected a name after 'let'")?;
+            let name = self.prev.symbol;
+            self.consume(Eq, "Expected '=' after the name")?;
+            Line::Let(name, self.parse_expr(tcx)?)
+        } else {
+            Line::Term(self.parse_expr(tcx)?)
+        };
+        self.consume(Eof, "Expected end of input")?;
+        Ok(line)
     }
 
-    pub fn parse_expr(&mut self, tcx: &mut TyContext) -> Term {
-        if self.eat(True) {
-            Term {
-                kind: TermKind::True,
-                span: self.prev.span,
-            }
-        } else if self.eat(False) {
-            Term {
-                kind: TermKind::False,
-                span: self.prev.span,
+    /// Parses the imports at the top of a file.
+    pub fn parse_imports(&mut self) -> err::Result<Vec<Import>> {
+        let mut imports = vec![];
+        loop {
+            let lo = self.curr.span;
+            if self.eat(TokenKind::Import) {
+                self.consume(Str, "Expected a path after 'import'")?;
+                let path = self.prev.symbol;
+                self.consume(Semi, "Expected ';' after import")?;
+                let span = lo.to(self.prev.span);
+                imports.push(Import::File { path, span });
+            } else if self.eat(Use) {
+                self.consume(Ident, "Expected a module name after 'use'")?;
+                let module = self.prev.symbol;
+                self.consume(ColonColon, "Expected '::' after the module name")?;
+                self.consume(Ident, "Expected a name after '::'")?;
+                let name = self.prev.symbol;
+                let name_span = self.prev.span;
+                self.consume(Semi, "Expected ';' after use")?;
+                let span = lo.to(self.prev.span);
+                imports.push(Import::Name {
+                    module,
+                    name,
+                    span,
+                    name_span,
+                });
+            } else {
+                return Ok(imports);
             }
-        } else if self.eat(Zero) {
-            Term {
-                kind: TermKind::Zero,
-                span: self.prev.span,
+        }
+    }
+
+    /// Parses `type` declarations and `def`s, then an optional term, up to
+    /// the end of the input. Each definition is in scope after it, not in
+    /// its own body.
+    pub fn parse_module(&mut self, tcx: &mut TyContext) -> err::Result<Module> {
+        let mut defs = vec![];
+        loop {
+            if self.eat(Type) {
+                self.parse_alias(tcx)?;
+            } else if self.eat(Def) {
+                self.consume(Ident, "Expected a name after 'def'")?;
+                let name = self.prev.symbol;
+                let span = self.prev.span;
+                let ty = if self.eat(Colon) {
+                    Some(self.parse_spanned_ty(tcx)?)
+                } else {
+                    None
+                };
+                self.consume(Eq, "Expected ':' or '=' after the name")?;
+                let term = Rc::new(self.parse_expr(tcx)?);
+                self.consume(Semi, "Expected ';' after definition")?;
+                self.names.push(name);
+                defs.push(Def {
+                    name,
+                    span,
+                    ty,
+                    term,
+                });
+            } else if matches!(self.curr.kind, TokenKind::Import | Use) {
+                bail!(
+                    &self.src,
+                    self.curr.span,
+                    A0005,
+                    "Imports must come before definitions"
+                );
+            } else {
+                break;
             }
+        }
+        let body = if self.curr.kind == Eof {
+            None
+        } else {
+            Some(self.parse_expr(tcx)?)
+        };
+        self.consume(Eof, "Expected end of input")?;
+        Ok(Module { defs, body })
+    }
+
+    pub fn parse_expr(&mut self, tcx: &mut TyContext) -> err::Result<Term> {
+        if self.eat(Type) {
+            self.parse_alias(tcx)?;
+            self.parse_expr(tcx)
         } else if self.eat(Succ) {
             let lo = self.prev.span;
-            let term = self.parse_expr(tcx);
-            Term {
+            let term = self.parse_expr(tcx)?;
+            Ok(Term {
                 kind: TermKind::Succ(Rc::new(term)),
                 span: lo.to(self.prev.span),
-            }
+            })
         } else if self.eat(Pred) {
             let lo = self.prev.span;
-            let term = self.parse_expr(tcx);
-            Term {
+            let term = self.parse_expr(tcx)?;
+            Ok(Term {
                 kind: TermKind::Pred(Rc::new(term)),
                 span: lo.to(self.prev.span),
-            }
+            })
         } else if self.eat(IsZero) {
             let lo = self.prev.span;
-            let term = self.parse_expr(tcx);
-            Term {
+            let term = self.parse_expr(tcx)?;
+            Ok(Term {
                 kind: TermKind::IsZero(Rc::new(term)),
                 span: lo.to(self.prev.span),
-            }
+            })
         } else if self.eat(If) {
             let lo = self.prev.span;
-            let cond = self.parse_expr(tcx);
-            self.consume(OpenBrace, "Expected '{' after If condition");
-            let yes = self.parse_expr(tcx);
-            self.consume(CloseBrace, "Expected '}'");
-            self.consume(Else, "Expected 'else'");
-            self.consume(OpenBrace, "Expected '{' after else");
-            let no = self.parse_expr(tcx);
-            self.consume(CloseBrace, "Expected '}'");
+            let cond = self.parse_expr(tcx)?;
+            self.consume(OpenBrace, "Expected '{' after If condition")?;
+            let yes = self.parse_expr(tcx)?;
+            self.consume(CloseBrace, "Expected '}'")?;
+            self.consume(Else, "Expected 'else'")?;
+            self.consume(OpenBrace, "Expected '{' after else")?;
+            let no = self.parse_expr(tcx)?;
+            self.consume(CloseBrace, "Expected '}'")?;
 
             let span = lo.to(self.prev.span);
-            Term {
+            Ok(Term {
                 kind: TermKind::If {
                     cond: Rc::new(cond),
                     then_branch: Rc::new(yes),
                     else_branch: Rc::new(no),
                 },
                 span,
-            }
+            })
         } else if self.eat(Pipe) {
             let lo = self.prev.span;
-            self.consume(Ident, "Expected an indentifier for Lambda parameter");
+            self.consume(Ident, "Expected an indentifier for Lambda parameter")?;
             let name = self.prev.symbol;
-            self.consume(Colon, "Expected ':' after lambda parameter");
-            self.consume(Ident, "Expected type after lambda parameter");
-            let ty_symbol = self.prev.symbol;
-
-            let ty = ty_symbol.as_str_with(|s| match s {
-                "Bool" => tcx.common.boolean,
-                "Nat" => tcx.common.nat,
-                x => quit!(&self.src, self.prev.span, "Unknown type: {}", x),
-            });
+            if !self.eat(Colon) {
+                return self.parse_ty_abs(tcx, lo, name);
+            }
+            let (ty, ty_span) = self.parse_spanned_ty(tcx)?;
+            self.consume(Pipe, "Expected '|' after Lambda parameter")?;
 
-            self.consume(Pipe, "Expected '|' after Lambda parameter");
-            let body = self.parse_expr(tcx);
+            self.names.push(name);
+            let body = self.parse_expr(tcx)?;
+            self.names.pop();
 
             let span = lo.to(self.prev.span);
-            Term {
+            Ok(Term {
                 kind: TermKind::Fun {
                     name,
                     ty,
+                    ty_span,
                     term: Rc::new(body),
                 },
                 span,
+            })
+        } else {
+            self.parse_sum(tcx)
+        }
+    }
+
+    fn parse_sum(&mut self, tcx: &mut TyContext) -> err::Result<Term> {
+        let mut lhs = self.parse_product(tcx)?;
+        loop {
+            let op = if self.eat(Plus) {
+                ArithOp::Add
+            } else if self.eat(Minus) {
+                ArithOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.parse_product(tcx)?;
+            lhs = Self::arith(op, lhs, rhs);
+        }
+    }
+
+    fn parse_product(&mut self, tcx: &mut TyContext) -> err::Result<Term> {
+        let mut lhs = self.parse_call(tcx)?;
+        loop {
+            let op = if self.eat(Star) {
+                ArithOp::Mul
+            } else if self.eat(Slash) {
+                ArithOp::Div
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.parse_call(tcx)?;
+            lhs = Self::arith(op, lhs, rhs);
+        }
+    }
+
+    fn arith(op: ArithOp, lhs: Term, rhs: Term) -> Term {
+        let span = lhs.span.to(rhs.span);
+        Term {
+            kind: TermKind::Arith {
+                op,
+                lhs: Rc::new(lhs),
+                rhs: Rc::new(rhs),
+            },
+            span,
+        }
+    }
+
+    /// Parses the rest of `type Name = T;`, or of `type Name;` which declares
+    /// an uninterpreted base type.
+    fn parse_alias(&mut self, tcx: &mut TyContext) -> err::Result<()> {
+        let lo = self.prev.span;
+        self.consume(Ident, "Expected a name after 'type'")?;
+        let name = self.prev.symbol;
+        let name_span = self.prev.span;
+        let (ty, ty_span) = if self.eat(Semi) {
+            (tcx.new_base(name), name_span)
+        } else {
+            self.consume(Eq, "Expected '=' or ';' after type name")?;
+            let ty = self.parse_spanned_ty(tcx)?;
+            self.consume(Semi, "Expected ';' after type alias")?;
+            ty
+        };
+
+        if !self.ty_vars.is_empty() {
+            bail!(
+                &self.src,
+                lo.to(self.prev.span),
+                A0011,
+                "Type aliases cannot be declared inside a type abstraction"
+            );
+        }
+        // Aliases may be type operators, so any kind will do, but an
+        // ill-kinded body is reported here rather than where it is used.
+        self.kind_of(tcx, ty, ty_span)?;
+        if !tcx.define_alias(name, ty) {
+            bail!(
+                &self.src,
+                name_span,
+                A0010,
+                "Type `{}` is already defined",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    /// Parses the rest of `|X::K <: T| body`, where the kind defaults to `*`
+    /// and the bound to `Top`.
+    fn parse_ty_abs(&mut self, tcx: &mut TyContext, lo: Span, name: Symbol) -> err::Result<Term> {
+        let (kind, bound, bound_span) = self.parse_ty_param(tcx)?;
+        self.consume(Pipe, "Expected '|' after type parameter")?;
+
+        self.ty_vars.push((name, kind.clone()));
+        let body = self.parse_expr(tcx)?;
+        self.ty_vars.pop();
+
+        let span = lo.to(self.prev.span);
+        Ok(Term {
+            kind: TermKind::TyAbs {
+                name,
+                kind,
+                bound,
+                bound_span,
+                term: Rc::new(body),
+            },
+            span,
+        })
+    }
+
+    fn parse_call(&mut self, tcx: &mut TyContext) -> err::Result<Term> {
+        let mut term = self.parse_proj(tcx)?;
+        loop {
+            if self.eat(OpenBracket) {
+                let (ty, ty_span) = self.parse_spanned_ty(tcx)?;
+                self.consume(CloseBracket, "Expected ']' after type argument")?;
+                let span = term.span.to(self.prev.span);
+                term = Term {
+                    kind: TermKind::TyApp {
+                        term: Rc::new(term),
+                        ty,
+                        ty_span,
+                    },
+                    span,
+                };
+            } else if self.at_atom() {
+                let arg = self.parse_proj(tcx)?;
+                let span = term.span.to(arg.span);
+                term = Term {
+                    kind: TermKind::Call {
+                        callee: Rc::new(term),
+                        arg: Rc::new(arg),
+                    },
+                    span,
+                };
+            } else {
+                return Ok(term);
+            }
+        }
+    }
+
+    fn parse_proj(&mut self, tcx: &mut TyContext) -> err::Result<Term> {
+        let mut term = self.parse_atom(tcx)?;
+        while self.eat(Dot) {
+            self.consume(Ident, "Expected a field name after '.'")?;
+            let label = self.prev.symbol;
+            let span = term.span.to(self.prev.span);
+            term = Term {
+                kind: TermKind::Proj {
+                    term: Rc::new(term),
+                    label,
+                },
+                span,
+            };
+        }
+        Ok(term)
+    }
+
+    fn at_atom(&self) -> bool {
+        match self.curr.kind {
+            True | False | Zero | Number | Float | Str | Ident | OpenParen => true,
+            OpenBrace => self.at_record(),
+            _ => false,
+        }
+    }
+
+    /// A `{` starts a record if it is followed by `}` or `label =`. Otherwise
+    /// it is the block of an `if`.
+    fn at_record(&self) -> bool {
+        let mut next = self.tokens.iter().rev().map(|t| t.kind);
+        match next.next() {
+            Some(CloseBrace) => true,
+            Some(Ident) => next.next() == Some(Eq),
+            _ => false,
+        }
+    }
+
+    fn parse_atom(&mut self, tcx: &mut TyContext) -> err::Result<Term> {
+        if self.eat(True) {
+            Ok(Term {
+                kind: TermKind::True,
+                span: self.prev.span,
+            })
+        } else if self.eat(False) {
+            Ok(Term {
+                kind: TermKind::False,
+                span: self.prev.span,
+            })
+        } else if self.eat(Zero) {
+            Ok(Term {
+                kind: TermKind::NatLit(0),
+                span: self.prev.span,
+            })
+        } else if self.eat(Float) {
+            match self.prev.symbol.parse() {
+                Ok(f) => Ok(Term {
+                    kind: TermKind::Float(f),
+                    span: self.prev.span,
+                }),
+                Err(_) => bail!(&self.src, self.prev.span, A0006, "Invalid float literal"),
+            }
+        } else if self.eat(Number) {
+            match self.prev.symbol.parse() {
+                Ok(n) => Ok(Term {
+                    kind: TermKind::NatLit(n),
+                    span: self.prev.span,
+                }),
+                Err(_) => bail!(
+                    &self.src,
+                    self.prev.span,
+                    A0033,
+                    "Nat literal is larger than {}",
+                    u64::MAX
+                ),
+            }
+        } else if self.eat(Str) {
+            Ok(Term {
+                kind: TermKind::Str(self.prev.symbol),
+                span: self.prev.span,
+            })
+        } else if self.eat(Ident) {
+            let name = self.prev.symbol;
+            match self.names.iter().rev().position(|&n| n == name) {
+                Some(idx) => Ok(Term {
+                    kind: TermKind::Var {
+                        idx: idx as u32,
+                        len: self.names.len() as u32,
+                    },
+                    span: self.prev.span,
+                }),
+                None => bail!(
+                    &self.src,
+                    self.prev.span,
+                    A0008,
+                    "Unbound variable: {}",
+                    name
+                ),
             }
+        } else if self.eat(OpenParen) {
+            let lo = self.prev.span;
+            let mut term = self.parse_expr(tcx)?;
+            self.consume(CloseParen, "Expected ')'")?;
+            term.span = lo.to(self.prev.span);
+            Ok(term)
+        } else if self.at_record() && self.eat(OpenBrace) {
+            let lo = self.prev.span;
+            let mut fields = vec![];
+            while !self.eat(CloseBrace) {
+                if !fields.is_empty() {
+                    self.consume(Comma, "Expected ',' between record fields")?;
+                }
+                self.consume(Ident, "Expected a field name")?;
+                let label = self.prev.symbol;
+                self.consume(Eq, "Expected '=' after field name")?;
+                let term = self.parse_expr(tcx)?;
+                fields.push((label, Rc::new(term)));
+            }
+            Ok(Term {
+                kind: TermKind::Record(fields),
+                span: lo.to(self.prev.span),
+            })
         } else {
-            quit!(
+            bail!(
                 &self.src,
                 self.curr.span,
+                A0005,
                 "Unexpected token: {:?}",
                 self.curr.kind
             );
         }
     }
 
-    fn consume(&mut self, kind: TokenKind, msg: &str) {
+    /// Parses the kind and bound of a type parameter, and returns the span
+    /// of the bound, or a dummy span if there is none.
+    fn parse_ty_param(&mut self, tcx: &mut TyContext) -> err::Result<(Kind, TypeId, Span)> {
+        let kind = if self.eat(ColonColon) {
+            self.parse_kind()?
+        } else {
+            Kind::Star
+        };
+        if self.eat(Lt) {
+            self.consume(Colon, "Expected '<:' before the bound of type variable")?;
+            let (bound, span) = self.parse_spanned_ty(tcx)?;
+            Ok((kind, bound, span))
+        } else {
+            Ok((kind, tcx.common.top, Span::dummy()))
+        }
+    }
+
+    /// Parses a type and returns it with its span.
+    fn parse_spanned_ty(&mut self, tcx: &mut TyContext) -> err::Result<(TypeId, Span)> {
+        let lo = self.curr.span;
+        let ty = self.parse_ty(tcx)?;
+        Ok((ty, lo.to(self.prev.span)))
+    }
+
+    fn parse_kind(&mut self) -> err::Result<Kind> {
+        let kind = if self.eat(Star) {
+            Kind::Star
+        } else if self.eat(OpenParen) {
+            let kind = self.parse_kind()?;
+            self.consume(CloseParen, "Expected ')'")?;
+            kind
+        } else {
+            bail!(&self.src, self.curr.span, A0005, "Expected a kind");
+        };
+
+        if self.eat(FatArrow) {
+            Ok(Kind::Arrow(Rc::new(kind), Rc::new(self.parse_kind()?)))
+        } else {
+            Ok(kind)
+        }
+    }
+
+    pub fn parse_ty(&mut self, tcx: &mut TyContext) -> err::Result<TypeId> {
+        if self.eat(Pipe) {
+            let from = self.parse_ty(tcx)?;
+            self.consume(Pipe, "Expected '|' after parameter type")?;
+            let to = self.parse_ty(tcx)?;
+            Ok(tcx.new_arrow(from, to))
+        } else if self.eat(Mu) {
+            let lo = self.prev.span;
+            self.consume(Ident, "Expected a type variable after 'mu'")?;
+            let name = self.prev.symbol;
+            self.consume(Dot, "Expected '.' after recursive type variable")?;
+
+            self.ty_vars.push((name, Kind::Star));
+            let body = self.parse_ty(tcx)?;
+            self.ty_vars.pop();
+
+            // `is_contractive` reduces the body, which only terminates if
+            // it is well-kinded.
+            let span = lo.to(self.prev.span);
+            let ty = tcx.new_rec(name, body);
+            self.check_star(tcx, ty, span)?;
+            if !tcx.is_contractive(body) {
+                bail!(&self.src, span, A0012, "Recursive type is not contractive");
+            }
+            Ok(ty)
+        } else if self.eat(Forall) {
+            self.consume(Ident, "Expected a type variable after 'forall'")?;
+            let name = self.prev.symbol;
+            let (kind, bound, _) = self.parse_ty_param(tcx)?;
+            self.consume(Dot, "Expected '.' after universal type variable")?;
+
+            self.ty_vars.push((name, kind.clone()));
+            let body = self.parse_ty(tcx)?;
+            self.ty_vars.pop();
+
+            Ok(tcx.new_all(name, kind, bound, body))
+        } else if self.eat(Backslash) {
+            self.consume(Ident, "Expected a type variable after '\\'")?;
+            let name = self.prev.symbol;
+            let kind = if self.eat(ColonColon) {
+                self.parse_kind()?
+            } else {
+                Kind::Star
+            };
+            self.consume(Dot, "Expected '.' after type operator parameter")?;
+
+            self.ty_vars.push((name, kind.clone()));
+            let body = self.parse_ty(tcx)?;
+            self.ty_vars.pop();
+
+            Ok(tcx.new_abs(name, kind, body))
+        } else {
+            let mut ty = self.parse_ty_atom(tcx)?;
+            while matches!(self.curr.kind, Ident | OpenParen | OpenBrace) {
+                let arg = self.parse_ty_atom(tcx)?;
+                ty = tcx.new_app(ty, arg);
+            }
+            Ok(ty)
+        }
+    }
+
+    fn parse_ty_atom(&mut self, tcx: &mut TyContext) -> err::Result<TypeId> {
+        if self.eat(OpenBrace) {
+            let mut fields = vec![];
+            while !self.eat(CloseBrace) {
+                if !fields.is_empty() {
+                    self.consume(Comma, "Expected ',' between record fields")?;
+                }
+                self.consume(Ident, "Expected a field name")?;
+                let label = self.prev.symbol;
+                self.consume(Colon, "Expected ':' after field name")?;
+                let ty = self.parse_ty(tcx)?;
+                fields.push((label, ty));
+            }
+            Ok(tcx.new_record(fields))
+        } else if self.eat(OpenParen) {
+            let ty = self.parse_ty(tcx)?;
+            self.consume(CloseParen, "Expected ')'")?;
+            Ok(ty)
+        } else if self.eat(Ident) {
+            let name = self.prev.symbol;
+            if let Some(idx) = self.ty_vars.iter().rev().position(|(n, _)| *n == name) {
+                return Ok(tcx.new_var(idx as u32));
+            }
+            match tcx.lookup_alias(name) {
+                Some(ty) => Ok(ty),
+                None => {
+                    let msg = format!("Unknown type: {}", name);
+                    Err(Diagnostic::new(&self.src, msg, self.prev.span)
+                        .with_code(codes::A0009)
+                        .with_help(format!(
+                            "declare it with `type {} = ...;` or as a base type with `type {};`",
+                            name, name
+                        )))
+                }
+            }
+        } else {
+            bail!(&self.src, self.curr.span, A0005, "Expected a type");
+        }
+    }
+
+    /// The kind of `ty`, written at `span`, in the scope of the type
+    /// variables around it.
+    fn kind_of(&self, tcx: &mut TyContext, ty: TypeId, span: Span) -> err::Result<Kind> {
+        let top = tcx.common.top;
+        let ctx = self
+            .ty_vars
+            .iter()
+            .fold(Context::default(), |ctx, (name, kind)| {
+                let binding = Binding::TyVar {
+                    bound: top,
+                    kind: kind.clone(),
+                };
+                ctx.add_binding(*name, binding)
+            });
+        tcx.kind_of(&ctx, ty)
+            .map_err(|msg| Diagnostic::new(&self.src, msg, span).with_code(codes::A0023))
+    }
+
+    /// Checks that `ty`, written at `span`, is a proper type of kind `*`.
+    fn check_star(&self, tcx: &mut TyContext, ty: TypeId, span: Span) -> err::Result<()> {
+        match self.kind_of(tcx, ty, span)? {
+            Kind::Star => Ok(()),
+            kind => bail!(
+                &self.src,
+                span,
+                A0023,
+                "Kind mismatch: expected: *, actual: {}",
+                kind
+            ),
+        }
+    }
+
+    fn consume(&mut self, kind: TokenKind, msg: &str) -> err::Result<()> {
         if self.eat(kind) {
-            return;
+            return Ok(());
         }
 
-        quit!(&self.src, self.curr.span, msg);
+        bail!(&self.src, self.curr.span, A0005, msg);
     }
 
     fn eat(&mut self, kind: TokenKind) -> bool {
@@ -139,6 +731,8 @@ impl Parser {
     }
 
     fn advance(&mut self) {
-        self.prev = std::mem::replace(&mut self.curr, self.lexer.next_token());
+        if let Some(next) = self.tokens.pop() {
+            self.prev = std::mem::replace(&mut self.curr, next);
+        }
     }
 }
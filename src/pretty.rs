@@ -0,0 +1,369 @@
+//! A pretty printer in the style of Wadler's "A prettier printer". Terms are
+//! turned into a `Doc`, which is laid out to fit a given width. The output
+//! parses back to the same term.
+
+use crate::{
+    lexer::Symbol,
+    source,
+    syntax::{ArithOp, Binding, Context, Kind, Term, TermKind::*, TyContext},
+};
+use std::rc::Rc;
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// A document with alternative layouts: each group is printed on one line
+/// if it fits, and otherwise has its line breaks taken.
+#[derive(Clone)]
+pub struct Doc(Rc<Node>);
+
+enum Node {
+    Nil,
+    Text(String),
+    /// A line break, or the given text when its group is flat.
+    Line(&'static str),
+    /// A line break that also breaks every group around it.
+    HardLine,
+    /// Text that is put off until the end of the line, like a `//` comment.
+    /// Groups that would put more text after it on the line are broken.
+    LineSuffix(String),
+    Cat(Doc, Doc),
+    Nest(usize, Doc),
+    Group(Doc),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+impl Doc {
+    pub fn nil() -> Doc {
+        Doc(Rc::new(Node::Nil))
+    }
+
+    pub fn text(s: impl Into<String>) -> Doc {
+        Doc(Rc::new(Node::Text(s.into())))
+    }
+
+    /// A line break, or a space when flat.
+    pub fn line() -> Doc {
+        Doc(Rc::new(Node::Line(" ")))
+    }
+
+    /// A line break, or nothing when flat.
+    pub fn softline() -> Doc {
+        Doc(Rc::new(Node::Line("")))
+    }
+
+    /// A line break in every layout.
+    pub fn hardline() -> Doc {
+        Doc(Rc::new(Node::HardLine))
+    }
+
+    pub fn line_suffix(s: impl Into<String>) -> Doc {
+        Doc(Rc::new(Node::LineSuffix(s.into())))
+    }
+
+    pub fn append(self, other: Doc) -> Doc {
+        Doc(Rc::new(Node::Cat(self, other)))
+    }
+
+    /// Indents the lines after each line break in `self` by `indent` more.
+    pub fn nest(self, indent: usize) -> Doc {
+        Doc(Rc::new(Node::Nest(indent, self)))
+    }
+
+    pub fn group(self) -> Doc {
+        Doc(Rc::new(Node::Group(self)))
+    }
+
+    pub fn render(&self, width: usize) -> String {
+        let mut buf = String::new();
+        let mut col = 0;
+        let mut suffix = String::new();
+        let mut stack = vec![(0, Mode::Break, self)];
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match &*doc.0 {
+                Node::Nil => {}
+                Node::Text(s) => {
+                    buf.push_str(s);
+                    col += source::display_width(s);
+                }
+                Node::Line(flat) if mode == Mode::Flat => {
+                    buf.push_str(flat);
+                    col += flat.len();
+                }
+                Node::Line(_) | Node::HardLine => {
+                    // No trailing whitespace, even on blank lines.
+                    buf.truncate(buf.trim_end_matches(' ').len());
+                    buf.push_str(&suffix);
+                    suffix.clear();
+                    buf.push('\n');
+                    buf.extend(std::iter::repeat_n(' ', indent));
+                    col = indent;
+                }
+                Node::LineSuffix(s) => suffix.push_str(s),
+                Node::Cat(a, b) => {
+                    stack.push((indent, mode, b));
+                    stack.push((indent, mode, a));
+                }
+                Node::Nest(n, doc) => stack.push((indent + n, mode, doc)),
+                Node::Group(doc) => {
+                    let flat = (indent, Mode::Flat, doc);
+                    let fits =
+                        mode == Mode::Flat || fits(width as isize - col as isize, flat, &stack);
+                    stack.push(if fits {
+                        flat
+                    } else {
+                        (indent, Mode::Break, doc)
+                    });
+                }
+            }
+        }
+        buf.push_str(&suffix);
+        buf
+    }
+}
+
+/// Whether `next` fits in `width` columns along with what follows it on the
+/// same line.
+fn fits(mut width: isize, next: (usize, Mode, &Doc), rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut rest = rest.iter().rev();
+    let mut stack = vec![next];
+    let mut suffix = false;
+    while width >= 0 {
+        let (indent, mode, doc) = match stack.pop().or_else(|| rest.next().copied()) {
+            Some(item) => item,
+            None => return true,
+        };
+        match &*doc.0 {
+            Node::Nil => {}
+            Node::Text(s) if suffix && !s.is_empty() => return false,
+            Node::Text(s) => width -= source::display_width(s) as isize,
+            Node::Line(flat) if mode == Mode::Flat => width -= flat.len() as isize,
+            Node::Line(_) => return true,
+            Node::HardLine => return mode == Mode::Break,
+            Node::LineSuffix(_) => suffix = true,
+            Node::Cat(a, b) => {
+                stack.push((indent, mode, b));
+                stack.push((indent, mode, a));
+            }
+            Node::Nest(n, doc) => stack.push((indent + n, mode, doc)),
+            Node::Group(doc) => stack.push((indent, mode, doc)),
+        }
+    }
+    false
+}
+
+/// How tightly a term binds, from loosest to tightest. A term needs
+/// parentheses where the grammar asks for a tighter one.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Prec {
+    /// `succ`, `pred`, `iszero`, `if` and abstractions.
+    Expr,
+    Sum,
+    Product,
+    Call,
+    Proj,
+    Atom,
+}
+
+impl Prec {
+    fn of(term: &Term) -> Prec {
+        match &term.kind {
+            Succ(_) | Pred(_) | IsZero(_) | If { .. } | Fun { .. } | TyAbs { .. } => Prec::Expr,
+            Arith { op, .. } => Prec::of_op(*op),
+            Float(f) if !f.is_finite() || f.is_sign_negative() => Prec::Sum,
+            Call { .. } | TyApp { .. } => Prec::Call,
+            Proj { .. } => Prec::Proj,
+            True | False | NatLit(_) | Str(_) | Float(_) | Var { .. } | Record(_) => Prec::Atom,
+        }
+    }
+
+    fn of_op(op: ArithOp) -> Prec {
+        match op {
+            ArithOp::Add | ArithOp::Sub => Prec::Sum,
+            ArithOp::Mul | ArithOp::Div => Prec::Product,
+        }
+    }
+
+    fn next(self) -> Prec {
+        match self {
+            Prec::Expr => Prec::Sum,
+            Prec::Sum => Prec::Product,
+            Prec::Product => Prec::Call,
+            Prec::Call => Prec::Proj,
+            Prec::Proj | Prec::Atom => Prec::Atom,
+        }
+    }
+}
+
+/// Prints `term` as source code that fits in `width` columns where possible.
+pub fn pretty(term: &Term, ctx: &Context, tyctx: &TyContext, width: usize) -> String {
+    to_doc(term, ctx, tyctx).render(width)
+}
+
+pub fn to_doc(term: &Term, ctx: &Context, tyctx: &TyContext) -> Doc {
+    match &term.kind {
+        True => Doc::text("true"),
+        False => Doc::text("false"),
+        NatLit(n) => Doc::text(n.to_string()),
+        Str(s) => Doc::text(s.as_str_with(|s| format!("{:?}", s))),
+        Float(f) => float(*f),
+        Arith { op, lhs, rhs } => {
+            let prec = Prec::of_op(*op);
+            at(lhs, prec, ctx, tyctx)
+                .append(Doc::text(format!(" {}", op.as_str())))
+                .append(Doc::line().append(at(rhs, prec.next(), ctx, tyctx)).nest(2))
+                .group()
+        }
+        If {
+            cond,
+            then_branch,
+            else_branch,
+        } => Doc::text("if ")
+            .append(to_doc(cond, ctx, tyctx))
+            .append(Doc::text(" {"))
+            .append(Doc::line().append(to_doc(then_branch, ctx, tyctx)).nest(2))
+            .append(Doc::line())
+            .append(Doc::text("} else {"))
+            .append(Doc::line().append(to_doc(else_branch, ctx, tyctx)).nest(2))
+            .append(Doc::line())
+            .append(Doc::text("}"))
+            .group(),
+        Succ(t) => Doc::text("succ ").append(to_doc(t, ctx, tyctx)),
+        Pred(t) => Doc::text("pred ").append(to_doc(t, ctx, tyctx)),
+        IsZero(t) => Doc::text("iszero ").append(to_doc(t, ctx, tyctx)),
+        Var { idx, len } => {
+            if ctx.len() == *len as usize {
+                Doc::text(
+                    ctx.index_to_name(*idx as usize)
+                        .as_str_with(|s| s.to_owned()),
+                )
+            } else {
+                Doc::text("[bad index]")
+            }
+        }
+        Fun { name, ty, term, .. } => {
+            let name = fresh_name(ctx, *name);
+            let inner = ctx.add_binding(name, Binding::Name);
+            let param = format!("|{}: {}|", name, tyctx.display(ctx, *ty));
+            abs(param, to_doc(term, &inner, tyctx))
+        }
+        TyAbs {
+            name,
+            kind,
+            bound,
+            term,
+            ..
+        } => {
+            let mut param = format!("|{}", name);
+            if *kind != Kind::Star {
+                param.push_str(&format!("::{}", kind));
+            }
+            if *bound != tyctx.common.top {
+                param.push_str(&format!(" <: {}", tyctx.display(ctx, *bound)));
+            }
+            param.push('|');
+            let binding = Binding::TyVar {
+                bound: *bound,
+                kind: kind.clone(),
+            };
+            let inner = ctx.add_binding(*name, binding);
+            abs(param, to_doc(term, &inner, tyctx))
+        }
+        Call { .. } | TyApp { .. } => {
+            // The arguments of a curried call are laid out together.
+            let mut args = vec![];
+            let mut head = term;
+            loop {
+                match &head.kind {
+                    Call { callee, arg } => {
+                        args.push(at(arg, Prec::Proj, ctx, tyctx));
+                        head = callee;
+                    }
+                    TyApp { term, ty, .. } => {
+                        args.push(Doc::text(format!("[{}]", tyctx.display(ctx, *ty))));
+                        head = term;
+                    }
+                    _ => break,
+                }
+            }
+            let args = args
+                .into_iter()
+                .rev()
+                .fold(Doc::nil(), |doc, arg| doc.append(Doc::line()).append(arg));
+            at(head, Prec::Proj, ctx, tyctx)
+                .append(args.nest(2))
+                .group()
+        }
+        Record(fields) if fields.is_empty() => Doc::text("{}"),
+        Record(fields) => {
+            let mut doc = Doc::nil();
+            for (i, (label, t)) in fields.iter().enumerate() {
+                if i > 0 {
+                    doc = doc.append(Doc::text(",")).append(Doc::line());
+                }
+                let field = Doc::text(format!("{} = ", label));
+                doc = doc.append(field.append(to_doc(t, ctx, tyctx)).group());
+            }
+            Doc::text("{")
+                .append(Doc::softline().append(doc).nest(2))
+                .append(Doc::softline())
+                .append(Doc::text("}"))
+                .group()
+        }
+        Proj { term, label } => {
+            at(term, Prec::Proj, ctx, tyctx).append(Doc::text(format!(".{}", label)))
+        }
+    }
+}
+
+/// The document for `term` where the grammar expects `prec`.
+fn at(term: &Term, prec: Prec, ctx: &Context, tyctx: &TyContext) -> Doc {
+    let doc = to_doc(term, ctx, tyctx);
+    if Prec::of(term) < prec {
+        Doc::text("(").append(doc.nest(1)).append(Doc::text(")"))
+    } else {
+        doc
+    }
+}
+
+/// Renames a binder that would shadow a name already in scope. Unlike
+/// `Context::pick_fresh_name`, the new name is a valid identifier.
+fn fresh_name(ctx: &Context, name: Symbol) -> Symbol {
+    if !ctx.is_name_bound(&name) {
+        return name;
+    }
+    let base = name.as_str_with(|s| s.to_owned());
+    (1..)
+        .map(|i| Symbol::intern(&format!("{}{}", base, i)))
+        .find(|name| !ctx.is_name_bound(name))
+        .unwrap()
+}
+
+fn abs(param: String, body: Doc) -> Doc {
+    Doc::text(param)
+        .append(Doc::line().append(body).nest(2))
+        .group()
+}
+
+/// Float literals have no sign, exponent or special values, so those are
+/// written as arithmetic.
+fn float(f: f64) -> Doc {
+    if f.is_nan() {
+        Doc::text("0.0 / 0.0")
+    } else if f.is_infinite() {
+        let sign = if f < 0.0 { "0.0 - " } else { "" };
+        Doc::text(format!("{}1.0 / 0.0", sign))
+    } else if f.is_sign_negative() {
+        Doc::text("0.0 - ").append(float(-f))
+    } else {
+        let mut s = f.to_string();
+        if !s.contains('.') {
+            s.push_str(".0");
+        }
+        Doc::text(s)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    err,
+    lexer::Symbol,
+    module::{self, PRELUDE},
+    parser::{Line, Parser},
+    pretty,
+    source::SourceFile,
+    syntax::{Binding, Context, Eval, SubtypingMode, Term, TyContext, TypeId},
+};
+use std::rc::Rc;
+
+pub const HELP: &str = "\
+Enter a term to evaluate it, or one of:
+    let x = t    Evaluate t and bind the value to x
+    type N = T;  Define a type alias, or a base type with `type N;`
+    :type t      Show the type of t
+    :ast t       Show the syntax tree of t
+    :step t      Show every evaluation step of t
+    :help        Show this message
+    :quit        Exit";
+
+/// A read-eval-print session. Type declarations and `let` definitions
+/// persist from one input to the next, and the definitions of the prelude
+/// are in scope unless it is created `without_prelude`.
+#[derive(Default)]
+pub struct Repl {
+    tyctx: TyContext,
+    /// The `let` definitions so far, outermost first. Values are closed.
+    defs: Vec<(Symbol, Rc<Term>, TypeId)>,
+    inputs: usize,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        let mut repl = Self::default();
+        let src = Rc::new(SourceFile::new("<prelude>", PRELUDE));
+        let prelude = module::load(src, &mut repl.tyctx, false).expect("the prelude parses");
+        let types = prelude
+            .def_types(&mut repl.tyctx)
+            .expect("the prelude type checks");
+        let values = prelude.values(&mut repl.tyctx);
+        for ((def, value), ty) in prelude.defs.iter().zip(values).zip(types) {
+            repl.defs.push((def.name, value, ty));
+        }
+        repl
+    }
+
+    pub fn without_prelude() -> Self {
+        Self::default()
+    }
+
+    /// Selects the rule used to compare universal types.
+    pub fn set_subtyping(&mut self, mode: SubtypingMode) {
+        self.tyctx.subtyping = mode;
+    }
+
+    /// Handles one input and returns what to print. After an error the
+    /// session carries on as if the input had not been entered, except for
+    /// type declarations that came before the error.
+    pub fn eval_line(&mut self, input: &str) -> err::Result<String> {
+        let input = input.trim();
+        let (command, input) = match input.strip_prefix(':') {
+            Some(rest) => {
+                let (command, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+                (Some(command), rest)
+            }
+            None => (None, input),
+        };
+
+        self.inputs += 1;
+        let name = format!("<repl:{}>", self.inputs);
+        let src = Rc::new(SourceFile::new(name, input));
+        let eval = Eval::new(src.clone());
+
+        match command {
+            None | Some("type") | Some("ast") | Some("step") => {}
+            Some("help") => return Ok(HELP.to_owned()),
+            Some(command) => return Ok(format!("Unknown command: :{}\n{}", command, HELP)),
+        }
+
+        let names = self.defs.iter().map(|(name, ..)| *name).collect();
+        let line = Parser::new(src)?
+            .with_names(names)
+            .parse_line(&mut self.tyctx)?;
+        let (name, term) = match line {
+            Line::Empty => return Ok(String::new()),
+            Line::Let(name, term) => (Some(name), Rc::new(term)),
+            Line::Term(term) => (None, Rc::new(term)),
+        };
+
+        let ctx = self.context();
+        if command == Some("ast") {
+            let buf = &mut String::new();
+            eval.dump(&term, &ctx, &self.tyctx, buf);
+            return Ok(buf.trim_end().to_owned());
+        }
+
+        let ty = eval.type_of(&term, &ctx, &mut self.tyctx)?;
+        let ty_str = self.tyctx.display(&Context::default(), ty);
+        if command == Some("type") {
+            return Ok(ty_str);
+        }
+
+        // The definitions are substituted innermost first. Each value is
+        // closed, so it only needs weakening past the definitions before it.
+        let mut term = term;
+        for (i, (_, value, _)) in self.defs.iter().enumerate().rev() {
+            term = eval.subst_top(&term, eval.shift(value, i as i32));
+        }
+
+        let ctx = &mut Context::default();
+        if command == Some("step") {
+            let buf = &mut String::from("   ");
+            eval.print(&term, ctx, buf);
+            for step in eval.trace(&term, ctx, &mut self.tyctx) {
+                buf.push('\n');
+                eval.print_step(&step, ctx, buf);
+            }
+            return Ok(buf.clone());
+        }
+
+        let value = eval.eval(&term, ctx, &mut self.tyctx);
+        if !value.is_val(ctx) {
+            return Err(eval.stuck(&value));
+        }
+        match name {
+            Some(name) => {
+                self.defs.push((name, value, ty));
+                Ok(format!("{} : {}", name, ty_str))
+            }
+            None => {
+                let value = pretty::pretty(&value, ctx, &self.tyctx, pretty::DEFAULT_WIDTH);
+                Ok(format!("{} : {}", value, ty_str))
+            }
+        }
+    }
+
+    /// The typing context of the definitions so far.
+    fn context(&self) -> Context {
+        let mut ctx = Context::default();
+        for &(name, _, ty) in &self.defs {
+            ctx = ctx.add_binding(name, Binding::Variable(ty));
+        }
+        ctx
+    }
+}
@@ -0,0 +1,171 @@
+use std::cell::Cell;
+use std::ops::Range;
+
+/// Identifies a `SourceFile`, so that spans can tell which file they are in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(u32);
+
+impl FileId {
+    /// The file of `Span::dummy`, never given to a `SourceFile`.
+    pub const DUMMY: FileId = FileId(0);
+}
+
+thread_local! {
+    static NEXT_FILE_ID: Cell<u32> = const { Cell::new(1) };
+}
+
+/// A named piece of source text, e.g. a file on disk or a line typed into
+/// the REPL.
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    /// Unique among the files created on this thread.
+    pub id: FileId,
+    map: SourceMap,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let id = NEXT_FILE_ID.with(|next| next.replace(next.get() + 1));
+        Self {
+            name: name.into(),
+            map: SourceMap::new(&text),
+            text,
+            id: FileId(id),
+        }
+    }
+
+    /// Finds the zero-based line and columns of a byte offset. Offsets past
+    /// the end or inside a multi-byte character are moved back to the
+    /// nearest character boundary.
+    pub fn loc(&self, offset: usize) -> Loc {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.map.line_index(offset);
+        let before = &self.text[self.map.line_range(line).start..offset];
+        Loc {
+            line,
+            col: before.chars().count(),
+            display_col: display_width(before),
+            utf16_col: before.encode_utf16().count(),
+        }
+    }
+
+    /// The byte offset of a zero-based line and UTF-16 column, the way LSP
+    /// clients count. Positions past the end of a line or of the file are
+    /// moved back to the end.
+    pub fn offset(&self, line: usize, utf16_col: usize) -> usize {
+        if line >= self.line_count() {
+            return self.text.len();
+        }
+        let range = self.map.line_range(line);
+        let mut col = 0;
+        for (i, c) in self.text[range.clone()].char_indices() {
+            if col >= utf16_col {
+                return range.start + i;
+            }
+            col += c.len_utf16();
+        }
+        range.end
+    }
+
+    /// The text of a zero-based line without its line terminator.
+    pub fn line(&self, line: usize) -> &str {
+        if line < self.line_count() {
+            self.text[self.map.line_range(line)].trim_end_matches('\r')
+        } else {
+            ""
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.map.line_starts.len()
+    }
+}
+
+/// A position in a source file. All fields are zero-based; `col` counts
+/// characters, `display_col` terminal cells and `utf16_col` UTF-16 code
+/// units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loc {
+    pub line: usize,
+    pub col: usize,
+    pub display_col: usize,
+    pub utf16_col: usize,
+}
+
+/// The byte offsets at which each line of a source file starts. There is
+/// always at least one line, even for an empty file.
+struct SourceMap {
+    line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl SourceMap {
+    fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            line_starts,
+            len: text.len(),
+        }
+    }
+
+    fn line_index(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// The byte range of a line, excluding the `\n` ending it.
+    fn line_range(&self, line: usize) -> Range<usize> {
+        let start = self.line_starts[line];
+        let end = match self.line_starts.get(line + 1) {
+            Some(&next) => next - 1,
+            None => self.len,
+        };
+        start..end
+    }
+}
+
+/// Tabs are shown as this many spaces.
+pub const TAB_WIDTH: usize = 4;
+
+/// The number of terminal cells `s` takes up once tabs are expanded.
+pub fn display_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// Replaces tabs with spaces so columns line up with `display_width`.
+pub fn expand_tabs(s: &str) -> String {
+    s.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x09 => TAB_WIDTH,
+        0x00..=0x1f | 0x7f..=0x9f => 0,
+        // Combining marks and zero width spaces and joiners.
+        0x0300..=0x036f | 0x200b..=0x200f | 0xfe00..=0xfe0f => 0,
+        // East Asian wide and fullwidth characters, and emoji.
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
@@ -1,10 +1,12 @@
+use crate::source::{FileId, Loc, SourceFile};
 use std::fmt;
 
+/// A range of byte offsets in the file `file`.
 #[derive(Copy, Clone)]
 pub struct Span {
     pub lo: usize,
     pub hi: usize,
-    pub line: usize,
+    pub file: FileId,
 }
 
 impl Span {
@@ -12,17 +14,28 @@ impl Span {
         Self {
             lo: 0,
             hi: 0,
-            line: 0,
+            file: FileId::DUMMY,
         }
     }
 
+    /// The span covering both `self` and `other`, which must be in the same
+    /// file.
     pub fn to(&self, other: Self) -> Self {
         Self {
             lo: self.lo.min(other.lo),
             hi: self.hi.max(other.hi),
-            line: self.line.min(other.line),
+            file: self.file,
         }
     }
+
+    pub fn start(&self, src: &SourceFile) -> Loc {
+        src.loc(self.lo)
+    }
+
+    /// The location just past the span. Never before `start`.
+    pub fn end(&self, src: &SourceFile) -> Loc {
+        src.loc(self.hi.max(self.lo))
+    }
 }
 
 impl fmt::Debug for Span {
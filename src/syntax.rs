@@ -1,5 +1,18 @@
-use crate::{lexer::Symbol, span::Span};
+mod derivation;
+mod type_map;
+mod walk;
+
+use crate::{
+    err::{self, codes, Diagnostic},
+    lexer::Symbol,
+    source::SourceFile,
+    span::Span,
+};
+pub use derivation::Derivation;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
 use std::rc::Rc;
+pub use type_map::TypeMap;
 use TermKind::*;
 
 #[macro_export]
@@ -19,7 +32,15 @@ pub struct Term {
 pub enum TermKind {
     True,
     False,
-    Zero,
+    /// A Nat, kept as a machine integer rather than a chain of `Succ`s.
+    NatLit(u64),
+    Str(Symbol),
+    Float(f64),
+    Arith {
+        op: ArithOp,
+        lhs: Rc<Term>,
+        rhs: Rc<Term>,
+    },
     If {
         cond: Rc<Term>,
         then_branch: Rc<Term>,
@@ -35,12 +56,133 @@ pub enum TermKind {
     Fun {
         name: Symbol,
         ty: TypeId,
+        /// The span of the parameter's type.
+        ty_span: Span,
         term: Rc<Term>,
     },
     Call {
         callee: Rc<Term>,
         arg: Rc<Term>,
     },
+    TyAbs {
+        name: Symbol,
+        kind: Kind,
+        bound: TypeId,
+        /// The span of the bound, or a dummy span if it was left out.
+        bound_span: Span,
+        term: Rc<Term>,
+    },
+    TyApp {
+        term: Rc<Term>,
+        ty: TypeId,
+        /// The span of the type argument.
+        ty_span: Span,
+    },
+    Record(Vec<(Symbol, Rc<Term>)>),
+    Proj {
+        term: Rc<Term>,
+        label: Symbol,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl ArithOp {
+    pub fn apply(self, lhs: f64, rhs: f64) -> f64 {
+        match self {
+            ArithOp::Add => lhs + rhs,
+            ArithOp::Sub => lhs - rhs,
+            ArithOp::Mul => lhs * rhs,
+            ArithOp::Div => lhs / rhs,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+        }
+    }
+}
+
+/// One step of evaluation, see `Eval::step`.
+pub struct Step {
+    /// The rules used, outermost first. All but the last are congruence
+    /// rules that find the redex, the last one reduces it.
+    pub rules: Vec<Rule>,
+    pub redex: Span,
+    /// The whole term after the step.
+    pub term: Rc<Term>,
+}
+
+/// The evaluation rules, named as in TAPL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rule {
+    IfTrue,
+    IfFalse,
+    If,
+    AppAbs,
+    App1,
+    App2,
+    Succ,
+    SuccNat,
+    PredZero,
+    PredSucc,
+    Pred,
+    IsZeroZero,
+    IsZeroSucc,
+    IsZero,
+    Arith,
+    Arith1,
+    Arith2,
+    TAppTAbs,
+    TApp,
+    Rcd,
+    ProjRcd,
+    Proj,
+}
+
+impl Rule {
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::IfTrue => "E-IfTrue",
+            Rule::IfFalse => "E-IfFalse",
+            Rule::If => "E-If",
+            Rule::AppAbs => "E-AppAbs",
+            Rule::App1 => "E-App1",
+            Rule::App2 => "E-App2",
+            Rule::Succ => "E-Succ",
+            Rule::SuccNat => "E-SuccNat",
+            Rule::PredZero => "E-PredZero",
+            Rule::PredSucc => "E-PredSucc",
+            Rule::Pred => "E-Pred",
+            Rule::IsZeroZero => "E-IszeroZero",
+            Rule::IsZeroSucc => "E-IszeroSucc",
+            Rule::IsZero => "E-Iszero",
+            Rule::Arith => "E-Arith",
+            Rule::Arith1 => "E-Arith1",
+            Rule::Arith2 => "E-Arith2",
+            Rule::TAppTAbs => "E-TappTabs",
+            Rule::TApp => "E-Tapp",
+            Rule::Rcd => "E-Rcd",
+            Rule::ProjRcd => "E-ProjRcd",
+            Rule::Proj => "E-Proj",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 impl Term {
@@ -57,90 +199,526 @@ impl Term {
 
     pub fn is_val(&self, _ctx: &Context) -> bool {
         match &self.kind {
-            Fun { .. } => true,
-            _ => false,
+            True | False | Str(_) | Float(_) | Fun { .. } | TyAbs { .. } => true,
+            Record(fields) => fields.iter().all(|(_, t)| t.is_val(_ctx)),
+            _ => self.is_numeric_val(),
+        }
+    }
+
+    pub fn is_numeric_val(&self) -> bool {
+        matches!(self.kind, NatLit(_))
+    }
+}
+
+/// A term `Eval::eval` has gone into, waiting for the value of a subterm.
+/// Each holds the parts of the term other than that subterm.
+enum Frame {
+    If {
+        span: Span,
+        then_branch: Rc<Term>,
+        else_branch: Rc<Term>,
+    },
+    Callee {
+        span: Span,
+        arg: Rc<Term>,
+    },
+    Arg {
+        span: Span,
+        callee: Rc<Term>,
+    },
+    Succ(Span),
+    Pred(Span),
+    IsZero(Span),
+    Lhs {
+        span: Span,
+        op: ArithOp,
+        rhs: Rc<Term>,
+    },
+    Rhs {
+        span: Span,
+        op: ArithOp,
+        lhs: Rc<Term>,
+    },
+    TyApp {
+        span: Span,
+        ty: TypeId,
+        ty_span: Span,
+    },
+    /// The fields before `next` are values already.
+    Record {
+        span: Span,
+        fields: Vec<(Symbol, Rc<Term>)>,
+        next: usize,
+    },
+    Proj {
+        span: Span,
+        label: Symbol,
+    },
+}
+
+impl Frame {
+    fn span(&self) -> Span {
+        match *self {
+            Frame::If { span, .. }
+            | Frame::Callee { span, .. }
+            | Frame::Arg { span, .. }
+            | Frame::Succ(span)
+            | Frame::Pred(span)
+            | Frame::IsZero(span)
+            | Frame::Lhs { span, .. }
+            | Frame::Rhs { span, .. }
+            | Frame::TyApp { span, .. }
+            | Frame::Record { span, .. }
+            | Frame::Proj { span, .. } => span,
         }
     }
 }
 
 pub struct Eval {
-    src: Rc<String>,
+    src: Rc<SourceFile>,
+    /// Other files the terms may come from, see `with_files`.
+    files: Vec<Rc<SourceFile>>,
 }
 
 impl Eval {
-    pub fn new(src: Rc<String>) -> Self {
-        Self { src }
+    pub fn new(src: Rc<SourceFile>) -> Self {
+        Self { src, files: vec![] }
     }
 
-    pub fn eval(&self, term: &Rc<Term>, ctx: &mut Context) -> Rc<Term> {
-        self.eval_1(term, ctx).unwrap_or_else(|| term.clone())
+    /// Makes errors in terms from `files` point into the right file, for
+    /// programs made of several modules.
+    pub fn with_files(mut self, files: Vec<Rc<SourceFile>>) -> Self {
+        self.files = files;
+        self
     }
 
-    fn eval_1(&self, term: &Rc<Term>, ctx: &mut Context) -> Option<Rc<Term>> {
-        match &term.kind {
+    /// The file `span` is in.
+    fn src(&self, span: Span) -> &Rc<SourceFile> {
+        self.files
+            .iter()
+            .find(|src| src.id == span.file)
+            .unwrap_or(&self.src)
+    }
+
+    /// Evaluates `term` to the normal form repeated `eval_1` would reach, but
+    /// in big steps, keeping the terms that wait for the value of a subterm
+    /// on a stack of frames.
+    pub fn eval(&self, term: &Rc<Term>, _ctx: &mut Context, tyctx: &mut TyContext) -> Rc<Term> {
+        let mut frames = vec![];
+        let mut term = term.clone();
+        loop {
+            let span = term.span;
+            // Go down to the first subterm to evaluate.
+            term = match &term.kind {
+                If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => {
+                    frames.push(Frame::If {
+                        span,
+                        then_branch: then_branch.clone(),
+                        else_branch: else_branch.clone(),
+                    });
+                    cond.clone()
+                }
+                Call { callee, arg } => {
+                    frames.push(Frame::Callee {
+                        span,
+                        arg: arg.clone(),
+                    });
+                    callee.clone()
+                }
+                Succ(t) => {
+                    frames.push(Frame::Succ(span));
+                    t.clone()
+                }
+                Pred(t) => {
+                    frames.push(Frame::Pred(span));
+                    t.clone()
+                }
+                IsZero(t) => {
+                    frames.push(Frame::IsZero(span));
+                    t.clone()
+                }
+                Arith { op, lhs, rhs } => {
+                    frames.push(Frame::Lhs {
+                        span,
+                        op: *op,
+                        rhs: rhs.clone(),
+                    });
+                    lhs.clone()
+                }
+                TyApp {
+                    term: t,
+                    ty,
+                    ty_span,
+                } => {
+                    frames.push(Frame::TyApp {
+                        span,
+                        ty: *ty,
+                        ty_span: *ty_span,
+                    });
+                    t.clone()
+                }
+                Record(fields) if !fields.is_empty() => {
+                    frames.push(Frame::Record {
+                        span,
+                        fields: fields.clone(),
+                        next: 0,
+                    });
+                    fields[0].1.clone()
+                }
+                Proj { term: t, label } => {
+                    frames.push(Frame::Proj {
+                        span,
+                        label: *label,
+                    });
+                    t.clone()
+                }
+                _ => {
+                    // A value, or a variable, which is stuck. Go back up
+                    // until a frame has another term to evaluate.
+                    let mut value = term.clone();
+                    let mut stuck = matches!(value.kind, Var { .. });
+                    loop {
+                        let frame = match frames.pop() {
+                            Some(frame) => frame,
+                            None => return value,
+                        };
+                        let span = frame.span();
+                        let kind = match frame {
+                            Frame::If {
+                                then_branch,
+                                else_branch,
+                                ..
+                            } if !stuck && matches!(value.kind, True | False) => {
+                                break if let True = value.kind {
+                                    then_branch
+                                } else {
+                                    else_branch
+                                };
+                            }
+                            Frame::If {
+                                then_branch,
+                                else_branch,
+                                ..
+                            } => If {
+                                cond: value,
+                                then_branch,
+                                else_branch,
+                            },
+                            Frame::Callee { span, arg } if !stuck => {
+                                frames.push(Frame::Arg {
+                                    span,
+                                    callee: value,
+                                });
+                                break arg;
+                            }
+                            Frame::Callee { arg, .. } => Call { callee: value, arg },
+                            Frame::Arg { callee, .. } => match &callee.kind {
+                                Fun { term: body, .. } if !stuck => {
+                                    break self.subst_top(body, value);
+                                }
+                                _ => Call { callee, arg: value },
+                            },
+                            Frame::Succ(_) => match value.kind {
+                                NatLit(n) if !stuck && n < u64::MAX => NatLit(n + 1),
+                                _ => Succ(value),
+                            },
+                            Frame::Pred(_) => match value.kind {
+                                NatLit(n) if !stuck => NatLit(n.saturating_sub(1)),
+                                _ => Pred(value),
+                            },
+                            Frame::IsZero(_) => match value.kind {
+                                NatLit(0) if !stuck => True,
+                                NatLit(_) if !stuck => False,
+                                _ => IsZero(value),
+                            },
+                            Frame::Lhs { span, op, rhs } if !stuck => {
+                                frames.push(Frame::Rhs {
+                                    span,
+                                    op,
+                                    lhs: value,
+                                });
+                                break rhs;
+                            }
+                            Frame::Lhs { op, rhs, .. } => Arith {
+                                op,
+                                lhs: value,
+                                rhs,
+                            },
+                            Frame::Rhs { op, lhs, .. } => match (&lhs.kind, &value.kind) {
+                                (Float(l), Float(r)) if !stuck => Float(op.apply(*l, *r)),
+                                _ => Arith {
+                                    op,
+                                    lhs,
+                                    rhs: value,
+                                },
+                            },
+                            Frame::TyApp { ty, ty_span, .. } => match &value.kind {
+                                TyAbs { term: body, .. } if !stuck => {
+                                    break self.ty_subst_top(body, ty, tyctx);
+                                }
+                                _ => TyApp {
+                                    term: value,
+                                    ty,
+                                    ty_span,
+                                },
+                            },
+                            Frame::Record {
+                                span,
+                                mut fields,
+                                next,
+                            } => {
+                                fields[next].1 = value;
+                                if !stuck && next + 1 < fields.len() {
+                                    let field = fields[next + 1].1.clone();
+                                    frames.push(Frame::Record {
+                                        span,
+                                        fields,
+                                        next: next + 1,
+                                    });
+                                    break field;
+                                }
+                                Record(fields)
+                            }
+                            Frame::Proj { label, .. } => match &value.kind {
+                                Record(fields) if !stuck => {
+                                    match fields.iter().find(|(l, _)| *l == label) {
+                                        Some((_, field)) => {
+                                            value = field.clone();
+                                            continue;
+                                        }
+                                        None => Proj { term: value, label },
+                                    }
+                                }
+                                _ => Proj { term: value, label },
+                            },
+                        };
+                        // Whether the frame's term is a value, or stuck like
+                        // the subterm was or because no rule applies.
+                        stuck = stuck
+                            || !matches!(kind, True | False | NatLit(_) | Float(_) | Record(_));
+                        value = Rc::new(Term { kind, span });
+                    }
+                }
+            };
+        }
+    }
+
+    /// The error for a term that evaluation got stuck on: A0031 if a Nat
+    /// overflowed, A0024 otherwise. Type checking rules out every other way
+    /// to get stuck, so A0024 on a program that type checks is a bug in the
+    /// evaluator, not in the program.
+    pub fn stuck(&self, term: &Rc<Term>) -> Diagnostic {
+        // Follow the evaluation order down to the term that cannot step.
+        let ctx = &Context::default();
+        let mut term = term;
+        loop {
+            term = match &term.kind {
+                Succ(t) if matches!(t.kind, NatLit(u64::MAX)) => return self.overflow(term.span),
+                Succ(t) | Pred(t) | IsZero(t) => t,
+                If { cond, .. } => cond,
+                Call { callee, .. } if !callee.is_val(ctx) => callee,
+                Call { arg, .. } => arg,
+                Arith { lhs, .. } if !lhs.is_val(ctx) => lhs,
+                Arith { rhs, .. } => rhs,
+                TyApp { term, .. } | Proj { term, .. } => term,
+                Record(fields) => match fields.iter().find(|(_, t)| !t.is_val(ctx)) {
+                    Some((_, t)) => t,
+                    None => break,
+                },
+                _ => break,
+            };
+        }
+        let msg = "Evaluation got stuck".to_owned();
+        Diagnostic::new(self.src(term.span), msg, term.span).with_code(codes::A0024)
+    }
+
+    /// The error for taking the successor of the largest Nat, at `span`.
+    pub fn overflow(&self, span: Span) -> Diagnostic {
+        let msg = "Nat overflow".to_owned();
+        Diagnostic::new(self.src(span), msg, span)
+            .with_code(codes::A0031)
+            .with_primary_label(format!("the successor of {} is too large", u64::MAX))
+    }
+
+    /// Takes a single evaluation step, or returns `None` if `term` is a
+    /// value or stuck.
+    pub fn eval_1(
+        &self,
+        term: &Rc<Term>,
+        ctx: &mut Context,
+        tyctx: &mut TyContext,
+    ) -> Option<Rc<Term>> {
+        self.step_in(term, ctx, tyctx, &mut vec![])
+            .map(|(term, _)| term)
+    }
+
+    /// Like `eval_1`, but also tells which rules were used and where the
+    /// redex was.
+    pub fn step(&self, term: &Rc<Term>, ctx: &mut Context, tyctx: &mut TyContext) -> Option<Step> {
+        let mut rules = vec![];
+        let (term, redex) = self.step_in(term, ctx, tyctx, &mut rules)?;
+        Some(Step { rules, redex, term })
+    }
+
+    /// Evaluates `term` to a normal form and returns every step taken.
+    pub fn trace(&self, term: &Rc<Term>, ctx: &mut Context, tyctx: &mut TyContext) -> Vec<Step> {
+        let mut steps: Vec<Step> = vec![];
+        let mut term = term.clone();
+        while let Some(step) = self.step(&term, ctx, tyctx) {
+            term = step.term.clone();
+            steps.push(step);
+        }
+        steps
+    }
+
+    /// Takes a step and pushes the rules used onto `rules`, outermost
+    /// first. Returns the new term and the span of the redex.
+    fn step_in(
+        &self,
+        term: &Rc<Term>,
+        ctx: &mut Context,
+        tyctx: &mut TyContext,
+        rules: &mut Vec<Rule>,
+    ) -> Option<(Rc<Term>, Span)> {
+        let mut redex = term.span;
+        let mut congruence =
+            |rule: Rule, t: &Rc<Term>, ctx: &mut Context, tyctx: &mut TyContext| {
+                rules.push(rule);
+                let (t, span) = self.step_in(t, ctx, tyctx, rules)?;
+                redex = span;
+                Some(t)
+            };
+        let (rule, kind) = match &term.kind {
             If {
                 cond,
                 then_branch,
                 else_branch,
-            } => {
-                let cond = self.eval(cond, ctx);
-                match &cond.kind {
-                    True => Some(self.eval(then_branch, ctx)),
-                    False => Some(self.eval(else_branch, ctx)),
-                    _ => None,
-                }
-            }
+            } => match &cond.kind {
+                True => return self.fired(rules, Rule::IfTrue, then_branch.clone(), term.span),
+                False => return self.fired(rules, Rule::IfFalse, else_branch.clone(), term.span),
+                _ => (
+                    None,
+                    If {
+                        cond: congruence(Rule::If, cond, ctx, tyctx)?,
+                        then_branch: then_branch.clone(),
+                        else_branch: else_branch.clone(),
+                    },
+                ),
+            },
             Call { callee, arg } => match &callee.kind {
-                Fun { term, .. } if arg.is_val(ctx) => Some(self.subst_top(arg, term.clone())),
-                _ if callee.is_val(ctx) => {
-                    let arg = self.eval(arg, ctx);
-                    Some(Rc::new(Term {
-                        kind: Call {
-                            callee: callee.clone(),
-                            arg,
-                        },
-                        span: term.span,
-                    }))
+                Fun { term: body, .. } if arg.is_val(ctx) => {
+                    let t = self.subst_top(body, arg.clone());
+                    return self.fired(rules, Rule::AppAbs, t, term.span);
                 }
-                _ => {
-                    let callee = self.eval(callee, ctx);
-                    Some(Rc::new(Term {
-                        kind: Call {
-                            callee,
-                            arg: arg.clone(),
-                        },
-                        span: term.span,
-                    }))
+                _ if callee.is_val(ctx) => (
+                    None,
+                    Call {
+                        callee: callee.clone(),
+                        arg: congruence(Rule::App2, arg, ctx, tyctx)?,
+                    },
+                ),
+                _ => (
+                    None,
+                    Call {
+                        callee: congruence(Rule::App1, callee, ctx, tyctx)?,
+                        arg: arg.clone(),
+                    },
+                ),
+            },
+            Succ(t) => match &t.kind {
+                // The successor of the largest Nat overflows, which leaves
+                // the term stuck. See `Eval::stuck`.
+                NatLit(n) => (Some(Rule::SuccNat), NatLit(n.checked_add(1)?)),
+                _ => (None, Succ(congruence(Rule::Succ, t, ctx, tyctx)?)),
+            },
+            Pred(t) => match &t.kind {
+                NatLit(0) => (Some(Rule::PredZero), NatLit(0)),
+                NatLit(n) => (Some(Rule::PredSucc), NatLit(n - 1)),
+                _ => (None, Pred(congruence(Rule::Pred, t, ctx, tyctx)?)),
+            },
+            IsZero(t) => match &t.kind {
+                NatLit(0) => (Some(Rule::IsZeroZero), True),
+                NatLit(_) => (Some(Rule::IsZeroSucc), False),
+                _ => (None, IsZero(congruence(Rule::IsZero, t, ctx, tyctx)?)),
+            },
+            Arith { op, lhs, rhs } => match (&lhs.kind, &rhs.kind) {
+                (Float(l), Float(r)) => (Some(Rule::Arith), Float(op.apply(*l, *r))),
+                _ if lhs.is_val(ctx) => (
+                    None,
+                    Arith {
+                        op: *op,
+                        lhs: lhs.clone(),
+                        rhs: congruence(Rule::Arith2, rhs, ctx, tyctx)?,
+                    },
+                ),
+                _ => (
+                    None,
+                    Arith {
+                        op: *op,
+                        lhs: congruence(Rule::Arith1, lhs, ctx, tyctx)?,
+                        rhs: rhs.clone(),
+                    },
+                ),
+            },
+            TyApp {
+                term: t,
+                ty,
+                ty_span,
+            } => match &t.kind {
+                TyAbs { term: body, .. } => {
+                    let t = self.ty_subst_top(body, *ty, tyctx);
+                    return self.fired(rules, Rule::TAppTAbs, t, term.span);
                 }
+                _ => (
+                    None,
+                    TyApp {
+                        term: congruence(Rule::TApp, t, ctx, tyctx)?,
+                        ty: *ty,
+                        ty_span: *ty_span,
+                    },
+                ),
             },
-            Succ(t) => Some(Rc::new(Term {
-                kind: Succ(self.eval(t, ctx)),
-                span: term.span,
-            })),
-            Pred(t) => {
-                let t = self.eval(t, ctx);
-                match &t.kind {
-                    Zero => Some(Rc::new(Term {
-                        kind: Zero,
-                        span: term.span,
-                    })),
-                    Succ(t) => Some(t.clone()),
-                    _ => return None,
-                }
-            }
-            IsZero(t) => {
-                let t = self.eval(t, ctx);
-                let kind = match &t.kind {
-                    Zero => True,
-                    _ => False,
-                };
-                Some(Rc::new(Term {
-                    kind,
-                    span: term.span,
-                }))
+            Record(fields) => {
+                let i = fields.iter().position(|(_, t)| !t.is_val(ctx))?;
+                let mut fields = fields.clone();
+                fields[i].1 = congruence(Rule::Rcd, &fields[i].1, ctx, tyctx)?;
+                (None, Record(fields))
             }
-            _ => None,
+            Proj { term: t, label } => match &t.kind {
+                Record(fields) if t.is_val(ctx) => {
+                    let (_, field) = fields.iter().find(|(l, _)| l == label)?;
+                    return self.fired(rules, Rule::ProjRcd, field.clone(), term.span);
+                }
+                _ => (
+                    None,
+                    Proj {
+                        term: congruence(Rule::Proj, t, ctx, tyctx)?,
+                        label: *label,
+                    },
+                ),
+            },
+            _ => return None,
+        };
+        if let Some(rule) = rule {
+            rules.push(rule);
         }
+        let span = term.span;
+        Some((Rc::new(Term { kind, span }), redex))
+    }
+
+    fn fired(
+        &self,
+        rules: &mut Vec<Rule>,
+        rule: Rule,
+        term: Rc<Term>,
+        redex: Span,
+    ) -> Option<(Rc<Term>, Span)> {
+        rules.push(rule);
+        Some((term, redex))
     }
 
     pub fn subst_top(&self, term: &Rc<Term>, subst_term: Rc<Term>) -> Rc<Term> {
@@ -180,199 +758,751 @@ impl Eval {
         self.shift_above(term, 0, dist)
     }
 
+    /// Substitutes `ty` for the type variable bound by the innermost type
+    /// abstraction around `term`.
+    pub fn ty_subst_top(&self, term: &Rc<Term>, ty: TypeId, tyctx: &mut TyContext) -> Rc<Term> {
+        let ty = tyctx.shift(ty, 1);
+        let term = self.map_ty(term, 0, tyctx, &|tyctx, ctx, id| {
+            let ty = tyctx.shift(ty, ctx as i32);
+            tyctx.subst(id, ctx, ty)
+        });
+        self.map_ty(&term, 0, tyctx, &|tyctx, ctx, id| {
+            tyctx.shift_above(id, ctx, -1)
+        })
+    }
+
     fn map<F>(&self, term: &Rc<Term>, ctx: u32, map_fn: &F) -> Rc<Term>
     where
         F: Fn(Span, u32, u32, u32) -> Rc<Term>,
     {
-        fn walk<F>(term: &Rc<Term>, ctx: u32, map_fn: &F) -> Rc<Term>
-        where
-            F: Fn(Span, u32, u32, u32) -> Rc<Term>,
-        {
-            let kind = match &term.kind {
-                True | False | Zero => return term.clone(),
-                If {
-                    cond,
-                    then_branch,
-                    else_branch,
-                } => If {
-                    cond: walk(cond, ctx, map_fn),
-                    then_branch: walk(then_branch, ctx, map_fn),
-                    else_branch: walk(else_branch, ctx, map_fn),
-                },
-                Succ(t) => Succ(walk(t, ctx, map_fn)),
-                Pred(t) => match &t.kind {
-                    Zero => Zero,
-                    Succ(t1) => return t1.clone(),
-                    _ => Pred(walk(t, ctx, map_fn)),
-                },
-                IsZero(t) => match &t.kind {
-                    Zero => True,
-                    Succ(_) => False,
-                    _ => IsZero(walk(t, ctx, map_fn)),
-                },
-                Var { idx, len } => return map_fn(term.span, ctx, *idx, *len),
-                Fun { name, ty, term } => Fun {
-                    name: name.clone(),
-                    ty: ty.clone(),
-                    term: walk(term, ctx + 1, map_fn),
-                },
-                Call { callee, arg } => Call {
-                    callee: walk(callee, ctx, map_fn),
-                    arg: walk(arg, ctx, map_fn),
-                },
-            };
+        walk::rebuild(
+            term,
+            |term, depth| match &term.kind {
+                True | False | NatLit(_) | Str(_) | Float(_) => Some(term.clone()),
+                Var { idx, len } => Some(map_fn(term.span, ctx + depth.funs, *idx, *len)),
+                _ => None,
+            },
+            |kind, _| kind,
+        )
+    }
 
-            Rc::new(Term {
-                kind,
-                span: term.span,
-            })
-        }
+    /// Rewrites every type annotation in `term`. `map_fn` receives the number
+    /// of type abstractions enclosing the annotation.
+    fn map_ty<F>(&self, term: &Rc<Term>, ctx: u32, tyctx: &mut TyContext, map_fn: &F) -> Rc<Term>
+    where
+        F: Fn(&mut TyContext, u32, TypeId) -> TypeId,
+    {
+        walk::rebuild(
+            term,
+            |term, _| match term.kind {
+                True | False | NatLit(_) | Str(_) | Float(_) | Var { .. } => Some(term.clone()),
+                _ => None,
+            },
+            |kind, depth| {
+                let ctx = ctx + depth.ty_abs;
+                match kind {
+                    Fun {
+                        name,
+                        ty,
+                        ty_span,
+                        term,
+                    } => Fun {
+                        name,
+                        ty: map_fn(tyctx, ctx, ty),
+                        ty_span,
+                        term,
+                    },
+                    TyAbs {
+                        name,
+                        kind,
+                        bound,
+                        bound_span,
+                        term,
+                    } => TyAbs {
+                        name,
+                        kind,
+                        bound: map_fn(tyctx, ctx, bound),
+                        bound_span,
+                        term,
+                    },
+                    TyApp { term, ty, ty_span } => TyApp {
+                        term,
+                        ty: map_fn(tyctx, ctx, ty),
+                        ty_span,
+                    },
+                    kind => kind,
+                }
+            },
+        )
+    }
 
-        walk(term, ctx, map_fn)
+    /// Writes the syntax tree of `term`, one node per line, indented by
+    /// depth and followed by its span.
+    pub fn dump(&self, term: &Term, ctx: &Context, tyctx: &TyContext, buf: &mut String) {
+        self.dump_at(term, ctx, tyctx, 0, buf)
     }
 
-    pub fn print(&self, term: &Term, ctx: &mut Context, buf: &mut String) {
+    fn dump_at(
+        &self,
+        term: &Term,
+        ctx: &Context,
+        tyctx: &TyContext,
+        depth: usize,
+        buf: &mut String,
+    ) {
+        let indent = "  ".repeat(depth);
+        let line = |buf: &mut String, node: fmt::Arguments<'_>| {
+            writeln!(buf, "{}{} {:?}", indent, node, term.span).unwrap();
+        };
+        let depth = depth + 1;
         match &term.kind {
-            True => buf.push_str("true"),
-            False => buf.push_str("false"),
-            Zero => buf.push('0'),
+            True => line(buf, format_args!("True")),
+            False => line(buf, format_args!("False")),
+            NatLit(n) => line(buf, format_args!("NatLit {}", n)),
+            Str(s) => s.as_str_with(|s| line(buf, format_args!("Str {:?}", s))),
+            Float(f) => line(buf, format_args!("Float {:?}", f)),
+            Arith { op, lhs, rhs } => {
+                line(buf, format_args!("Arith {:?}", op));
+                self.dump_at(lhs, ctx, tyctx, depth, buf);
+                self.dump_at(rhs, ctx, tyctx, depth, buf);
+            }
             If {
                 cond,
                 then_branch,
                 else_branch,
             } => {
-                buf.push_str("if ");
-                self.print(cond, ctx, buf);
-                buf.push_str(" { ");
-                self.print(then_branch, ctx, buf);
-                buf.push_str(" } else { ");
-                self.print(else_branch, ctx, buf);
-                buf.push_str(" }");
-            }
-            Succ(t) => {
-                buf.push_str("succ ");
-                self.print(t, ctx, buf);
-            }
-            Pred(t) => {
-                buf.push_str("pred ");
-                self.print(t, ctx, buf);
-            }
-            IsZero(t) => {
-                buf.push_str("iszero ");
-                self.print(t, ctx, buf);
-            }
-            Fun { name, term, .. } => {
-                let x1 = ctx.pick_fresh_name(*name);
-                buf.push_str("(|");
-                x1.as_str_with(|s| buf.push_str(s));
-                buf.push_str("| ");
-                self.print(term, ctx, buf);
-                buf.push(')');
+                line(buf, format_args!("If"));
+                self.dump_at(cond, ctx, tyctx, depth, buf);
+                self.dump_at(then_branch, ctx, tyctx, depth, buf);
+                self.dump_at(else_branch, ctx, tyctx, depth, buf);
             }
-            Call { callee, arg } => {
-                buf.push('(');
-                self.print(callee, ctx, buf);
-                buf.push(' ');
-                self.print(arg, ctx, buf);
-                buf.push(')');
+            Succ(t) | Pred(t) | IsZero(t) => {
+                let node = match term.kind {
+                    Succ(_) => "Succ",
+                    Pred(_) => "Pred",
+                    _ => "IsZero",
+                };
+                line(buf, format_args!("{}", node));
+                self.dump_at(t, ctx, tyctx, depth, buf);
             }
             Var { idx, len } => {
                 if ctx.len() == *len as usize {
                     let name = ctx.index_to_name(*idx as usize);
-                    name.as_str_with(|s| buf.push_str(s));
+                    line(buf, format_args!("Var {} #{}", name, idx));
                 } else {
-                    buf.push_str("[bad index]");
+                    line(buf, format_args!("Var [bad index] #{}", idx));
+                }
+            }
+            Fun {
+                name, ty, term: t, ..
+            } => {
+                line(
+                    buf,
+                    format_args!("Fun {}: {}", name, tyctx.display(ctx, *ty)),
+                );
+                let ctx = ctx.add_binding(*name, Binding::Variable(*ty));
+                self.dump_at(t, &ctx, tyctx, depth, buf);
+            }
+            Call { callee, arg } => {
+                line(buf, format_args!("Call"));
+                self.dump_at(callee, ctx, tyctx, depth, buf);
+                self.dump_at(arg, ctx, tyctx, depth, buf);
+            }
+            TyAbs {
+                name,
+                kind,
+                bound,
+                term: t,
+                ..
+            } => {
+                let bound_str = tyctx.display(ctx, *bound);
+                line(
+                    buf,
+                    format_args!("TyAbs {}::{} <: {}", name, kind, bound_str),
+                );
+                let binding = Binding::TyVar {
+                    bound: *bound,
+                    kind: kind.clone(),
+                };
+                let ctx = ctx.add_binding(*name, binding);
+                self.dump_at(t, &ctx, tyctx, depth, buf);
+            }
+            TyApp { term: t, ty, .. } => {
+                line(buf, format_args!("TyApp [{}]", tyctx.display(ctx, *ty)));
+                self.dump_at(t, ctx, tyctx, depth, buf);
+            }
+            Record(fields) => {
+                line(buf, format_args!("Record"));
+                for (label, t) in fields {
+                    writeln!(buf, "{}  {} =", indent, label).unwrap();
+                    self.dump_at(t, ctx, tyctx, depth + 1, buf);
                 }
             }
+            Proj { term: t, label } => {
+                line(buf, format_args!("Proj .{}", label));
+                self.dump_at(t, ctx, tyctx, depth, buf);
+            }
         }
     }
 
-    pub fn type_of(&self, term: &Term, ctx: &Context, tyctx: &mut TyContext) -> TypeId {
+    /// Prints the term after a step, followed by the rules used.
+    pub fn print_step(&self, step: &Step, ctx: &mut Context, buf: &mut String) {
+        buf.push_str("-> ");
+        self.print(&step.term, ctx, buf);
+        buf.push_str("  [");
+        for (i, rule) in step.rules.iter().enumerate() {
+            if i > 0 {
+                buf.push_str(", ");
+            }
+            buf.push_str(rule.name());
+        }
+        buf.push(']');
+    }
+
+    /// Prints `term` on one line.
+    pub fn print(&self, term: &Term, ctx: &mut Context, buf: &mut String) {
+        enum Piece<'a> {
+            Term(&'a Term),
+            Text(&'static str),
+            Name(Symbol),
+            /// Leaves the scope of a function.
+            Unbind,
+        }
+        use Piece::{Name, Text, Unbind};
+
+        let mut work = vec![Piece::Term(term)];
+        while let Some(piece) = work.pop() {
+            let term = match piece {
+                Piece::Term(term) => term,
+                Text(s) => {
+                    buf.push_str(s);
+                    continue;
+                }
+                Name(name) => {
+                    name.as_str_with(|s| buf.push_str(s));
+                    continue;
+                }
+                Unbind => {
+                    ctx.list.pop();
+                    continue;
+                }
+            };
+            let pieces = match &term.kind {
+                True => vec![Text("true")],
+                False => vec![Text("false")],
+                NatLit(n) => {
+                    write!(buf, "{}", n).unwrap();
+                    continue;
+                }
+                Str(s) => {
+                    s.as_str_with(|s| buf.push_str(&format!("{:?}", s)));
+                    continue;
+                }
+                Float(f) => {
+                    buf.push_str(&format!("{:?}", f));
+                    continue;
+                }
+                Arith { op, lhs, rhs } => vec![
+                    Text("("),
+                    Piece::Term(lhs),
+                    Text(" "),
+                    Text(op.as_str()),
+                    Text(" "),
+                    Piece::Term(rhs),
+                    Text(")"),
+                ],
+                If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => vec![
+                    Text("if "),
+                    Piece::Term(cond),
+                    Text(" { "),
+                    Piece::Term(then_branch),
+                    Text(" } else { "),
+                    Piece::Term(else_branch),
+                    Text(" }"),
+                ],
+                Succ(t) => vec![Text("succ "), Piece::Term(t)],
+                Pred(t) => vec![Text("pred "), Piece::Term(t)],
+                IsZero(t) => vec![Text("iszero "), Piece::Term(t)],
+                Fun { name, term, .. } => {
+                    let x1 = ctx.pick_fresh_name(*name);
+                    vec![
+                        Text("(|"),
+                        Name(x1),
+                        Text("| "),
+                        Piece::Term(term),
+                        Text(")"),
+                        Unbind,
+                    ]
+                }
+                Call { callee, arg } => vec![
+                    Text("("),
+                    Piece::Term(callee),
+                    Text(" "),
+                    Piece::Term(arg),
+                    Text(")"),
+                ],
+                Var { idx, len } => {
+                    if ctx.len() == *len as usize {
+                        vec![Name(ctx.index_to_name(*idx as usize))]
+                    } else {
+                        vec![Text("[bad index]")]
+                    }
+                }
+                TyAbs { name, term, .. } => vec![
+                    Text("(|"),
+                    Name(*name),
+                    Text("| "),
+                    Piece::Term(term),
+                    Text(")"),
+                ],
+                TyApp { term, .. } => vec![Text("("), Piece::Term(term), Text(" [_])")],
+                Record(fields) => {
+                    let mut pieces = vec![Text("{")];
+                    for (i, (label, t)) in fields.iter().enumerate() {
+                        if i > 0 {
+                            pieces.push(Text(", "));
+                        }
+                        pieces.extend([Name(*label), Text(" = "), Piece::Term(t)]);
+                    }
+                    pieces.push(Text("}"));
+                    pieces
+                }
+                Proj { term, label } => vec![Piece::Term(term), Text("."), Name(*label)],
+            };
+            work.extend(pieces.into_iter().rev());
+        }
+    }
+
+    pub fn type_of(
+        &self,
+        term: &Term,
+        ctx: &Context,
+        tyctx: &mut TyContext,
+    ) -> err::Result<TypeId> {
+        self.check(term, ctx, tyctx, None, None)
+    }
+
+    /// Type checks `term` like `type_of`, recording the type of each
+    /// subterm in `types`. The subterms checked before an error are kept.
+    pub fn type_map(
+        &self,
+        term: &Term,
+        ctx: &Context,
+        tyctx: &mut TyContext,
+        types: &mut TypeMap,
+    ) -> err::Result<TypeId> {
+        self.check(term, ctx, tyctx, None, Some(types))
+    }
+
+    /// Type checks `term` like `type_of` and returns the derivation of its
+    /// type.
+    pub fn derive(
+        &self,
+        term: &Term,
+        ctx: &Context,
+        tyctx: &mut TyContext,
+    ) -> err::Result<Derivation> {
+        let mut out = vec![];
+        self.check(term, ctx, tyctx, Some(&mut out), None)?;
+        Ok(out.pop().unwrap())
+    }
+
+    /// Computes the type of `term`. If `out` is given, the derivation of
+    /// the type is pushed onto it, and if `types` is, the type is recorded
+    /// there.
+    fn check(
+        &self,
+        term: &Term,
+        ctx: &Context,
+        tyctx: &mut TyContext,
+        out: Option<&mut Vec<Derivation>>,
+        mut types: Option<&mut TypeMap>,
+    ) -> err::Result<TypeId> {
+        enum Work<'a> {
+            /// Checks what comes before the subterms, and queues them.
+            Enter(&'a Term),
+            /// Checks the type of the `i`th subterm, when the ones after it
+            /// are only checked if it is right.
+            Checked(&'a Term, usize),
+            /// Computes the type from the types of the subterms.
+            Exit(&'a Term),
+        }
+
+        // Functions and type abstractions are entered and left on this one
+        // context.
+        let mut ctx = ctx.clone();
+        // The types of the subterms checked so far, and their derivations.
+        let mut tys = vec![];
+        let mut derivations = vec![];
+        let mut work = vec![Work::Enter(term)];
+        while let Some(item) = work.pop() {
+            let term = match item {
+                Work::Enter(term) => {
+                    match &term.kind {
+                        Fun {
+                            name, ty, ty_span, ..
+                        } => {
+                            self.check_kind(&ctx, tyctx, *ty, &Kind::Star, *ty_span)?;
+                            ctx.list.push((*name, Binding::Variable(*ty)));
+                        }
+                        TyAbs {
+                            name,
+                            kind,
+                            bound,
+                            bound_span,
+                            ..
+                        } => {
+                            if *bound != tyctx.common.top {
+                                self.check_kind(&ctx, tyctx, *bound, kind, *bound_span)?;
+                            }
+                            let binding = Binding::TyVar {
+                                bound: *bound,
+                                kind: kind.clone(),
+                            };
+                            ctx.list.push((*name, binding));
+                        }
+                        _ => {}
+                    }
+                    work.push(Work::Exit(term));
+                    for (i, t) in term.kind.children().into_iter().enumerate().rev() {
+                        work.push(Work::Checked(term, i));
+                        work.push(Work::Enter(t));
+                    }
+                    continue;
+                }
+                Work::Checked(term, i) => {
+                    self.check_operand(term, i, *tys.last().unwrap(), &ctx, tyctx)?;
+                    continue;
+                }
+                Work::Exit(term) => term,
+            };
+            if let Fun { .. } | TyAbs { .. } = term.kind {
+                ctx.list.pop();
+            }
+            let n = term.kind.children().len();
+            let subterm_tys = tys.split_off(tys.len() - n);
+            let mut conditions = vec![];
+            let ty = self.check_node(
+                term,
+                &subterm_tys,
+                &ctx,
+                tyctx,
+                out.as_ref().map(|_| &mut conditions),
+            )?;
+
+            if let Some(types) = types.as_deref_mut() {
+                types.insert(term.span, ty, &ctx);
+            }
+            if out.is_some() {
+                let mut names = ctx.clone();
+                let buf = &mut String::new();
+                self.print(term, &mut names, buf);
+                let premises = derivations.split_off(derivations.len() - n);
+                derivations.push(Derivation {
+                    rule: Derivation::rule(&term.kind),
+                    ctx: ctx.display_bindings(tyctx),
+                    term: buf.clone(),
+                    ty: tyctx.display(&ctx, ty),
+                    conditions,
+                    premises,
+                });
+            }
+            tys.push(ty);
+        }
+        if let Some(out) = out {
+            out.extend(derivations);
+        }
+        Ok(tys.pop().unwrap())
+    }
+
+    /// Checks the type `ty` of the `i`th subterm of `term`, for the terms
+    /// whose later subterms are only checked if it is right.
+    fn check_operand(
+        &self,
+        term: &Term,
+        i: usize,
+        ty: TypeId,
+        ctx: &Context,
+        tyctx: &mut TyContext,
+    ) -> err::Result<()> {
         match &term.kind {
+            Arith { op, lhs, rhs } if !tyctx.subtype(ctx, ty, tyctx.common.float) => {
+                let t = if i == 0 { lhs } else { rhs };
+                let msg = format!("Operands of `{}` must be Floats", op.as_str());
+                Err(self
+                    .mismatch(msg, t.span, "`Float`", tyctx.display(ctx, ty))
+                    .with_code(codes::A0016))
+            }
+            If { cond, .. } if i == 0 && !tyctx.subtype(ctx, ty, tyctx.common.boolean) => {
+                let msg = "Guard of conditional must be a boolean".to_owned();
+                Err(self
+                    .mismatch(msg, cond.span, "`Bool`", tyctx.display(ctx, ty))
+                    .with_code(codes::A0013))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Computes the type of `term` from `tys`, the types of its subterms.
+    /// If `conditions` is given, the subtyping the typing rule relies on is
+    /// pushed onto it.
+    fn check_node(
+        &self,
+        term: &Term,
+        tys: &[TypeId],
+        ctx: &Context,
+        tyctx: &mut TyContext,
+        conditions: Option<&mut Vec<String>>,
+    ) -> err::Result<TypeId> {
+        let ty = match &term.kind {
             True | False => tyctx.common.boolean,
-            Zero => tyctx.common.nat,
+            NatLit(_) => tyctx.common.nat,
+            Str(_) => tyctx.common.string,
+            Float(_) => tyctx.common.float,
+            // The operands were checked by `check_operand`.
+            Arith { .. } => tyctx.common.float,
             If {
-                cond,
                 then_branch,
                 else_branch,
+                ..
             } => {
-                if self.type_of(cond, ctx, tyctx) == tyctx.common.boolean {
-                    let ty1 = self.type_of(then_branch, ctx, tyctx);
-                    let ty2 = self.type_of(else_branch, ctx, tyctx);
-                    if ty1 == ty2 {
-                        return ty1;
-                    } else {
-                        quit!(
-                            &self.src,
-                            term.span,
-                            "Arms of Conditionals have different types",
-                        );
-                    }
+                let (ty1, ty2) = (tys[1], tys[2]);
+                if tyctx.equal(ctx, ty1, ty2) {
+                    ty1
                 } else {
-                    quit!(
-                        &self.src,
-                        cond.span,
-                        "Guard of conditional must be a boolean"
+                    let msg = "Arms of Conditionals have different types".to_owned();
+                    let label1 = format!("this arm has type `{}`", tyctx.display(ctx, ty1));
+                    let label2 = format!("but this arm has type `{}`", tyctx.display(ctx, ty2));
+                    return Err(
+                        Diagnostic::new(self.src(else_branch.span), msg, else_branch.span)
+                            .with_code(codes::A0014)
+                            .with_primary_label(label2)
+                            .with_label(then_branch.span, label1),
                     );
                 }
             }
-            Succ(t) | Pred(t) => {
-                let ty = self.type_of(t, ctx, tyctx);
-                if ty == tyctx.common.nat {
-                    return ty;
-                } else {
-                    quit!(&self.src, t.span, "argument must be a Nat");
+            Succ(t) | Pred(t) | IsZero(t) => {
+                if !tyctx.subtype(ctx, tys[0], tyctx.common.nat) {
+                    let msg = "argument must be a Nat".to_owned();
+                    return Err(self
+                        .mismatch(msg, t.span, "`Nat`", tyctx.display(ctx, tys[0]))
+                        .with_code(codes::A0015));
                 }
-            }
-            IsZero(t) => {
-                if self.type_of(t, ctx, tyctx) == tyctx.common.nat {
-                    return tyctx.common.boolean;
+                if let IsZero(_) = term.kind {
+                    tyctx.common.boolean
                 } else {
-                    quit!(&self.src, t.span, "argument must be a Nat");
+                    tyctx.common.nat
                 }
             }
-            Var { idx, .. } => ctx.get_ty(&self.src, term.span, *idx as usize),
-            Fun { name, ty, term } => {
-                let ctx = ctx.add_binding(*name, Binding::Variable(*ty));
-                let to = self.type_of(term, &ctx, tyctx);
-                tyctx.new_arrow(*ty, to)
-            }
+            Var { idx, .. } => ctx.get_ty(self.src(term.span), term.span, *idx as usize, tyctx)?,
+            Fun { ty, .. } => tyctx.new_arrow(*ty, tys[0]),
             Call { callee, arg } => {
-                let ty_callee = self.type_of(callee, ctx, tyctx);
-                let ty_arg = self.type_of(arg, ctx, tyctx);
-                match tyctx.get(ty_callee) {
+                let (ty_callee, ty_arg) = (tys[0], tys[1]);
+                let exposed = tyctx.expose(ctx, ty_callee);
+                match tyctx.get(exposed) {
                     &Ty::Arrow { from, to } => {
-                        if from == ty_arg {
-                            return to;
+                        if tyctx.subtype(ctx, ty_arg, from) {
+                            if let Some(conditions) = conditions {
+                                if !tyctx.equal(ctx, ty_arg, from) {
+                                    conditions.push(format!(
+                                        "{} <: {}",
+                                        tyctx.display(ctx, ty_arg),
+                                        tyctx.display(ctx, from)
+                                    ));
+                                }
+                            }
+                            to
                         } else {
-                            quit!(
-                                &self.src,
-                                term.span,
-                                "Parameter type mismatch: expected: {:?}, actual: {:?}",
-                                ty_arg,
-                                from,
+                            let msg = "Parameter type mismatch".to_owned();
+                            let expected = format!("`{}`", tyctx.display(ctx, from));
+                            let found = tyctx.display(ctx, ty_arg);
+                            let label = format!(
+                                "this function has type `{}`",
+                                tyctx.display(ctx, ty_callee)
                             );
+                            return Err(self
+                                .mismatch(msg, arg.span, &expected, found)
+                                .with_code(codes::A0017)
+                                .with_label(callee.span, label));
                         }
                     }
-                    _ => quit!(&self.src, term.span, "Arrow type expected"),
+                    _ => {
+                        let msg = "Arrow type expected".to_owned();
+                        let label = format!(
+                            "this has type `{}`, which is not a function",
+                            tyctx.display(ctx, ty_callee)
+                        );
+                        return Err(Diagnostic::new(self.src(callee.span), msg, callee.span)
+                            .with_code(codes::A0018)
+                            .with_primary_label(label)
+                            .with_label(arg.span, "but is applied to this argument"));
+                    }
+                }
+            }
+            TyAbs {
+                name, kind, bound, ..
+            } => tyctx.new_all(*name, kind.clone(), *bound, tys[0]),
+            TyApp {
+                term: t,
+                ty,
+                ty_span,
+            } => {
+                let ty_t = tys[0];
+                let exposed = tyctx.expose(ctx, ty_t);
+                match tyctx.get(exposed).clone() {
+                    Ty::All {
+                        kind, bound, body, ..
+                    } => {
+                        self.check_kind(ctx, tyctx, *ty, &kind, *ty_span)?;
+                        if tyctx.subtype(ctx, *ty, bound) {
+                            if let Some(conditions) = conditions {
+                                if bound != tyctx.common.top {
+                                    conditions.push(format!(
+                                        "{} <: {}",
+                                        tyctx.display(ctx, *ty),
+                                        tyctx.display(ctx, bound)
+                                    ));
+                                }
+                            }
+                            tyctx.subst_top(body, *ty)
+                        } else {
+                            let msg =
+                                "Type argument does not satisfy the bound of the type parameter";
+                            let expected = format!("a subtype of `{}`", tyctx.display(ctx, bound));
+                            let found = tyctx.display(ctx, *ty);
+                            let label = format!("this has type `{}`", tyctx.display(ctx, ty_t));
+                            return Err(self
+                                .mismatch(msg.to_owned(), *ty_span, &expected, found)
+                                .with_code(codes::A0020)
+                                .with_label(t.span, label));
+                        }
+                    }
+                    _ => {
+                        let msg = "Universal type expected".to_owned();
+                        let found = tyctx.display(ctx, ty_t);
+                        return Err(self
+                            .mismatch(msg, t.span, "a universal type", found)
+                            .with_code(codes::A0019));
+                    }
+                }
+            }
+            Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .zip(tys)
+                    .map(|((label, _), ty)| (*label, *ty))
+                    .collect();
+                tyctx.new_record(fields)
+            }
+            Proj { term: t, label } => {
+                let ty_t = tys[0];
+                let exposed = tyctx.expose(ctx, ty_t);
+                match tyctx.get(exposed) {
+                    Ty::Record(fields) => match fields.iter().find(|(l, _)| l == label) {
+                        Some(&(_, ty)) => ty,
+                        None => {
+                            let msg = format!("No field `{}` in record", label);
+                            // The field name is the last token of the projection.
+                            let span = Span {
+                                lo: term.span.hi - label.to_string().len(),
+                                ..term.span
+                            };
+                            let label = format!("this has type `{}`", tyctx.display(ctx, ty_t));
+                            return Err(Diagnostic::new(self.src(span), msg, span)
+                                .with_code(codes::A0022)
+                                .with_primary_label("unknown field")
+                                .with_label(t.span, label));
+                        }
+                    },
+                    _ => {
+                        let msg = "Record type expected".to_owned();
+                        let found = tyctx.display(ctx, ty_t);
+                        return Err(self
+                            .mismatch(msg, t.span, "a record", found)
+                            .with_code(codes::A0021));
+                    }
                 }
             }
+        };
+        Ok(ty)
+    }
+
+    /// Checks that a term at `span` whose type is `found` also has the type
+    /// `declared`, written at `decl_span`.
+    pub fn check_declared(
+        &self,
+        found: TypeId,
+        declared: TypeId,
+        span: Span,
+        decl_span: Span,
+        ctx: &Context,
+        tyctx: &mut TyContext,
+    ) -> err::Result<()> {
+        self.check_kind(ctx, tyctx, declared, &Kind::Star, decl_span)?;
+        if tyctx.subtype(ctx, found, declared) {
+            return Ok(());
+        }
+        let expected = format!("`{}`", tyctx.display(ctx, declared));
+        let msg = "Definition type mismatch".to_owned();
+        Err(self
+            .mismatch(msg, span, &expected, tyctx.display(ctx, found))
+            .with_code(codes::A0029)
+            .with_label(decl_span, "declared here"))
+    }
+
+    /// A type error at `span`, labelled with what was expected there and
+    /// the type that was found.
+    fn mismatch(&self, msg: String, span: Span, expected: &str, found: String) -> Diagnostic {
+        Diagnostic::new(self.src(span), msg, span)
+            .with_primary_label(format!("expected {}, found `{}`", expected, found))
+    }
+
+    fn check_kind(
+        &self,
+        ctx: &Context,
+        tyctx: &mut TyContext,
+        ty: TypeId,
+        expected: &Kind,
+        span: Span,
+    ) -> err::Result<()> {
+        match tyctx.kind_of(ctx, ty) {
+            Ok(kind) if kind == *expected => Ok(()),
+            Ok(kind) => bail!(
+                self.src(span),
+                span,
+                A0023,
+                "Kind mismatch: expected: {}, actual: {}",
+                expected,
+                kind
+            ),
+            Err(msg) => bail!(self.src(span), span, A0023, msg),
         }
     }
 }
 
-#[derive(Default)]
+/// Term and type variables live in separate de Bruijn namespaces that share
+/// this one list: a term variable's index counts only term bindings, and a type
+/// variable's index only type bindings.
+#[derive(Default, Clone)]
 pub struct Context {
     list: Vec<(Symbol, Binding)>,
 }
 
 impl Context {
     pub fn len(&self) -> usize {
-        self.list.len()
+        self.terms().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
     pub fn index_to_name(&self, index: usize) -> Symbol {
-        self.list[index].0
+        self.entry(index).0
     }
 
     pub fn pick_fresh_name(&mut self, mut name: Symbol) -> Symbol {
@@ -397,20 +1527,119 @@ impl Context {
         Self { list }
     }
 
-    pub fn get_ty(&self, src: &Rc<String>, span: Span, index: usize) -> TypeId {
-        match self.get_binding(index) {
-            Binding::Variable(ty) => *ty,
-            _ => quit!(
-                src,
-                span,
-                "Wrong kind of binding for variable: {}",
-                self.index_to_name(index)
-            ),
+    /// Returns the type of a term variable, shifted past the type variables
+    /// bound after it.
+    pub fn get_ty(
+        &self,
+        src: &Rc<SourceFile>,
+        span: Span,
+        index: usize,
+        tyctx: &mut TyContext,
+    ) -> err::Result<TypeId> {
+        let mut index = index;
+        let mut dist = 0;
+        for (name, binding) in self.list.iter().rev() {
+            match binding {
+                Binding::TyVar { .. } => dist += 1,
+                Binding::Variable(ty) if index == 0 => return Ok(tyctx.shift(*ty, dist)),
+                _ if index == 0 => {
+                    bail!(
+                        src,
+                        span,
+                        A0032,
+                        "Wrong kind of binding for variable: {}",
+                        name
+                    )
+                }
+                _ => index -= 1,
+            }
         }
+        bail!(src, span, A0008, "Unbound variable")
     }
 
     pub fn get_binding(&self, index: usize) -> &Binding {
-        &self.list[index].1
+        &self.entry(index).1
+    }
+
+    /// Returns the upper bound of a type variable, shifted to be valid in this
+    /// context.
+    pub fn get_bound(&self, index: usize, tyctx: &mut TyContext) -> TypeId {
+        let bound = match self.ty_var(index) {
+            Some(Binding::TyVar { bound, .. }) => *bound,
+            _ => tyctx.common.top,
+        };
+        tyctx.shift(bound, index as i32 + 1)
+    }
+
+    pub fn get_kind(&self, index: usize) -> Option<&Kind> {
+        match self.ty_var(index)? {
+            Binding::TyVar { kind, .. } => Some(kind),
+            _ => None,
+        }
+    }
+
+    fn ty_var(&self, index: usize) -> Option<&Binding> {
+        self.list
+            .iter()
+            .rev()
+            .map(|(_, binding)| binding)
+            .filter(|binding| matches!(binding, Binding::TyVar { .. }))
+            .nth(index)
+    }
+
+    /// The bindings as they would be written in a typing judgement, e.g.
+    /// `x: Nat, X <: Top`, outermost first.
+    pub fn display(&self, tyctx: &TyContext) -> String {
+        self.display_bindings(tyctx).join(", ")
+    }
+
+    /// Like `display`, but each binding on its own.
+    pub fn display_bindings(&self, tyctx: &TyContext) -> Vec<String> {
+        let mut bindings = vec![];
+        for (i, (name, binding)) in self.list.iter().enumerate() {
+            let prefix = Context {
+                list: self.list[..i].to_vec(),
+            };
+            let mut buf = String::new();
+            match binding {
+                Binding::Name => continue,
+                Binding::Variable(ty) => {
+                    write!(buf, "{}: {}", name, tyctx.display(&prefix, *ty)).unwrap()
+                }
+                Binding::TyVar { bound, kind } => {
+                    write!(buf, "{}", name).unwrap();
+                    if *kind != Kind::Star {
+                        write!(buf, " :: {}", kind).unwrap();
+                    }
+                    if *bound != tyctx.common.top {
+                        write!(buf, " <: {}", tyctx.display(&prefix, *bound)).unwrap();
+                    }
+                }
+            }
+            bindings.push(buf);
+        }
+        bindings
+    }
+
+    /// De Bruijn index 0 refers to the innermost, i.e. most recently pushed, binding.
+    fn entry(&self, index: usize) -> &(Symbol, Binding) {
+        self.terms().nth(index).expect("term variable out of scope")
+    }
+
+    /// The names of the type variables in scope, outermost first.
+    fn ty_names(&self) -> Vec<Symbol> {
+        self.list
+            .iter()
+            .filter(|(_, binding)| matches!(binding, Binding::TyVar { .. }))
+            .map(|&(name, _)| name)
+            .collect()
+    }
+
+    fn terms(&self) -> impl Iterator<Item = &(Symbol, Binding)> {
+        self.list
+            .iter()
+            .rev()
+            .filter(|(_, binding)| !matches!(binding, Binding::TyVar { .. }))
     }
 }
 
@@ -418,68 +1647,673 @@ impl Context {
 pub enum Binding {
     Name,
     Variable(TypeId),
+    TyVar { bound: TypeId, kind: Kind },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Star,
+    Arrow(Rc<Kind>, Rc<Kind>),
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Star => f.write_str("*"),
+            Kind::Arrow(from, to) => match **from {
+                Kind::Star => write!(f, "* => {}", to),
+                _ => write!(f, "({}) => {}", from, to),
+            },
+        }
+    }
+}
+
+/// Selects the rule used to compare universal types, see TAPL chapter 28.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubtypingMode {
+    /// `forall X <: S1. S2 <: forall X <: T1. T2` requires identical bounds.
+    /// Subtyping stays decidable.
+    Kernel,
+    /// Bounds are compared contravariantly. More programs type check, but the
+    /// subtype check may not terminate.
+    Full,
+}
+
+impl std::str::FromStr for SubtypingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "kernel" => Ok(SubtypingMode::Kernel),
+            "full" => Ok(SubtypingMode::Full),
+            _ => Err(format!("Unknown subtyping mode: {}", s)),
+        }
+    }
 }
 
+/// Types are hash-consed: structurally identical types share a `TypeId`, so
+/// comparing ids decides syntactic equality. Recursive types are
+/// equi-recursive, i.e. `mu X. T` is interchangeable with its unfolding, and
+/// `equal` / `subtype` decide the relations coinductively, up to beta
+/// reduction of type operators.
 pub struct TyContext {
     pub types: Vec<Ty>,
     pub common: CommonTypes,
+    pub subtyping: SubtypingMode,
+    ids: HashMap<Ty, TypeId>,
+    unfolded: HashMap<TypeId, TypeId>,
+    aliases: HashMap<Symbol, TypeId>,
+    names: HashMap<TypeId, Symbol>,
+}
+
+impl Default for TyContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TyContext {
     pub fn new() -> Self {
-        let common = CommonTypes { boolean: 0, nat: 1 };
-        let types = vec![Ty::Bool, Ty::Nat];
-        Self { types, common }
+        let mut tcx = Self {
+            types: vec![],
+            common: CommonTypes {
+                boolean: 0,
+                nat: 0,
+                top: 0,
+                string: 0,
+                float: 0,
+            },
+            subtyping: SubtypingMode::Kernel,
+            ids: HashMap::new(),
+            unfolded: HashMap::new(),
+            aliases: HashMap::new(),
+            names: HashMap::new(),
+        };
+        tcx.common.boolean = tcx.intern(Ty::Bool);
+        tcx.common.nat = tcx.intern(Ty::Nat);
+        tcx.common.top = tcx.intern(Ty::Top);
+        tcx.common.string = tcx.intern(Ty::String);
+        tcx.common.float = tcx.intern(Ty::Float);
+        tcx.define_alias(Symbol::intern("Bool"), tcx.common.boolean);
+        tcx.define_alias(Symbol::intern("Nat"), tcx.common.nat);
+        tcx.define_alias(Symbol::intern("Top"), tcx.common.top);
+        tcx.define_alias(Symbol::intern("String"), tcx.common.string);
+        tcx.define_alias(Symbol::intern("Float"), tcx.common.float);
+        tcx
+    }
+
+    fn intern(&mut self, ty: Ty) -> TypeId {
+        if let Some(&id) = self.ids.get(&ty) {
+            return id;
+        }
+        let id = self.types.len();
+        self.types.push(ty.clone());
+        self.ids.insert(ty, id);
+        id
+    }
+
+    /// Declares an uninterpreted base type. It has no values of its own and is
+    /// only equal to itself.
+    pub fn new_base(&mut self, name: Symbol) -> TypeId {
+        self.intern(Ty::Base(name))
     }
 
     pub fn new_arrow(&mut self, from: TypeId, to: TypeId) -> TypeId {
-        let i = self.types.len();
-        self.types.push(Ty::Arrow { from, to });
-        i
+        self.intern(Ty::Arrow { from, to })
+    }
+
+    pub fn new_var(&mut self, idx: u32) -> TypeId {
+        self.intern(Ty::Var(idx))
     }
 
-    pub fn new_ty(&mut self, symbol: Symbol) -> TypeId {
-        symbol.as_str_with(|s| match s {
-            "bool" => self.common.boolean,
-            "nat" => self.common.nat,
-            _ => todo!("Dont know how"),
+    pub fn new_rec(&mut self, name: Symbol, body: TypeId) -> TypeId {
+        self.intern(Ty::Rec { name, body })
+    }
+
+    pub fn new_all(&mut self, name: Symbol, kind: Kind, bound: TypeId, body: TypeId) -> TypeId {
+        self.intern(Ty::All {
+            name,
+            kind,
+            bound,
+            body,
         })
     }
 
+    pub fn new_abs(&mut self, name: Symbol, kind: Kind, body: TypeId) -> TypeId {
+        self.intern(Ty::Abs { name, kind, body })
+    }
+
+    pub fn new_app(&mut self, ty: TypeId, arg: TypeId) -> TypeId {
+        self.intern(Ty::App { ty, arg })
+    }
+
+    pub fn new_record(&mut self, fields: Vec<(Symbol, TypeId)>) -> TypeId {
+        self.intern(Ty::Record(fields))
+    }
+
+    /// Names `ty`. Returns `false` if the name is already taken. Where a type
+    /// has several names, the first one is used when printing it.
+    pub fn define_alias(&mut self, name: Symbol, ty: TypeId) -> bool {
+        if self.aliases.contains_key(&name) {
+            return false;
+        }
+        self.aliases.insert(name, ty);
+        self.names.entry(ty).or_insert(name);
+        true
+    }
+
+    pub fn lookup_alias(&self, name: Symbol) -> Option<TypeId> {
+        self.aliases.get(&name).copied()
+    }
+
     pub fn get(&self, id: TypeId) -> &Ty {
         &self.types[id]
     }
 
-    pub fn print(&self, id: TypeId) {
-        self.get(id).print(self);
-        println!()
+    /// Renders a type for messages, naming its free type variables after
+    /// the bindings in `ctx`.
+    pub fn display(&self, ctx: &Context, id: TypeId) -> String {
+        let mut buf = String::new();
+        self.print_ty(id, &mut ctx.ty_names(), &mut buf);
+        buf
+    }
+
+    fn print_ty(&self, id: TypeId, names: &mut Vec<Symbol>, buf: &mut String) {
+        match self.names.get(&id) {
+            Some(name) => write!(buf, "{}", name).unwrap(),
+            None => self.get(id).print(self, names, buf),
+        }
+    }
+
+    /// Whether the type prints without parentheses as an argument of a type
+    /// application.
+    fn is_atomic(&self, id: TypeId) -> bool {
+        self.names.contains_key(&id)
+            || matches!(
+                self.get(id),
+                Ty::Bool
+                    | Ty::Nat
+                    | Ty::Top
+                    | Ty::String
+                    | Ty::Float
+                    | Ty::Base(_)
+                    | Ty::Var(_)
+                    | Ty::Record(_)
+            )
+    }
+
+    /// `mu X. T` is contractive if `T` is not `X` itself, possibly under
+    /// further `mu` binders. Non-contractive types have no unfolding that
+    /// exposes a type constructor.
+    pub fn is_contractive(&mut self, body: TypeId) -> bool {
+        let mut body = body;
+        let mut depth = 0;
+        loop {
+            body = self.whnf(body);
+            match *self.get(body) {
+                Ty::Var(idx) => return idx > depth,
+                Ty::Rec { body: inner, .. } => {
+                    body = inner;
+                    depth += 1;
+                }
+                _ => return true,
+            }
+        }
+    }
+
+    pub fn kind_of(&mut self, ctx: &Context, id: TypeId) -> Result<Kind, String> {
+        let star = |tcx: &mut Self, ctx: &Context, id: TypeId| match tcx.kind_of(ctx, id)? {
+            Kind::Star => Ok(()),
+            kind => Err(format!(
+                "Expected a proper type of kind *, found kind {}",
+                kind
+            )),
+        };
+
+        match self.get(id).clone() {
+            Ty::Bool | Ty::Nat | Ty::Top | Ty::String | Ty::Float | Ty::Base(_) => Ok(Kind::Star),
+            Ty::Var(idx) => match ctx.get_kind(idx as usize) {
+                Some(kind) => Ok(kind.clone()),
+                None => Ok(Kind::Star),
+            },
+            Ty::Arrow { from, to } => {
+                star(self, ctx, from)?;
+                star(self, ctx, to)?;
+                Ok(Kind::Star)
+            }
+            Ty::Record(fields) => {
+                for (_, ty) in fields {
+                    star(self, ctx, ty)?;
+                }
+                Ok(Kind::Star)
+            }
+            Ty::Rec { name, body } => {
+                let top = self.common.top;
+                let ctx = ctx.add_binding(
+                    name,
+                    Binding::TyVar {
+                        bound: top,
+                        kind: Kind::Star,
+                    },
+                );
+                star(self, &ctx, body)?;
+                Ok(Kind::Star)
+            }
+            Ty::All {
+                name,
+                kind,
+                bound,
+                body,
+            } => {
+                if bound != self.common.top {
+                    let bound_kind = self.kind_of(ctx, bound)?;
+                    if bound_kind != kind {
+                        return Err(format!(
+                            "Bound of kind {} given for a type variable of kind {}",
+                            bound_kind, kind
+                        ));
+                    }
+                }
+                let ctx = ctx.add_binding(name, Binding::TyVar { bound, kind });
+                star(self, &ctx, body)?;
+                Ok(Kind::Star)
+            }
+            Ty::Abs { name, kind, body } => {
+                let top = self.common.top;
+                let binding = Binding::TyVar {
+                    bound: top,
+                    kind: kind.clone(),
+                };
+                let ctx = ctx.add_binding(name, binding);
+                let body = self.kind_of(&ctx, body)?;
+                Ok(Kind::Arrow(Rc::new(kind), Rc::new(body)))
+            }
+            Ty::App { ty, arg } => match self.kind_of(ctx, ty)? {
+                Kind::Arrow(from, to) => {
+                    let arg = self.kind_of(ctx, arg)?;
+                    if arg == *from {
+                        Ok((*to).clone())
+                    } else {
+                        Err(format!(
+                            "Kind mismatch in type application: expected: {}, actual: {}",
+                            from, arg
+                        ))
+                    }
+                }
+                Kind::Star => Err("Type of kind * cannot be applied to a type".to_owned()),
+            },
+        }
+    }
+
+    pub fn shift(&mut self, id: TypeId, dist: i32) -> TypeId {
+        self.shift_above(id, 0, dist)
+    }
+
+    pub fn shift_above(&mut self, id: TypeId, cutoff: u32, dist: i32) -> TypeId {
+        self.map(id, cutoff, &|tcx, cutoff, idx| {
+            if idx >= cutoff {
+                tcx.new_var(((idx as i32) + dist) as u32)
+            } else {
+                tcx.new_var(idx)
+            }
+        })
+    }
+
+    pub fn subst(&mut self, id: TypeId, ty_idx: u32, subst_ty: TypeId) -> TypeId {
+        self.map(id, 0, &|tcx, cutoff, idx| {
+            if idx == ty_idx + cutoff {
+                tcx.shift(subst_ty, cutoff as i32)
+            } else {
+                tcx.new_var(idx)
+            }
+        })
+    }
+
+    pub fn subst_top(&mut self, id: TypeId, subst_ty: TypeId) -> TypeId {
+        let subst_ty = self.shift(subst_ty, 1);
+        let id = self.subst(id, 0, subst_ty);
+        self.shift(id, -1)
+    }
+
+    fn map<F>(&mut self, id: TypeId, cutoff: u32, map_fn: &F) -> TypeId
+    where
+        F: Fn(&mut Self, u32, u32) -> TypeId,
+    {
+        match self.get(id).clone() {
+            Ty::Bool | Ty::Nat | Ty::Top | Ty::String | Ty::Float | Ty::Base(_) => id,
+            Ty::Var(idx) => map_fn(self, cutoff, idx),
+            Ty::Arrow { from, to } => {
+                let from = self.map(from, cutoff, map_fn);
+                let to = self.map(to, cutoff, map_fn);
+                self.new_arrow(from, to)
+            }
+            Ty::Rec { name, body } => {
+                let body = self.map(body, cutoff + 1, map_fn);
+                self.new_rec(name, body)
+            }
+            Ty::All {
+                name,
+                kind,
+                bound,
+                body,
+            } => {
+                let bound = self.map(bound, cutoff, map_fn);
+                let body = self.map(body, cutoff + 1, map_fn);
+                self.new_all(name, kind, bound, body)
+            }
+            Ty::Abs { name, kind, body } => {
+                let body = self.map(body, cutoff + 1, map_fn);
+                self.new_abs(name, kind, body)
+            }
+            Ty::App { ty, arg } => {
+                let ty = self.map(ty, cutoff, map_fn);
+                let arg = self.map(arg, cutoff, map_fn);
+                self.new_app(ty, arg)
+            }
+            Ty::Record(fields) => {
+                let fields = fields
+                    .into_iter()
+                    .map(|(label, ty)| (label, self.map(ty, cutoff, map_fn)))
+                    .collect();
+                self.new_record(fields)
+            }
+        }
+    }
+
+    /// Replaces `mu X. T` with `T[X := mu X. T]`. Other types are returned as is.
+    pub fn unfold(&mut self, id: TypeId) -> TypeId {
+        if let Some(&unfolded) = self.unfolded.get(&id) {
+            return unfolded;
+        }
+        let unfolded = match *self.get(id) {
+            Ty::Rec { body, .. } => self.subst_top(body, id),
+            _ => id,
+        };
+        self.unfolded.insert(id, unfolded);
+        unfolded
+    }
+
+    /// Reduces applications of type operators in head position.
+    pub fn whnf(&mut self, id: TypeId) -> TypeId {
+        match *self.get(id) {
+            Ty::App { ty, arg } => {
+                let ty = self.whnf(ty);
+                match *self.get(ty) {
+                    Ty::Abs { body, .. } => {
+                        let reduced = self.subst_top(body, arg);
+                        self.whnf(reduced)
+                    }
+                    _ => self.new_app(ty, arg),
+                }
+            }
+            _ => id,
+        }
+    }
+
+    /// Unfolds recursive types and reduces type operators until the outermost
+    /// constructor is exposed.
+    pub fn simplify(&mut self, mut id: TypeId) -> TypeId {
+        loop {
+            id = self.whnf(id);
+            match self.get(id) {
+                Ty::Rec { .. } => id = self.unfold(id),
+                _ => return id,
+            }
+        }
+    }
+
+    /// Finds the least supertype of `id` that is not a type variable, by
+    /// promoting variables to their bounds.
+    pub fn expose(&mut self, ctx: &Context, mut id: TypeId) -> TypeId {
+        loop {
+            id = self.simplify(id);
+            match *self.get(id) {
+                Ty::Var(idx) => id = ctx.get_bound(idx as usize, self),
+                _ => return id,
+            }
+        }
+    }
+
+    pub fn equal(&mut self, ctx: &Context, s: TypeId, t: TypeId) -> bool {
+        self.subtype(ctx, s, t) && self.subtype(ctx, t, s)
+    }
+
+    pub fn subtype(&mut self, ctx: &Context, s: TypeId, t: TypeId) -> bool {
+        self.subtype_ac(ctx, s, t, &mut HashSet::new())
+    }
+
+    /// Decides `s <: t` for the greatest relation closed under the subtyping
+    /// rules (TAPL, chapter 21). `seen` holds the pairs assumed to be related:
+    /// when a pair comes up again while checking itself, the assumption is
+    /// taken to hold. Since types are hash-consed, unfolding a recursive type
+    /// only ever reaches finitely many ids, so this terminates.
+    fn subtype_ac(
+        &mut self,
+        ctx: &Context,
+        s: TypeId,
+        t: TypeId,
+        seen: &mut HashSet<(TypeId, TypeId)>,
+    ) -> bool {
+        let s = self.whnf(s);
+        let t = self.whnf(t);
+        if s == t || !seen.insert((s, t)) {
+            return true;
+        }
+
+        match (self.get(s).clone(), self.get(t).clone()) {
+            (_, Ty::Top) => true,
+            (Ty::Rec { .. }, _) => {
+                let s = self.unfold(s);
+                self.subtype_ac(ctx, s, t, seen)
+            }
+            (_, Ty::Rec { .. }) => {
+                let t = self.unfold(t);
+                self.subtype_ac(ctx, s, t, seen)
+            }
+            (Ty::Var(idx), _) => {
+                let bound = ctx.get_bound(idx as usize, self);
+                self.subtype_ac(ctx, bound, t, seen)
+            }
+            (Ty::Arrow { from: s1, to: s2 }, Ty::Arrow { from: t1, to: t2 }) => {
+                self.subtype_ac(ctx, t1, s1, seen) && self.subtype_ac(ctx, s2, t2, seen)
+            }
+            (Ty::Record(s_fields), Ty::Record(t_fields)) => t_fields.iter().all(|(label, t_ty)| {
+                match s_fields.iter().find(|(l, _)| l == label) {
+                    Some(&(_, s_ty)) => self.subtype_ac(ctx, s_ty, *t_ty, seen),
+                    None => false,
+                }
+            }),
+            (
+                Ty::All {
+                    name,
+                    kind: k1,
+                    bound: s1,
+                    body: s2,
+                },
+                Ty::All {
+                    kind: k2,
+                    bound: t1,
+                    body: t2,
+                    ..
+                },
+            ) => {
+                let bounds_ok = k1 == k2
+                    && match self.subtyping {
+                        SubtypingMode::Kernel => self.equal(ctx, s1, t1),
+                        SubtypingMode::Full => self.subtype_ac(ctx, t1, s1, seen),
+                    };
+                // The assumptions in `seen` are only valid in the context they
+                // were made in, so the bodies start afresh.
+                bounds_ok && {
+                    let binding = Binding::TyVar {
+                        bound: t1,
+                        kind: k1,
+                    };
+                    let ctx = ctx.add_binding(name, binding);
+                    self.subtype(&ctx, s2, t2)
+                }
+            }
+            (
+                Ty::Abs {
+                    name,
+                    kind: k1,
+                    body: s1,
+                },
+                Ty::Abs {
+                    kind: k2, body: t1, ..
+                },
+            ) => {
+                k1 == k2 && {
+                    let top = self.common.top;
+                    let ctx = ctx.add_binding(
+                        name,
+                        Binding::TyVar {
+                            bound: top,
+                            kind: k1,
+                        },
+                    );
+                    self.equal(&ctx, s1, t1)
+                }
+            }
+            // Both sides are in weak head normal form, so these are
+            // applications of type variables. Arguments are invariant.
+            (Ty::App { ty: s1, arg: s2 }, Ty::App { ty: t1, arg: t2 }) => {
+                self.subtype_ac(ctx, s1, t1, seen)
+                    && self.subtype_ac(ctx, t1, s1, seen)
+                    && self.subtype_ac(ctx, s2, t2, seen)
+                    && self.subtype_ac(ctx, t2, s2, seen)
+            }
+            _ => false,
+        }
     }
 }
 
 pub struct CommonTypes {
     pub boolean: TypeId,
     pub nat: TypeId,
+    pub top: TypeId,
+    pub string: TypeId,
+    pub float: TypeId,
 }
 
-type TypeId = usize;
+pub type TypeId = usize;
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Ty {
     Bool,
     Nat,
-    Arrow { from: TypeId, to: TypeId },
+    Top,
+    String,
+    Float,
+    Base(Symbol),
+    Arrow {
+        from: TypeId,
+        to: TypeId,
+    },
+    Var(u32),
+    Rec {
+        name: Symbol,
+        body: TypeId,
+    },
+    All {
+        name: Symbol,
+        kind: Kind,
+        bound: TypeId,
+        body: TypeId,
+    },
+    Record(Vec<(Symbol, TypeId)>),
+    Abs {
+        name: Symbol,
+        kind: Kind,
+        body: TypeId,
+    },
+    App {
+        ty: TypeId,
+        arg: TypeId,
+    },
 }
 
 impl Ty {
-    fn print(&self, ctx: &TyContext) {
+    fn print(&self, ctx: &TyContext, names: &mut Vec<Symbol>, buf: &mut String) {
         match self {
-            Ty::Bool => print!("Bool"),
-            Ty::Nat => print!("Nat"),
+            Ty::Bool => buf.push_str("Bool"),
+            Ty::Nat => buf.push_str("Nat"),
+            Ty::Top => buf.push_str("Top"),
+            Ty::String => buf.push_str("String"),
+            Ty::Float => buf.push_str("Float"),
+            Ty::Base(name) => write!(buf, "{}", name).unwrap(),
             &Ty::Arrow { from, to } => {
-                print!("|");
-                ctx.get(from).print(ctx);
-                print!("| ");
-                ctx.get(to).print(ctx);
+                buf.push('|');
+                ctx.print_ty(from, names, buf);
+                buf.push_str("| ");
+                ctx.print_ty(to, names, buf);
+            }
+            &Ty::Var(idx) => match names.len().checked_sub(idx as usize + 1) {
+                Some(i) => write!(buf, "{}", names[i]).unwrap(),
+                None => buf.push_str("[bad index]"),
+            },
+            &Ty::Rec { name, body } => {
+                write!(buf, "mu {}. ", name).unwrap();
+                names.push(name);
+                ctx.print_ty(body, names, buf);
+                names.pop();
+            }
+            Ty::All {
+                name,
+                kind,
+                bound,
+                body,
+            } => {
+                let (name, bound, body) = (*name, *bound, *body);
+                write!(buf, "forall {}", name).unwrap();
+                if *kind != Kind::Star {
+                    write!(buf, "::{}", kind).unwrap();
+                }
+                if bound != ctx.common.top {
+                    buf.push_str(" <: ");
+                    ctx.print_ty(bound, names, buf);
+                }
+                buf.push_str(". ");
+                names.push(name);
+                ctx.print_ty(body, names, buf);
+                names.pop();
+            }
+            Ty::Record(fields) => {
+                buf.push('{');
+                for (i, &(label, ty)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        buf.push_str(", ");
+                    }
+                    write!(buf, "{}: ", label).unwrap();
+                    ctx.print_ty(ty, names, buf);
+                }
+                buf.push('}');
+            }
+            Ty::Abs { name, kind, body } => {
+                write!(buf, "\\{}::{}. ", name, kind).unwrap();
+                names.push(*name);
+                ctx.print_ty(*body, names, buf);
+                names.pop();
+            }
+            &Ty::App { ty, arg } => {
+                let paren = !ctx.is_atomic(ty) && !matches!(ctx.get(ty), Ty::App { .. });
+                if paren {
+                    buf.push('(');
+                }
+                ctx.print_ty(ty, names, buf);
+                if paren {
+                    buf.push(')');
+                }
+
+                let paren = !ctx.is_atomic(arg);
+                buf.push(' ');
+                if paren {
+                    buf.push('(');
+                }
+                ctx.print_ty(arg, names, buf);
+                if paren {
+                    buf.push(')');
+                }
             }
         }
     }
@@ -0,0 +1,156 @@
+use super::TermKind::{self, *};
+use std::fmt::Write;
+
+/// A typing derivation, see `Eval::derive`. The judgements are rendered as
+/// text when the derivation is built.
+#[derive(Debug, Clone)]
+pub struct Derivation {
+    /// The typing rule that concludes the judgement, e.g. `T-App`.
+    pub rule: &'static str,
+    /// The bindings of the context, outermost first, e.g. `x: Nat`.
+    pub ctx: Vec<String>,
+    pub term: String,
+    pub ty: String,
+    /// Subtyping side conditions, e.g. `Nat <: Top`.
+    pub conditions: Vec<String>,
+    pub premises: Vec<Derivation>,
+}
+
+impl Derivation {
+    /// The typing rules, named as in TAPL.
+    pub(super) fn rule(kind: &TermKind) -> &'static str {
+        match kind {
+            True => "T-True",
+            False => "T-False",
+            NatLit(0) => "T-Zero",
+            NatLit(_) => "T-Nat",
+            Str(_) => "T-String",
+            Float(_) => "T-Float",
+            Arith { .. } => "T-Arith",
+            If { .. } => "T-If",
+            Succ(_) => "T-Succ",
+            Pred(_) => "T-Pred",
+            IsZero(_) => "T-IsZero",
+            Var { .. } => "T-Var",
+            Fun { .. } => "T-Abs",
+            Call { .. } => "T-App",
+            TyAbs { .. } => "T-TAbs",
+            TyApp { .. } => "T-TApp",
+            Record(_) => "T-Rcd",
+            Proj { .. } => "T-Proj",
+        }
+    }
+
+    /// Leaves the `n` outermost bindings out of every context, e.g. the
+    /// definitions of the prelude.
+    pub fn hide_outer(&mut self, n: usize) {
+        self.ctx.drain(..n.min(self.ctx.len()));
+        for premise in &mut self.premises {
+            premise.hide_outer(n);
+        }
+    }
+
+    /// The judgement `ctx |- term : ty`.
+    pub fn judgement(&self) -> String {
+        if self.ctx.is_empty() {
+            format!("|- {} : {}", self.term, self.ty)
+        } else {
+            format!("{} |- {} : {}", self.ctx.join(", "), self.term, self.ty)
+        }
+    }
+
+    /// Renders the derivation as an indented tree, conclusion first:
+    ///
+    /// ```text
+    /// T-App: |- ((|x| x) 0) : Nat
+    ///   T-Abs: |- (|x| x) : |Nat| Nat
+    ///     T-Var: x: Nat |- x : Nat
+    ///   T-Zero: |- 0 : Nat
+    /// ```
+    pub fn to_text(&self) -> String {
+        let mut buf = String::new();
+        self.write_text(0, &mut buf);
+        buf
+    }
+
+    fn write_text(&self, depth: usize, buf: &mut String) {
+        let indent = "  ".repeat(depth);
+        writeln!(buf, "{}{}: {}", indent, self.rule, self.judgement()).unwrap();
+        for premise in &self.premises {
+            premise.write_text(depth + 1, buf);
+        }
+        for condition in &self.conditions {
+            writeln!(buf, "{}  where {}", indent, condition).unwrap();
+        }
+    }
+
+    /// Renders the derivation as a `prooftree` environment of the LaTeX
+    /// `bussproofs` package. Side conditions appear as premises without a
+    /// derivation of their own.
+    pub fn to_latex(&self) -> String {
+        let mut buf = String::from("\\begin{prooftree}\n");
+        self.write_latex(&mut buf);
+        buf.push_str("\\end{prooftree}\n");
+        buf
+    }
+
+    fn write_latex(&self, buf: &mut String) {
+        // `bussproofs` takes at most five premises, so longer lists are
+        // grouped into rows joined without an inference line.
+        let count = self.premises.len() + self.conditions.len();
+        let mut pending = 0;
+        for i in 0..count {
+            match self.premises.get(i) {
+                Some(premise) => premise.write_latex(buf),
+                None => {
+                    let condition = &self.conditions[i - self.premises.len()];
+                    writeln!(buf, "\\AxiomC{{\\texttt{{{}}}}}", escape(condition)).unwrap();
+                }
+            }
+            pending += 1;
+            if pending == 5 && i + 1 < count {
+                buf.push_str("\\noLine\n\\QuinaryInfC{}\n");
+                pending = 1;
+            }
+        }
+        if pending == 0 {
+            buf.push_str("\\AxiomC{}\n");
+            pending = 1;
+        }
+
+        let infer = ["Unary", "Binary", "Trinary", "Quaternary", "Quinary"][pending - 1];
+        let ctx = if self.ctx.is_empty() {
+            String::new()
+        } else {
+            format!("\\texttt{{{}}} ", escape(&self.ctx.join(", ")))
+        };
+        writeln!(buf, "\\RightLabel{{\\scriptsize {}}}", self.rule).unwrap();
+        writeln!(
+            buf,
+            "\\{}InfC{{{}$\\vdash$ \\texttt{{{}}} : \\texttt{{{}}}}}",
+            infer,
+            ctx,
+            escape(&self.term),
+            escape(&self.ty)
+        )
+        .unwrap();
+    }
+}
+
+/// Escapes the characters that are special to LaTeX in text mode.
+fn escape(s: &str) -> String {
+    let mut buf = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => buf.push_str("\\textbackslash{}"),
+            '^' => buf.push_str("\\^{}"),
+            '~' => buf.push_str("\\~{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                buf.push('\\');
+                buf.push(c);
+            }
+            _ => buf.push(c),
+        }
+    }
+    buf
+}
@@ -0,0 +1,58 @@
+use super::{Context, TypeId};
+use crate::{source::FileId, span::Span};
+
+/// The types of the subterms of a term, keyed by their spans. See
+/// `Eval::type_map`.
+#[derive(Default)]
+pub struct TypeMap {
+    /// Children come before their parents.
+    entries: Vec<(Span, TypeId, Context)>,
+    /// Only terms from this file are recorded, if set.
+    file: Option<FileId>,
+}
+
+impl TypeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A map of the terms in `file` only, for programs made of several
+    /// files, whose offsets would otherwise clash.
+    pub fn for_file(file: FileId) -> Self {
+        Self {
+            entries: vec![],
+            file: Some(file),
+        }
+    }
+
+    pub(super) fn insert(&mut self, span: Span, ty: TypeId, ctx: &Context) {
+        if self.file.is_none_or(|file| file == span.file) {
+            self.entries.push((span, ty, ctx.clone()));
+        }
+    }
+
+    /// The span and type of the innermost term around `offset`.
+    pub fn type_at(&self, offset: usize) -> Option<(Span, TypeId)> {
+        self.entry_at(offset).map(|(span, ty, _)| (*span, *ty))
+    }
+
+    /// The context the type at `offset` must be displayed in.
+    pub fn context_at(&self, offset: usize) -> Option<&Context> {
+        self.entry_at(offset).map(|(_, _, ctx)| ctx)
+    }
+
+    fn entry_at(&self, offset: usize) -> Option<&(Span, TypeId, Context)> {
+        self.entries
+            .iter()
+            .filter(|(span, _, _)| span.lo <= offset && offset < span.hi)
+            .min_by_key(|(span, _, _)| span.hi - span.lo)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
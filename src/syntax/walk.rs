@@ -0,0 +1,191 @@
+//! Traversals of terms that keep their own stack instead of recursing, so
+//! that terms nested a million deep, like long `succ` chains or application
+//! spines, do not overflow the native stack.
+
+use super::{
+    Term,
+    TermKind::{self, *},
+};
+use std::mem;
+use std::rc::Rc;
+
+impl TermKind {
+    /// The direct subterms, in evaluation order.
+    pub(super) fn children(&self) -> Vec<&Rc<Term>> {
+        match self {
+            True | False | NatLit(_) | Str(_) | Float(_) | Var { .. } => vec![],
+            Arith { lhs, rhs, .. } => vec![lhs, rhs],
+            If {
+                cond,
+                then_branch,
+                else_branch,
+            } => vec![cond, then_branch, else_branch],
+            Succ(t) | Pred(t) | IsZero(t) => vec![t],
+            Fun { term, .. } | TyAbs { term, .. } | TyApp { term, .. } | Proj { term, .. } => {
+                vec![term]
+            }
+            Call { callee, arg } => vec![callee, arg],
+            Record(fields) => fields.iter().map(|(_, t)| t).collect(),
+        }
+    }
+
+    /// Like `children`, but takes them.
+    fn into_children(self) -> Vec<Rc<Term>> {
+        match self {
+            True | False | NatLit(_) | Str(_) | Float(_) | Var { .. } => vec![],
+            Arith { lhs, rhs, .. } => vec![lhs, rhs],
+            If {
+                cond,
+                then_branch,
+                else_branch,
+            } => vec![cond, then_branch, else_branch],
+            Succ(t) | Pred(t) | IsZero(t) => vec![t],
+            Fun { term, .. } | TyAbs { term, .. } | TyApp { term, .. } | Proj { term, .. } => {
+                vec![term]
+            }
+            Call { callee, arg } => vec![callee, arg],
+            Record(fields) => fields.into_iter().map(|(_, t)| t).collect(),
+        }
+    }
+
+    /// A copy with the subterms replaced by `children`, given in the order
+    /// of `children()`.
+    pub(super) fn with_children(&self, children: Vec<Rc<Term>>) -> TermKind {
+        let mut children = children.into_iter();
+        let mut next = || children.next().expect("too few children");
+        match self {
+            True => True,
+            False => False,
+            NatLit(n) => NatLit(*n),
+            Str(s) => Str(*s),
+            Float(f) => Float(*f),
+            Var { idx, len } => Var {
+                idx: *idx,
+                len: *len,
+            },
+            Arith { op, .. } => Arith {
+                op: *op,
+                lhs: next(),
+                rhs: next(),
+            },
+            If { .. } => If {
+                cond: next(),
+                then_branch: next(),
+                else_branch: next(),
+            },
+            Succ(_) => Succ(next()),
+            Pred(_) => Pred(next()),
+            IsZero(_) => IsZero(next()),
+            Fun {
+                name, ty, ty_span, ..
+            } => Fun {
+                name: *name,
+                ty: *ty,
+                ty_span: *ty_span,
+                term: next(),
+            },
+            Call { .. } => Call {
+                callee: next(),
+                arg: next(),
+            },
+            TyAbs {
+                name,
+                kind,
+                bound,
+                bound_span,
+                ..
+            } => TyAbs {
+                name: *name,
+                kind: kind.clone(),
+                bound: *bound,
+                bound_span: *bound_span,
+                term: next(),
+            },
+            TyApp { ty, ty_span, .. } => TyApp {
+                term: next(),
+                ty: *ty,
+                ty_span: *ty_span,
+            },
+            Record(fields) => Record(fields.iter().map(|(label, _)| (*label, next())).collect()),
+            Proj { label, .. } => Proj {
+                term: next(),
+                label: *label,
+            },
+        }
+    }
+}
+
+impl Drop for Term {
+    fn drop(&mut self) {
+        // Dropping the subterms would recurse once per level, so the ones
+        // nothing else holds on to are taken apart here instead.
+        let mut stack = mem::replace(&mut self.kind, True).into_children();
+        while let Some(term) = stack.pop() {
+            if let Ok(mut term) = Rc::try_unwrap(term) {
+                stack.extend(mem::replace(&mut term.kind, True).into_children());
+            }
+        }
+    }
+}
+
+/// Where a subterm is inside the term being rebuilt: the number of `Fun`s
+/// and of `TyAbs`s around it.
+#[derive(Clone, Copy)]
+pub(super) struct Depth {
+    pub funs: u32,
+    pub ty_abs: u32,
+}
+
+/// Rebuilds `term` bottom-up. `visit` sees each subterm before its children
+/// and may return a replacement for it, in which case they are skipped.
+/// Otherwise the subterm is rebuilt from its rebuilt children and its kind
+/// passed through `finish`.
+pub(super) fn rebuild<V, F>(term: &Rc<Term>, mut visit: V, mut finish: F) -> Rc<Term>
+where
+    V: FnMut(&Rc<Term>, Depth) -> Option<Rc<Term>>,
+    F: FnMut(TermKind, Depth) -> TermKind,
+{
+    enum Work<'a> {
+        Visit(&'a Rc<Term>, Depth),
+        Build(&'a Rc<Term>, Depth),
+    }
+
+    let depth = Depth { funs: 0, ty_abs: 0 };
+    let mut work = vec![Work::Visit(term, depth)];
+    let mut done: Vec<Rc<Term>> = vec![];
+    while let Some(item) = work.pop() {
+        match item {
+            Work::Visit(term, depth) => {
+                if let Some(term) = visit(term, depth) {
+                    done.push(term);
+                    continue;
+                }
+                let inner = match term.kind {
+                    Fun { .. } => Depth {
+                        funs: depth.funs + 1,
+                        ..depth
+                    },
+                    TyAbs { .. } => Depth {
+                        ty_abs: depth.ty_abs + 1,
+                        ..depth
+                    },
+                    _ => depth,
+                };
+                work.push(Work::Build(term, depth));
+                for child in term.kind.children().into_iter().rev() {
+                    work.push(Work::Visit(child, inner));
+                }
+            }
+            Work::Build(term, depth) => {
+                let n = term.kind.children().len();
+                let children = done.split_off(done.len() - n);
+                let kind = finish(term.kind.with_children(children), depth);
+                done.push(Rc::new(Term {
+                    kind,
+                    span: term.span,
+                }));
+            }
+        }
+    }
+    done.pop().unwrap()
+}
@@ -0,0 +1,323 @@
+//! A bytecode compiler and a stack machine to run it, much faster than
+//! `Eval::eval` on programs that do real work. Instead of substituting
+//! arguments into function bodies, functions become closures over an
+//! environment of values that variables index into, by de Bruijn index.
+//!
+//! Types do not matter to evaluation. A type abstraction is a closure that
+//! is called by a type application, and the types it is applied to are only
+//! kept so that functions can be turned back into terms to print them.
+
+mod compile;
+mod disasm;
+
+pub use compile::compile;
+
+use crate::{
+    err,
+    lexer::Symbol,
+    span::Span,
+    syntax::{ArithOp, Eval, Term, TermKind, TyContext, TypeId},
+};
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+/// An instruction. Instructions pop their operands off the stack and push
+/// their result.
+#[derive(Debug, Clone)]
+pub enum Op {
+    True,
+    False,
+    Nat(u64),
+    Str(Symbol),
+    Float(f64),
+    Succ,
+    Pred,
+    IsZero,
+    Arith(ArithOp),
+    /// Pushes the value of the variable with this de Bruijn index.
+    Var(u32),
+    /// Pushes a closure of the function with this index over the current
+    /// environment.
+    Closure(u32),
+    /// Pops an argument and a closure, and calls the closure.
+    Call,
+    /// Like `Call`, but the result of the call is returned at once, so the
+    /// current frame is reused.
+    TailCall,
+    /// Pops a type abstraction and instantiates it with this type.
+    TyApp(TypeId),
+    /// Pops one value per label, the last one on top, into a record.
+    Record(Rc<[Symbol]>),
+    Proj(Symbol),
+    Jump(u32),
+    /// Pops a boolean and jumps if it is false.
+    JumpIfFalse(u32),
+    /// Pops a value and binds it as the innermost variable, for the
+    /// definitions of a program.
+    Bind,
+    Return,
+}
+
+/// A compiled program.
+pub struct Chunk {
+    /// The entry point first, then the body of every function and type
+    /// abstraction.
+    pub funs: Vec<Function>,
+}
+
+pub struct Function {
+    /// The `Fun` or `TyAbs` term compiled, or `None` for the entry point.
+    pub term: Option<Rc<Term>>,
+    pub code: Vec<Op>,
+    /// The span of the term each instruction comes from.
+    pub spans: Vec<Span>,
+}
+
+/// The result of running a chunk.
+#[derive(Clone)]
+pub enum Value {
+    Bool(bool),
+    Nat(u64),
+    Str(Symbol),
+    Float(f64),
+    Closure(Rc<Closure>),
+    Record(Rc<[(Symbol, Value)]>),
+}
+
+pub struct Closure {
+    fun: u32,
+    env: List<Value>,
+    /// The types the enclosing type abstractions were applied to.
+    tys: List<TypeId>,
+}
+
+/// An immutable linked list, innermost binding first, which closures share
+/// with the frame that created them.
+struct List<T>(Option<Rc<(T, List<T>)>>);
+
+impl<T> List<T> {
+    fn new() -> Self {
+        List(None)
+    }
+
+    fn push(&self, value: T) -> Self {
+        List(Some(Rc::new((value, self.clone()))))
+    }
+
+    fn get(&self, index: u32) -> &T {
+        self.iter()
+            .nth(index as usize)
+            .expect("variable out of scope")
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        let mut list = self;
+        std::iter::from_fn(move || {
+            let (value, next) = list.0.as_deref()?;
+            list = next;
+            Some(value)
+        })
+    }
+}
+
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List(self.0.clone())
+    }
+}
+
+struct Frame {
+    fun: u32,
+    pc: usize,
+    env: List<Value>,
+    tys: List<TypeId>,
+}
+
+impl Chunk {
+    /// Runs the program to a value. It must have been compiled from terms
+    /// that type check, so the only error is a Nat overflow, which `eval`
+    /// reports.
+    pub fn run(&self, eval: &Eval, tyctx: &mut TyContext) -> err::Result<Value> {
+        let mut frame = Frame {
+            fun: 0,
+            pc: 0,
+            env: List::new(),
+            tys: List::new(),
+        };
+        // Calls are not nested on the native stack, so deep recursion in
+        // the program only grows these.
+        let mut frames = vec![];
+        let mut stack = vec![];
+        loop {
+            let fun = &self.funs[frame.fun as usize];
+            let op = &fun.code[frame.pc];
+            frame.pc += 1;
+            match op {
+                Op::True => stack.push(Value::Bool(true)),
+                Op::False => stack.push(Value::Bool(false)),
+                Op::Nat(n) => stack.push(Value::Nat(*n)),
+                Op::Str(s) => stack.push(Value::Str(*s)),
+                Op::Float(f) => stack.push(Value::Float(*f)),
+                Op::Succ => match pop_nat(&mut stack).checked_add(1) {
+                    Some(n) => stack.push(Value::Nat(n)),
+                    None => return Err(eval.overflow(fun.spans[frame.pc - 1])),
+                },
+                Op::Pred => {
+                    let n = pop_nat(&mut stack);
+                    stack.push(Value::Nat(n.saturating_sub(1)));
+                }
+                Op::IsZero => {
+                    let n = pop_nat(&mut stack);
+                    stack.push(Value::Bool(n == 0));
+                }
+                Op::Arith(op) => {
+                    let rhs = pop_float(&mut stack);
+                    let lhs = pop_float(&mut stack);
+                    stack.push(Value::Float(op.apply(lhs, rhs)));
+                }
+                Op::Var(idx) => stack.push(frame.env.get(*idx).clone()),
+                Op::Closure(fun) => stack.push(Value::Closure(Rc::new(Closure {
+                    fun: *fun,
+                    env: frame.env.clone(),
+                    tys: frame.tys.clone(),
+                }))),
+                Op::Call | Op::TailCall => {
+                    let arg = stack.pop().expect("stack underflow");
+                    let closure = pop_closure(&mut stack);
+                    let callee = Frame {
+                        fun: closure.fun,
+                        pc: 0,
+                        env: closure.env.push(arg),
+                        tys: closure.tys.clone(),
+                    };
+                    if let Op::Call = op {
+                        frames.push(mem::replace(&mut frame, callee));
+                    } else {
+                        frame = callee;
+                    }
+                }
+                Op::TyApp(ty) => {
+                    // The type may refer to the type variables in scope,
+                    // which are replaced by what they stand for.
+                    let mut ty = *ty;
+                    for &arg in frame.tys.iter() {
+                        ty = tyctx.subst_top(ty, arg);
+                    }
+                    let closure = pop_closure(&mut stack);
+                    let callee = Frame {
+                        fun: closure.fun,
+                        pc: 0,
+                        env: closure.env.clone(),
+                        tys: closure.tys.push(ty),
+                    };
+                    frames.push(mem::replace(&mut frame, callee));
+                }
+                Op::Record(labels) => {
+                    let values = stack.split_off(stack.len() - labels.len());
+                    let fields = labels.iter().copied().zip(values).collect();
+                    stack.push(Value::Record(fields));
+                }
+                Op::Proj(label) => match stack.pop() {
+                    Some(Value::Record(fields)) => {
+                        let (_, value) = fields
+                            .iter()
+                            .find(|(l, _)| l == label)
+                            .expect("missing field");
+                        stack.push(value.clone());
+                    }
+                    _ => panic!("expected a record"),
+                },
+                Op::Jump(target) => frame.pc = *target as usize,
+                Op::JumpIfFalse(target) => match stack.pop() {
+                    Some(Value::Bool(true)) => {}
+                    Some(Value::Bool(false)) => frame.pc = *target as usize,
+                    _ => panic!("expected a boolean"),
+                },
+                Op::Bind => {
+                    let value = stack.pop().expect("stack underflow");
+                    frame.env = frame.env.push(value);
+                }
+                Op::Return => match frames.pop() {
+                    Some(caller) => frame = caller,
+                    None => return Ok(stack.pop().expect("stack underflow")),
+                },
+            }
+        }
+    }
+}
+
+fn pop_nat(stack: &mut Vec<Value>) -> u64 {
+    match stack.pop() {
+        Some(Value::Nat(n)) => n,
+        _ => panic!("expected a numeral"),
+    }
+}
+
+fn pop_float(stack: &mut Vec<Value>) -> f64 {
+    match stack.pop() {
+        Some(Value::Float(f)) => f,
+        _ => panic!("expected a float"),
+    }
+}
+
+fn pop_closure(stack: &mut Vec<Value>) -> Rc<Closure> {
+    match stack.pop() {
+        Some(Value::Closure(closure)) => closure,
+        _ => panic!("expected a closure"),
+    }
+}
+
+impl Value {
+    /// The value as a closed term, the same one `Eval::eval` gives. `chunk`
+    /// must be the one the value came from.
+    pub fn to_term(&self, chunk: &Chunk, eval: &Eval, tyctx: &mut TyContext) -> Rc<Term> {
+        self.to_term_in(chunk, eval, tyctx, &mut HashMap::new())
+    }
+
+    /// `to_term`, reusing the terms in `closures`. Closures share their
+    /// environments, so without it the definitions each closure captures
+    /// would be turned into terms again for every closure that captures
+    /// them, which takes exponential time in the number of definitions.
+    fn to_term_in(
+        &self,
+        chunk: &Chunk,
+        eval: &Eval,
+        tyctx: &mut TyContext,
+        closures: &mut HashMap<*const Closure, Rc<Term>>,
+    ) -> Rc<Term> {
+        let kind = match self {
+            Value::Bool(true) => TermKind::True,
+            Value::Bool(false) => TermKind::False,
+            Value::Nat(n) => TermKind::NatLit(*n),
+            Value::Str(s) => TermKind::Str(*s),
+            Value::Float(f) => TermKind::Float(*f),
+            Value::Record(fields) => TermKind::Record(
+                fields
+                    .iter()
+                    .map(|(label, value)| (*label, value.to_term_in(chunk, eval, tyctx, closures)))
+                    .collect(),
+            ),
+            Value::Closure(closure) => {
+                if let Some(term) = closures.get(&Rc::as_ptr(closure)) {
+                    return term.clone();
+                }
+                let fun = &chunk.funs[closure.fun as usize];
+                let mut term = fun.term.clone().expect("the entry point is not a function");
+                for &ty in closure.tys.iter() {
+                    term = eval.ty_subst_top(&term, ty, tyctx);
+                }
+                // Each value only needs weakening past the variables
+                // outside it, like in `Program::link`.
+                let len = closure.env.iter().count();
+                for (i, value) in closure.env.iter().enumerate() {
+                    let value = value.to_term_in(chunk, eval, tyctx, closures);
+                    term = eval.subst_top(&term, eval.shift(&value, (len - 1 - i) as i32));
+                }
+                closures.insert(Rc::as_ptr(closure), term.clone());
+                return term;
+            }
+        };
+        Rc::new(Term::with_span(kind, Span::dummy()))
+    }
+}
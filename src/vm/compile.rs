@@ -0,0 +1,152 @@
+use super::{Chunk, Function, Op};
+use crate::{
+    span::Span,
+    syntax::{Term, TermKind::*},
+};
+use std::rc::Rc;
+
+/// Compiles `body` in the scope of `defs`, outermost first, each of which
+/// is in the scope of the ones before it. All of them must type check.
+pub fn compile(defs: &[Rc<Term>], body: &Rc<Term>) -> Chunk {
+    let mut compiler = Compiler {
+        funs: vec![Function::new(None)],
+    };
+    let mut main = Function::new(None);
+    for def in defs {
+        compiler.term(def, &mut main, false);
+        main.emit(Op::Bind, def.span);
+    }
+    compiler.term(body, &mut main, true);
+    main.emit(Op::Return, body.span);
+    compiler.funs[0] = main;
+    Chunk {
+        funs: compiler.funs,
+    }
+}
+
+struct Compiler {
+    /// The functions compiled so far, after a placeholder for the entry
+    /// point.
+    funs: Vec<Function>,
+}
+
+impl Function {
+    fn new(term: Option<Rc<Term>>) -> Self {
+        Self {
+            term,
+            code: vec![],
+            spans: vec![],
+        }
+    }
+
+    fn emit(&mut self, op: Op, span: Span) {
+        self.code.push(op);
+        self.spans.push(span);
+    }
+}
+
+impl Compiler {
+    /// Appends the code of `term` to `fun`. A term in tail position is the
+    /// result of its function, so calls there become tail calls.
+    fn term(&mut self, term: &Rc<Term>, fun: &mut Function, tail: bool) {
+        let span = term.span;
+        match &term.kind {
+            True => fun.emit(Op::True, span),
+            False => fun.emit(Op::False, span),
+            NatLit(n) => fun.emit(Op::Nat(*n), span),
+            Str(s) => fun.emit(Op::Str(*s), span),
+            Float(f) => fun.emit(Op::Float(*f), span),
+            Succ(t) => match numeral(term) {
+                Some(n) => fun.emit(Op::Nat(n), span),
+                None => {
+                    self.term(t, fun, false);
+                    fun.emit(Op::Succ, span);
+                }
+            },
+            Pred(t) => {
+                self.term(t, fun, false);
+                fun.emit(Op::Pred, span);
+            }
+            IsZero(t) => {
+                self.term(t, fun, false);
+                fun.emit(Op::IsZero, span);
+            }
+            Arith { op, lhs, rhs } => {
+                self.term(lhs, fun, false);
+                self.term(rhs, fun, false);
+                fun.emit(Op::Arith(*op), span);
+            }
+            If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.term(cond, fun, false);
+                let jump_to_else = fun.code.len();
+                fun.emit(Op::JumpIfFalse(0), span);
+                self.term(then_branch, fun, tail);
+                let jump_to_end = fun.code.len();
+                fun.emit(Op::Jump(0), span);
+                fun.code[jump_to_else] = Op::JumpIfFalse(fun.code.len() as u32);
+                self.term(else_branch, fun, tail);
+                fun.code[jump_to_end] = Op::Jump(fun.code.len() as u32);
+            }
+            Var { idx, .. } => fun.emit(Op::Var(*idx), span),
+            Fun { term: body, .. } | TyAbs { term: body, .. } => {
+                let index = self.function(term, body);
+                fun.emit(Op::Closure(index), span);
+            }
+            Call { callee, arg } => {
+                self.term(callee, fun, false);
+                self.term(arg, fun, false);
+                fun.emit(if tail { Op::TailCall } else { Op::Call }, span);
+            }
+            TyApp { term, ty, .. } => {
+                self.term(term, fun, false);
+                fun.emit(Op::TyApp(*ty), span);
+            }
+            Record(fields) => {
+                for (_, t) in fields {
+                    self.term(t, fun, false);
+                }
+                let labels = fields.iter().map(|(label, _)| *label).collect();
+                fun.emit(Op::Record(labels), span);
+            }
+            Proj { term, label } => {
+                self.term(term, fun, false);
+                fun.emit(Op::Proj(*label), span);
+            }
+        }
+    }
+
+    /// Compiles the body of a function or type abstraction, and returns the
+    /// index of the function.
+    fn function(&mut self, term: &Rc<Term>, body: &Rc<Term>) -> u32 {
+        // Reserve the index first, so functions nested in the body come
+        // after it.
+        let index = self.funs.len();
+        self.funs.push(Function::new(Some(term.clone())));
+        let mut fun = Function::new(Some(term.clone()));
+        self.term(body, &mut fun, true);
+        fun.emit(Op::Return, body.span);
+        self.funs[index] = fun;
+        index as u32
+    }
+}
+
+/// The value of `term` if it is a numeral like `succ succ 0` that does not
+/// overflow.
+fn numeral(term: &Term) -> Option<u64> {
+    let mut n: u64 = 0;
+    let mut term = term;
+    loop {
+        match &term.kind {
+            NatLit(m) => return m.checked_add(n),
+            Succ(t) => {
+                n += 1;
+                term = t;
+            }
+            _ => return None,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use super::{Chunk, Op};
+use crate::syntax::{Context, TermKind, TyContext};
+use std::fmt::Write;
+
+impl Chunk {
+    /// Lists the instructions of every function, the entry point first,
+    /// for debugging the compiler.
+    pub fn disassemble(&self, tyctx: &TyContext, buf: &mut String) {
+        for (i, fun) in self.funs.iter().enumerate() {
+            if i > 0 {
+                buf.push('\n');
+            }
+            match fun.term.as_ref().map(|term| &term.kind) {
+                Some(TermKind::Fun { name, .. }) => writeln!(buf, "fn {} |{}|:", i, name),
+                Some(TermKind::TyAbs { name, .. }) => writeln!(buf, "fn {} [{}]:", i, name),
+                _ => writeln!(buf, "main:"),
+            }
+            .unwrap();
+            for (pc, op) in fun.code.iter().enumerate() {
+                write!(buf, "{:>4}  ", pc).unwrap();
+                match op {
+                    Op::True => buf.push_str("true"),
+                    Op::False => buf.push_str("false"),
+                    Op::Nat(n) => write!(buf, "nat {}", n).unwrap(),
+                    Op::Str(s) => s.as_str_with(|s| write!(buf, "str {:?}", s)).unwrap(),
+                    Op::Float(f) => write!(buf, "float {:?}", f).unwrap(),
+                    Op::Succ => buf.push_str("succ"),
+                    Op::Pred => buf.push_str("pred"),
+                    Op::IsZero => buf.push_str("iszero"),
+                    Op::Arith(op) => write!(buf, "arith {}", op.as_str()).unwrap(),
+                    Op::Var(idx) => write!(buf, "var {}", idx).unwrap(),
+                    Op::Closure(fun) => write!(buf, "closure fn {}", fun).unwrap(),
+                    Op::Call => buf.push_str("call"),
+                    Op::TailCall => buf.push_str("tailcall"),
+                    Op::TyApp(ty) => {
+                        let ty = tyctx.display(&Context::default(), *ty);
+                        write!(buf, "tyapp [{}]", ty).unwrap();
+                    }
+                    Op::Record(labels) => {
+                        buf.push_str("record");
+                        for label in labels.iter() {
+                            write!(buf, " {}", label).unwrap();
+                        }
+                    }
+                    Op::Proj(label) => write!(buf, "proj {}", label).unwrap(),
+                    Op::Jump(target) => write!(buf, "jump {}", target).unwrap(),
+                    Op::JumpIfFalse(target) => write!(buf, "jumpiffalse {}", target).unwrap(),
+                    Op::Bind => buf.push_str("bind"),
+                    Op::Return => buf.push_str("return"),
+                }
+                buf.push('\n');
+            }
+        }
+    }
+}
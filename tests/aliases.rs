@@ -0,0 +1,62 @@
+use arith::{err::Diagnostic, module, source::SourceFile, syntax::TyContext};
+use std::rc::Rc;
+
+/// The type of the program `src`, without the prelude.
+fn check(src: &str) -> Result<String, Diagnostic> {
+    let tyctx = &mut TyContext::new();
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let program = module::load(src, tyctx, false)?;
+    let ctx = program.check_defs(tyctx)?;
+    let ty = program.eval().type_of(program.body()?, &ctx, tyctx)?;
+    Ok(tyctx.display(&ctx, ty))
+}
+
+/// The code of the error in `src` and the text under its primary label.
+fn error(src: &str) -> (&'static str, &str) {
+    let d = check(src).unwrap_err();
+    let label = d.labels().iter().find(|label| label.primary).unwrap();
+    (d.code().unwrap(), &src[label.span.lo..label.span.hi])
+}
+
+#[test]
+fn aliases_expand_to_their_definitions() {
+    let src = "type T = {a: Nat, b: Bool};\n(|t: T| t.a) {a = 0, b = true}";
+    assert_eq!(check(src).unwrap(), "Nat");
+    let src = "type Pair = \\X. \\Y. {fst: X, snd: Y};\n\
+               (|p: Pair Nat Bool| p.snd) {fst = 0, snd = true}";
+    assert_eq!(check(src).unwrap(), "Bool");
+    let src = "type F = |Nat| Nat;\ntype G = |F| F;\n(|g: G| g (|n: Nat| succ n)) (|f: F| f)";
+    assert_eq!(check(src).unwrap(), "F");
+}
+
+#[test]
+fn types_print_under_their_alias() {
+    let src = "type T = {a: Nat, b: Bool};\n|t: T| t";
+    assert_eq!(check(src).unwrap(), "|T| T");
+    // The alias names the type, not just the annotations that use it.
+    let src = "type T = {a: Nat, b: Bool};\n|t: {a: Nat, b: Bool}| t.b";
+    assert_eq!(check(src).unwrap(), "|T| Bool");
+}
+
+#[test]
+fn an_alias_defined_twice() {
+    let src = "type T = Nat;\ntype T = Bool;\n0";
+    assert_eq!(error(src), ("A0010", "T"));
+    // The label is on the second definition.
+    let d = check(src).unwrap_err();
+    assert_eq!(d.labels()[0].span.lo, src.rfind('T').unwrap());
+}
+
+#[test]
+fn an_alias_inside_a_type_abstraction() {
+    assert_eq!(error("|X| type T = X;\n0"), ("A0011", "type T = X;"));
+}
+
+#[test]
+fn ill_kinded_alias_bodies_are_reported_where_declared() {
+    assert_eq!(error("type Bad = Nat Nat;\n0"), ("A0023", "Nat Nat"));
+    // Even if the alias is never used.
+    assert_eq!(error("type Bad = \\X. X Nat;\n0"), ("A0023", "\\X. X Nat"));
+    // Operators themselves are fine.
+    assert_eq!(check("type Id = \\X. X;\n0").unwrap(), "Nat");
+}
@@ -0,0 +1,97 @@
+use arith::{
+    err::Diagnostic,
+    module, pretty,
+    source::SourceFile,
+    syntax::{Context, TyContext},
+};
+use std::rc::Rc;
+
+/// Evaluates `src` without the prelude and returns `value : type`.
+fn run(src: &str) -> Result<String, Diagnostic> {
+    let tyctx = &mut TyContext::new();
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let program = module::load(src, tyctx, false)?;
+    let eval = program.eval();
+    let ctx = program.check_defs(tyctx)?;
+    let body = program.body()?;
+    let ty = eval.type_of(body, &ctx, tyctx)?;
+    let ty = tyctx.display(&ctx, ty);
+    let ctx = &mut Context::default();
+    let value = eval.eval(&program.link(body, tyctx), ctx, tyctx);
+    let value = pretty::pretty(&value, ctx, tyctx, pretty::DEFAULT_WIDTH);
+    Ok(format!("{} : {}", value, ty))
+}
+
+/// The code of the error in `src`, the text under its primary label and
+/// the label.
+fn error(src: &str) -> (&'static str, &str, String) {
+    let d = run(src).unwrap_err();
+    let label = d.labels().iter().find(|label| label.primary).unwrap();
+    let text = &src[label.span.lo..label.span.hi];
+    (d.code().unwrap(), text, label.msg.clone())
+}
+
+#[test]
+fn string_literals() {
+    assert_eq!(run(r#""""#).unwrap(), r#""" : String"#);
+    assert_eq!(run(r#""héllo""#).unwrap(), r#""héllo" : String"#);
+    assert_eq!(
+        run(r#""tab\there \"q\" \\ \u{1F600}""#).unwrap(),
+        r#""tab\there \"q\" \\ 😀" : String"#
+    );
+    assert_eq!(error(r#""bad \q""#).0, "A0003");
+    assert_eq!(error("\"open").0, "A0002");
+}
+
+#[test]
+fn float_literals_and_operators() {
+    assert_eq!(run("1.5").unwrap(), "1.5 : Float");
+    assert_eq!(run("1.5 + 2.25 * 2.0").unwrap(), "6.0 : Float");
+    assert_eq!(run("(1.5 + 2.5) * 2.0").unwrap(), "8.0 : Float");
+    assert_eq!(run("8.0 - 2.0 - 1.0").unwrap(), "5.0 : Float");
+    assert_eq!(run("9.0 / 3.0 / 3.0").unwrap(), "1.0 : Float");
+    assert_eq!(run("(|x: Float| x * x) 1.5").unwrap(), "2.25 : Float");
+    // There are no negative or infinite literals, so such values print as
+    // the arithmetic that makes them.
+    assert_eq!(run("2.0 - 3.5").unwrap(), "0.0 - 1.5 : Float");
+    assert_eq!(run("1.0 / 0.0").unwrap(), "1.0 / 0.0 : Float");
+}
+
+#[test]
+fn arithmetic_on_other_types() {
+    assert_eq!(
+        error("1 + 2.0"),
+        ("A0016", "1", "expected `Float`, found `Nat`".to_owned())
+    );
+    assert_eq!(
+        error("1.5 * true"),
+        ("A0016", "true", "expected `Float`, found `Bool`".to_owned())
+    );
+    assert_eq!(
+        error(r#""a" + "b""#),
+        (
+            "A0016",
+            r#""a""#,
+            "expected `Float`, found `String`".to_owned()
+        )
+    );
+    assert_eq!(error("1.0 - (|x: Float| x)").1, "(|x: Float| x)");
+}
+
+#[test]
+fn uninterpreted_atoms() {
+    assert_eq!(
+        run("type Int;\n|x: Int| x").unwrap(),
+        "|x: Int| x : |Int| Int"
+    );
+    assert_eq!(
+        run("type A;\ntype B;\n|f: |A| B| |a: A| f a").unwrap(),
+        "|f: |A| B| |a: A| f a : ||A| B| |A| B"
+    );
+    // An atom is equal only to itself.
+    assert_eq!(error("type Int;\n(|x: Int| x) 0").0, "A0017");
+    assert_eq!(
+        error("type A;\ntype B;\n(|a: A| a) ((|b: B| b) 0)").0,
+        "A0017"
+    );
+}
@@ -0,0 +1,208 @@
+//! Runs the `arith` binary and checks its output and exit status.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `arith` with `args` on `stdin` and returns its exit status, stdout
+/// and stderr.
+fn arith(args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_arith"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Usage errors exit without reading stdin.
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let out = child.wait_with_output().unwrap();
+    (
+        out.status.code().unwrap(),
+        String::from_utf8(out.stdout).unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    )
+}
+
+fn status(args: &[&str], stdin: &str) -> i32 {
+    arith(args, stdin).0
+}
+
+/// A fresh directory for the files of one test.
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("arith-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn subcommands() {
+    let run = |command: &str| {
+        let (status, stdout, stderr) = arith(&[command, "--no-prelude"], "succ 0");
+        assert_eq!((status, stderr.as_str()), (0, ""), "{}", command);
+        stdout
+    };
+    assert_eq!(run("run"), "1 : Nat\n");
+    assert_eq!(run("check"), "Nat\n");
+    assert_eq!(run("eval"), "1\n");
+    assert_eq!(run("parse"), "Succ 0..6\n  NatLit 0 5..6\n");
+    assert_eq!(run("disasm"), "main:\n   0  nat 1\n   1  return\n");
+    // `run` is the default, and `-` is stdin.
+    assert_eq!(arith(&["--no-prelude"], "succ 0").1, "1 : Nat\n");
+    assert_eq!(arith(&["check", "-"], "succ 0").1, "Nat\n");
+
+    assert_eq!(arith(&["fmt"], "succ   0").1, "succ 0\n");
+    let (status, stdout, _) = arith(&["repl", "--no-prelude"], "succ 0\n:q\n");
+    assert_eq!(status, 0);
+    assert!(stdout.contains("> 1 : Nat\n"), "{}", stdout);
+
+    let (status, stdout, _) = arith(&["--explain", "a0017"], "");
+    assert_eq!(status, 0);
+    assert!(stdout.starts_with("A function was applied"), "{}", stdout);
+
+    // A file named like a command is read once a command was given.
+    let dir = temp_dir("subcommands");
+    std::fs::write(dir.join("check"), "true").unwrap();
+    let (_, stdout, stderr) = arith(&["check", dir.join("check").to_str().unwrap()], "");
+    assert_eq!(stdout, "Bool\n", "{}", stderr);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn errors_exit_with_the_status_of_their_phase() {
+    assert_eq!(status(&[], "(succ 0"), 1);
+    assert_eq!(status(&[], "succ @"), 1);
+    assert_eq!(status(&[], "succ true"), 2);
+    assert_eq!(status(&["check"], "succ true"), 2);
+    assert_eq!(status(&[], "succ 18446744073709551615"), 3);
+    assert_eq!(status(&["--vm"], "succ 18446744073709551615"), 3);
+    // `check` does not evaluate.
+    assert_eq!(status(&["check"], "succ 18446744073709551615"), 0);
+}
+
+#[test]
+fn bad_usage() {
+    for args in [
+        &["--frobnicate"][..],
+        &["a.arith", "b.arith"],
+        &["--explain"],
+        &["--explain", "A9999"],
+        &["--error-format=xml"],
+        &["--width=wide"],
+        &["--trace"],
+        &["check", "--vm"],
+        &["eval", "--vm", "--trace"],
+        &["run", "--derivation"],
+        &["--derivation=html"],
+        &["--check"],
+        &["fmt", "--no-prelude"],
+        &["repl", "a.arith"],
+    ] {
+        let (status, stdout, stderr) = arith(args, "0");
+        assert_eq!(status, 64, "{:?}", args);
+        assert_eq!(stdout, "", "{:?}", args);
+        assert!(stderr.starts_with("error: "), "{:?}: {}", args, stderr);
+    }
+    let (status, stdout, _) = arith(&["--help"], "");
+    assert_eq!(status, 0);
+    assert!(stdout.starts_with("usage: arith"), "{}", stdout);
+}
+
+#[test]
+fn unreadable_input() {
+    let dir = temp_dir("unreadable");
+    let missing = dir.join("missing.arith");
+    let (code, _, stderr) = arith(&[missing.to_str().unwrap()], "");
+    assert_eq!(code, 66);
+    assert!(stderr.contains("Could not read"), "{}", stderr);
+    assert_eq!(fmt(&missing), 66);
+    // A directory cannot be read as a file either.
+    assert_eq!(status(&[dir.to_str().unwrap()], ""), 66);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unwritable_output() {
+    let dir = temp_dir("unwritable");
+    let formatted = dir.join("formatted.arith");
+    let unformatted = dir.join("unformatted.arith");
+    std::fs::write(&formatted, "succ 0\n").unwrap();
+    std::fs::write(&unformatted, "succ   0").unwrap();
+    for path in [&formatted, &unformatted] {
+        let mut permissions = std::fs::metadata(path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(path, permissions).unwrap();
+    }
+    // Formatted files are left alone, so they need not be writable.
+    assert_eq!(fmt(&formatted), 0);
+    // Permissions do not stop a privileged user, e.g. root in a container.
+    if std::fs::OpenOptions::new()
+        .write(true)
+        .open(&unformatted)
+        .is_err()
+    {
+        assert_eq!(fmt(&unformatted), 73);
+        assert_eq!(std::fs::read_to_string(&unformatted).unwrap(), "succ   0");
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Formats the file at `path` in place and returns the exit status.
+fn fmt(path: &std::path::Path) -> i32 {
+    status(&["fmt", path.to_str().unwrap()], "")
+}
+
+#[test]
+fn tracing_evaluation() {
+    let src = "(|x: Nat| if iszero x {\n  {a = 0}.a } else { pred x }) (succ 0)";
+    let (status, stdout, stderr) = arith(&["eval", "--trace", "--no-prelude"], src);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    // Each step names the congruence rules that find the redex, the rule
+    // that reduces it, and where the redex was written.
+    let expected = "   \
+   ((|x| if iszero x { {a = 0}.a } else { pred x }) succ 0)
+-> ((|x| if iszero x { {a = 0}.a } else { pred x }) 1)  [E-App2, E-SuccNat] at <stdin>:2:32
+-> if iszero 1 { {a = 0}.a } else { pred 1 }  [E-AppAbs] at <stdin>:1:1
+-> if false { {a = 0}.a } else { pred 1 }  [E-If, E-IszeroSucc] at <stdin>:1:14
+-> pred 1  [E-IfFalse] at <stdin>:1:11
+-> 0  [E-PredSucc] at <stdin>:2:22
+";
+    assert_eq!(stdout, expected);
+
+    // Redexes from the prelude point into it.
+    let (_, stdout, _) = arith(&["eval", "--trace"], "and true false");
+    assert!(stdout.contains("at <prelude>:"), "{}", stdout);
+}
+
+#[test]
+fn width() {
+    let src = "{first = 0, second = true, third = {nested = 1.5}}";
+    let (_, stdout, _) = arith(&["eval", "--no-prelude"], src);
+    assert_eq!(stdout, format!("{}\n", src));
+    let (_, stdout, _) = arith(&["eval", "--no-prelude", "--width=30"], src);
+    assert_eq!(
+        stdout,
+        "{\n  first = 0,\n  second = true,\n  third = {nested = 1.5}\n}\n"
+    );
+    let (_, stdout, _) = arith(&["fmt", "--width=30"], src);
+    assert_eq!(
+        stdout,
+        "{\n  first = 0,\n  second = true,\n  third = {nested = 1.5}\n}\n"
+    );
+}
+
+#[test]
+fn no_prelude() {
+    assert_eq!(arith(&[], "plus 2 3").1, "5 : Nat\n");
+    let (status, _, stderr) = arith(&["--no-prelude"], "plus 2 3");
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("error[A0008]"), "{}", stderr);
+    for command in ["run", "check", "eval", "parse", "disasm", "repl"] {
+        let (status, _, stderr) = arith(&[command, "--no-prelude"], "0");
+        assert_eq!((status, stderr.as_str()), (0, ""), "{}", command);
+    }
+    // The prelude is never part of the program as written.
+    let (_, with, _) = arith(&["parse"], "def one = 1;\none");
+    let (_, without, _) = arith(&["parse", "--no-prelude"], "def one = 1;\none");
+    assert_eq!(with, without);
+}
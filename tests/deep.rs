@@ -0,0 +1,112 @@
+use arith::{
+    lexer::Symbol,
+    source::SourceFile,
+    span::Span,
+    syntax::{Context, Eval, Kind, Term, TermKind, TermKind::*, TyContext},
+};
+use std::rc::Rc;
+
+/// Deep enough to overflow the native stack if any of the walks recursed.
+const DEPTH: usize = 1_000_000;
+
+fn term(kind: TermKind) -> Rc<Term> {
+    Rc::new(Term::new(kind))
+}
+
+/// `wrap` applied `DEPTH` times to `base`. The parser is left out, as it
+/// recurses.
+fn nest(base: Rc<Term>, wrap: impl Fn(Rc<Term>) -> TermKind) -> Rc<Term> {
+    (0..DEPTH).fold(base, |t, _| term(wrap(t)))
+}
+
+fn eval() -> Eval {
+    Eval::new(Rc::new(SourceFile::new("<test>", "")))
+}
+
+fn print(term: &Term) -> String {
+    let mut buf = String::new();
+    eval().print(term, &mut Context::default(), &mut buf);
+    buf
+}
+
+fn id(tyctx: &TyContext) -> Rc<Term> {
+    term(Fun {
+        name: Symbol::intern("x"),
+        ty: tyctx.common.nat,
+        ty_span: Span::dummy(),
+        term: term(Var { idx: 0, len: 1 }),
+    })
+}
+
+#[test]
+fn succ_chain() {
+    let tyctx = &mut TyContext::new();
+    let t = nest(term(NatLit(0)), Succ);
+    let ty = eval().type_of(&t, &Context::default(), tyctx).unwrap();
+    assert_eq!(ty, tyctx.common.nat);
+    let value = eval().eval(&t, &mut Context::default(), tyctx);
+    assert_eq!(print(&value), DEPTH.to_string());
+}
+
+#[test]
+fn pred_chain() {
+    let tyctx = &mut TyContext::new();
+    let t = nest(term(NatLit(DEPTH as u64 / 2)), Pred);
+    let ty = eval().type_of(&t, &Context::default(), tyctx).unwrap();
+    assert_eq!(ty, tyctx.common.nat);
+    let value = eval().eval(&t, &mut Context::default(), tyctx);
+    assert!(matches!(value.kind, NatLit(0)));
+}
+
+#[test]
+fn application_spine() {
+    let tyctx = &mut TyContext::new();
+    let f = id(tyctx);
+    let t = nest(term(NatLit(7)), |arg| Call {
+        callee: f.clone(),
+        arg,
+    });
+    let ty = eval().type_of(&t, &Context::default(), tyctx).unwrap();
+    assert_eq!(ty, tyctx.common.nat);
+    let value = eval().eval(&t, &mut Context::default(), tyctx);
+    assert!(matches!(value.kind, NatLit(7)));
+    assert!(print(&t).starts_with("((|x| x) ((|x| x) "));
+}
+
+#[test]
+fn substitution_into_a_deep_body() {
+    let tyctx = &mut TyContext::new();
+    let body = nest(term(Var { idx: 0, len: 1 }), Succ);
+    let f = term(Fun {
+        name: Symbol::intern("x"),
+        ty: tyctx.common.nat,
+        ty_span: Span::dummy(),
+        term: body,
+    });
+    let t = term(Call {
+        callee: f,
+        arg: term(NatLit(1)),
+    });
+    let value = eval().eval(&t, &mut Context::default(), tyctx);
+    assert!(matches!(value.kind, NatLit(n) if n == DEPTH as u64 + 1));
+}
+
+#[test]
+fn type_substitution_into_a_deep_body() {
+    let tyctx = &mut TyContext::new();
+    let t = term(TyApp {
+        term: term(TyAbs {
+            name: Symbol::intern("X"),
+            kind: Kind::Star,
+            bound: tyctx.common.top,
+            bound_span: Span::dummy(),
+            term: nest(term(NatLit(0)), Succ),
+        }),
+        ty: tyctx.common.nat,
+        ty_span: Span::dummy(),
+    });
+    let ty = eval().type_of(&t, &Context::default(), tyctx).unwrap();
+    assert_eq!(ty, tyctx.common.nat);
+    let value = eval().eval(&t, &mut Context::default(), tyctx);
+    assert!(matches!(value.kind, NatLit(n) if n == DEPTH as u64));
+}
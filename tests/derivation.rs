@@ -0,0 +1,120 @@
+use arith::{
+    parser::Parser,
+    source::SourceFile,
+    syntax::{Context, Derivation, Eval, TyContext},
+};
+use std::rc::Rc;
+
+fn derive(src: &str) -> Derivation {
+    let tcx = &mut TyContext::new();
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let term = Parser::new(src.clone()).unwrap().parse_expr(tcx).unwrap();
+    Eval::new(src)
+        .derive(&term, &Context::default(), tcx)
+        .unwrap()
+}
+
+#[test]
+fn text() {
+    let expected = "\
+T-App: |- ((|x| succ x) 0) : Nat
+  T-Abs: |- (|x| succ x) : |Nat| Nat
+    T-Succ: x: Nat |- succ x : Nat
+      T-Var: x: Nat |- x : Nat
+  T-Zero: |- 0 : Nat
+";
+    assert_eq!(derive("(|x: Nat| succ x) 0").to_text(), expected);
+
+    // Subtyping side conditions come after the premises of their rule.
+    let expected = "\
+T-App: |- (((|X| (|x| x.a)) [_]) {a = 0, b = true}) : Nat
+  T-TApp: |- ((|X| (|x| x.a)) [_]) : |{a: Nat, b: Bool}| Nat
+    T-TAbs: |- (|X| (|x| x.a)) : forall X <: {a: Nat}. |X| Nat
+      T-Abs: X <: {a: Nat} |- (|x| x.a) : |X| Nat
+        T-Proj: X <: {a: Nat}, x: X |- x.a : Nat
+          T-Var: X <: {a: Nat}, x: X |- x : X
+    where {a: Nat, b: Bool} <: {a: Nat}
+  T-Rcd: |- {a = 0, b = true} : {a: Nat, b: Bool}
+    T-Zero: |- 0 : Nat
+    T-True: |- true : Bool
+";
+    let src = "(|X <: {a: Nat}| |x: X| x.a) [{a: Nat, b: Bool}] {a = 0, b = true}";
+    assert_eq!(derive(src).to_text(), expected);
+
+    let expected = "\
+T-App: |- ((|r| r.a) {a = 0, b = true}) : Nat
+  T-Abs: |- (|r| r.a) : |{a: Nat}| Nat
+    T-Proj: r: {a: Nat} |- r.a : Nat
+      T-Var: r: {a: Nat} |- r : {a: Nat}
+  T-Rcd: |- {a = 0, b = true} : {a: Nat, b: Bool}
+    T-Zero: |- 0 : Nat
+    T-True: |- true : Bool
+  where {a: Nat, b: Bool} <: {a: Nat}
+";
+    assert_eq!(
+        derive("(|r: {a: Nat}| r.a) {a = 0, b = true}").to_text(),
+        expected
+    );
+}
+
+#[test]
+fn latex() {
+    let expected = r"\begin{prooftree}
+\AxiomC{}
+\RightLabel{\scriptsize T-Var}
+\UnaryInfC{\texttt{x: Nat} $\vdash$ \texttt{x} : \texttt{Nat}}
+\RightLabel{\scriptsize T-Succ}
+\UnaryInfC{\texttt{x: Nat} $\vdash$ \texttt{succ x} : \texttt{Nat}}
+\RightLabel{\scriptsize T-Abs}
+\UnaryInfC{$\vdash$ \texttt{(|x| succ x)} : \texttt{|Nat| Nat}}
+\AxiomC{}
+\RightLabel{\scriptsize T-Zero}
+\UnaryInfC{$\vdash$ \texttt{0} : \texttt{Nat}}
+\RightLabel{\scriptsize T-App}
+\BinaryInfC{$\vdash$ \texttt{((|x| succ x) 0)} : \texttt{Nat}}
+\end{prooftree}
+";
+    assert_eq!(derive("(|x: Nat| succ x) 0").to_latex(), expected);
+
+    // Side conditions are premises without a derivation.
+    let expected = r"\begin{prooftree}
+\AxiomC{}
+\RightLabel{\scriptsize T-Zero}
+\UnaryInfC{\texttt{X <: \{\}} $\vdash$ \texttt{0} : \texttt{Nat}}
+\RightLabel{\scriptsize T-TAbs}
+\UnaryInfC{$\vdash$ \texttt{(|X| 0)} : \texttt{forall X <: \{\}. Nat}}
+\AxiomC{\texttt{\{a: Nat\} <: \{\}}}
+\RightLabel{\scriptsize T-TApp}
+\BinaryInfC{$\vdash$ \texttt{((|X| 0) [\_])} : \texttt{Nat}}
+\end{prooftree}
+";
+    assert_eq!(derive("(|X <: {}| 0) [{a: Nat}]").to_latex(), expected);
+}
+
+#[test]
+fn latex_with_many_premises() {
+    // `bussproofs` takes at most five premises, so the first five are
+    // joined into one without an inference line. Special characters are
+    // escaped.
+    let src = r#"{a = 0, b = 0, c = 0, d = 0, e = 0, f = "%$#&_~^\\"}"#;
+    let zero = r"\AxiomC{}
+\RightLabel{\scriptsize T-Zero}
+\UnaryInfC{$\vdash$ \texttt{0} : \texttt{Nat}}
+";
+    let string = r#""\%\$\#\&\_\~{}\^{}\textbackslash{}\textbackslash{}""#;
+    let expected = format!(
+        r"\begin{{prooftree}}
+{}\noLine
+\QuinaryInfC{{}}
+\AxiomC{{}}
+\RightLabel{{\scriptsize T-String}}
+\UnaryInfC{{$\vdash$ \texttt{{{s}}} : \texttt{{String}}}}
+\RightLabel{{\scriptsize T-Rcd}}
+\BinaryInfC{{$\vdash$ \texttt{{\{{a = 0, b = 0, c = 0, d = 0, e = 0, f = {s}\}}}} : \texttt{{\{{a: Nat, b: Nat, c: Nat, d: Nat, e: Nat, f: String\}}}}}}
+\end{{prooftree}}
+",
+        zero.repeat(5),
+        s = string
+    );
+    assert_eq!(derive(src).to_latex(), expected);
+}
@@ -0,0 +1,83 @@
+use arith::err::codes;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The erroneous examples of an explanation: the indented blocks after each
+/// line starting with "Erroneous example".
+fn examples(explanation: &str) -> Vec<String> {
+    let mut examples = vec![];
+    let mut lines = explanation.lines();
+    while let Some(line) = lines.next() {
+        if !line.starts_with("Erroneous example") {
+            continue;
+        }
+        let block: Vec<_> = lines
+            .by_ref()
+            .skip_while(|line| line.is_empty())
+            .take_while(|line| line.is_empty() || line.starts_with("    "))
+            .map(|line| line.strip_prefix("    ").unwrap_or(line))
+            .collect();
+        examples.push(block.join("\n").trim_end().to_owned());
+    }
+    examples
+}
+
+/// The code of the error `arith` reports for `src`.
+fn error_code(src: &str) -> Option<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_arith"))
+        .arg("--error-format=json")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(src.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    let json = arith::json::Json::parse(stderr.lines().next()?).unwrap();
+    Some(json.get("code")?.as_str()?.to_owned())
+}
+
+/// The programs of an example: A0004 lists one escape per line.
+fn split<'a>(code: &str, example: &'a str) -> Vec<&'a str> {
+    if code == codes::A0004 {
+        example.lines().collect()
+    } else {
+        vec![example]
+    }
+}
+
+#[test]
+fn examples_produce_their_own_code() {
+    let mut without = vec![];
+    for code in codes::all() {
+        let examples = examples(codes::explain(code).unwrap());
+        // A0026 needs two files and A0028 a module next to the input.
+        if examples.is_empty() || code == codes::A0026 || code == codes::A0028 {
+            without.push(code);
+            continue;
+        }
+        for example in examples {
+            for src in split(code, &example) {
+                assert_eq!(error_code(src).as_deref(), Some(code), "{}", src);
+            }
+        }
+    }
+    // The codes with no example that can be checked here. Give new codes one.
+    assert_eq!(
+        without,
+        [
+            codes::A0006,
+            codes::A0007,
+            codes::A0024,
+            codes::A0026,
+            codes::A0028,
+            codes::A0032
+        ]
+    );
+}
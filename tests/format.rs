@@ -0,0 +1,103 @@
+use arith::{format::format, source::SourceFile};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+const SOURCES: &[&str] = &[
+    "succ   succ 0",
+    "type T = {a: Nat, b: Bool};\n|t: T| t.a",
+    "def   two = |n: Nat|   succ succ n;\ntwo 0",
+    "(|x: Nat| if iszero x { {a = x, b = true} } else { {a = pred x, b = false} }) (succ succ 0)",
+    "(|X <: {a: Nat}| |x: X| {orig = x, asucc = succ x.a}) [{a: Nat, b: Bool}] {a = 0, b = true}",
+    "// Two.\n/// The successor of one.\n\
+     succ /* a block /* nested */ comment */ succ 0 // trailing",
+    "if true { // yes\n succ 0 } else { /* no */ 0 }",
+];
+
+fn fmt(src: &str, width: usize) -> String {
+    format(Rc::new(SourceFile::new("<test>", src)), width).unwrap()
+}
+
+/// Runs `arith fmt --check` on `src` and returns its exit status.
+fn fmt_check(src: &str) -> i32 {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_arith"))
+        .args(["fmt", "--check"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(src.as_bytes())
+        .unwrap();
+    child.wait().unwrap().code().unwrap()
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for src in SOURCES {
+        for width in [20, 40, 80] {
+            let once = fmt(src, width);
+            assert_eq!(fmt(&once, width), once, "width {}:\n{}", width, src);
+        }
+    }
+}
+
+#[test]
+fn check_accepts_formatted_input() {
+    for src in SOURCES {
+        assert_eq!(fmt_check(&fmt(src, 80)), 0, "{}", src);
+    }
+}
+
+#[test]
+fn check_exits_with_4_on_unformatted_input() {
+    for src in SOURCES {
+        let formatted = fmt(src, 80);
+        if formatted != *src {
+            assert_eq!(fmt_check(src), 4, "{}", src);
+        }
+    }
+    assert_eq!(fmt_check("succ   0"), 4);
+}
+
+#[test]
+fn line_comments_are_kept() {
+    let out = fmt("// first\nsucc 0 // after\n// last\n", 80);
+    assert_eq!(out, "// first\nsucc 0 // after\n// last\n");
+}
+
+#[test]
+fn nested_block_comments_are_kept() {
+    let src = "|x: Nat| /* id /* really */ */   x";
+    assert_eq!(fmt(src, 80), "|x: Nat| /* id /* really */ */ x\n");
+}
+
+#[test]
+fn doc_comments_stay_above_their_term() {
+    let out = fmt("/// The identity.\n|x: Nat|   x", 80);
+    assert!(out.starts_with("/// The identity.\n|x: Nat|"), "{}", out);
+}
+
+#[test]
+fn doc_comments_stay_on_their_definition() {
+    let src = "/// The number one.\ndef   one = succ 0;\n/// Two.\ndef two = succ one;\ntwo";
+    let out = fmt(src, 80);
+    assert_eq!(
+        out,
+        "/// The number one.\ndef one = succ 0;\n\n/// Two.\ndef two = succ one;\n\ntwo\n"
+    );
+}
+
+#[test]
+fn comments_survive_line_breaks() {
+    let src = "if true { // yes\n succ 0 } else { /* no */ 0 }";
+    for width in [10, 80] {
+        let out = fmt(src, width);
+        assert!(out.contains("// yes\n"), "{}", out);
+        assert!(out.contains("/* no */"), "{}", out);
+    }
+}
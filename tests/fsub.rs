@@ -0,0 +1,139 @@
+use arith::{
+    parser::Parser,
+    source::SourceFile,
+    syntax::{Context, Eval, SubtypingMode, TyContext, TypeId},
+};
+use std::rc::Rc;
+
+fn type_of(tcx: &mut TyContext, src: &str) -> TypeId {
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let term = Parser::new(src.clone()).unwrap().parse_expr(tcx).unwrap();
+    Eval::new(src)
+        .type_of(&term, &Context::default(), tcx)
+        .unwrap()
+}
+
+fn parse_ty(tcx: &mut TyContext, src: &str) -> TypeId {
+    let src = Rc::new(SourceFile::new("<test>", src));
+    Parser::new(src).unwrap().parse_ty(tcx).unwrap()
+}
+
+fn eval(tcx: &mut TyContext, src: &str) -> String {
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let term = Rc::new(Parser::new(src.clone()).unwrap().parse_expr(tcx).unwrap());
+    let eval = Eval::new(src);
+    let value = eval.eval(&term, &mut Context::default(), tcx);
+    let mut buf = String::new();
+    eval.print(&value, &mut Context::default(), &mut buf);
+    buf
+}
+
+fn assert_type(src: &str, expected: &str) {
+    let tcx = &mut TyContext::new();
+    let actual = type_of(tcx, src);
+    let expected = parse_ty(tcx, expected);
+    assert!(tcx.equal(&Context::default(), actual, expected), "{}", src);
+}
+
+fn is_subtype(mode: SubtypingMode, s: &str, t: &str) -> bool {
+    let tcx = &mut TyContext::new();
+    tcx.subtyping = mode;
+    let s = parse_ty(tcx, s);
+    let t = parse_ty(tcx, t);
+    tcx.subtype(&Context::default(), s, t)
+}
+
+#[test]
+fn f2poly_keeps_the_extra_fields() {
+    let f2poly = "(|X <: {a: Nat}| |x: X| {orig = x, asucc = succ x.a})";
+    assert_type(f2poly, "forall X <: {a: Nat}. |X| {orig: X, asucc: Nat}");
+
+    let ra = format!("{} [{{a: Nat, b: Bool}}] {{a = 0, b = true}}", f2poly);
+    assert_type(&ra, "{orig: {a: Nat, b: Bool}, asucc: Nat}");
+    assert_type(&format!("({}).orig.b", ra), "Bool");
+
+    let tcx = &mut TyContext::new();
+    assert_eq!(eval(tcx, &format!("({}).orig.b", ra)), "true");
+    assert_eq!(eval(tcx, &format!("({}).asucc", ra)), "1");
+}
+
+#[test]
+fn type_variables_are_promoted_to_their_bounds() {
+    assert_type(
+        "|X <: {a: Nat}| |x: X| x.a",
+        "forall X <: {a: Nat}. |X| Nat",
+    );
+    assert_type(
+        "|X <: |Nat| Bool| |f: X| f 0",
+        "forall X <: |Nat| Bool. |X| Bool",
+    );
+    assert_type(
+        "|X <: {a: Nat}| |Y <: X| |y: Y| y.a",
+        "forall X <: {a: Nat}. forall Y <: X. |Y| Nat",
+    );
+}
+
+#[test]
+fn church_numerals() {
+    let cnat = "forall X. ||X| X| |X| X";
+    let czero = "(|X| |s: |X| X| |z: X| z)";
+    let csucc = format!("(|n: {}| |X| |s: |X| X| |z: X| s (n [X] s z))", cnat);
+    assert_type(czero, cnat);
+    assert_type(&csucc, &format!("|{}| {}", cnat, cnat));
+
+    let two = format!("{} ({} {})", csucc, csucc, czero);
+    let tcx = &mut TyContext::new();
+    let to_nat = format!("({}) [Nat] (|n: Nat| succ n) 0", two);
+    assert_eq!(eval(tcx, &to_nat), "2");
+}
+
+#[test]
+fn bounded_church_booleans() {
+    // TAPL 26.3: the bound on X lets `tru` be used where a `True` is expected.
+    let sbool = "forall X. forall T <: X. forall F <: X. |T| |F| X";
+    let stru = "forall X. forall T <: X. forall F <: X. |T| |F| T";
+    let tru = "(|X| |T <: X| |F <: X| |t: T| |f: F| t)";
+    assert_type(tru, stru);
+    assert!(is_subtype(SubtypingMode::Kernel, stru, sbool));
+    assert!(!is_subtype(SubtypingMode::Kernel, sbool, stru));
+}
+
+#[test]
+fn record_subtyping() {
+    let mode = SubtypingMode::Kernel;
+    assert!(is_subtype(mode, "{a: Nat, b: Bool}", "{a: Nat}"));
+    assert!(is_subtype(mode, "{a: Nat, b: Bool}", "{b: Bool, a: Nat}"));
+    assert!(is_subtype(mode, "{r: {a: Nat, b: Bool}}", "{r: {a: Nat}}"));
+    assert!(!is_subtype(mode, "{a: Nat}", "{a: Nat, b: Bool}"));
+    assert!(is_subtype(
+        mode,
+        "|{a: Nat}| Nat",
+        "|{a: Nat, b: Bool}| Top"
+    ));
+}
+
+#[test]
+fn kernel_subtyping_requires_identical_bounds() {
+    let s = "forall X. |X| X";
+    let t = "forall X <: Nat. |X| X";
+    assert!(!is_subtype(SubtypingMode::Kernel, s, t));
+    assert!(is_subtype(SubtypingMode::Full, s, t));
+    assert!(!is_subtype(SubtypingMode::Full, t, s));
+
+    let s = "forall X <: {a: Nat}. {a: Nat}";
+    let t = "forall X <: {a: Nat, b: Bool}. Top";
+    assert!(!is_subtype(SubtypingMode::Kernel, s, t));
+    assert!(is_subtype(SubtypingMode::Full, s, t));
+}
+
+#[test]
+fn full_subtyping_checks_bodies_under_the_smaller_bound() {
+    let s = "forall X <: Top. |X| X";
+    let t = "forall X <: {a: Nat}. |X| Top";
+    assert!(is_subtype(SubtypingMode::Full, s, t));
+    assert!(!is_subtype(
+        SubtypingMode::Full,
+        "forall X <: {a: Nat}. {a: Nat}",
+        "forall X <: {a: Nat, b: Bool}. X"
+    ));
+}
@@ -0,0 +1,98 @@
+use arith::json::{self, Json};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `arith` with `args` on `src` and returns its exit status and stderr.
+fn arith(args: &[&str], src: &str) -> (i32, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_arith"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(src.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    (out.status.code().unwrap(), stderr)
+}
+
+#[test]
+fn errors_as_json() {
+    let src = "(|x: Nat|\n  x) \"tab\\t \\\"q\\\" é😀\"";
+    let (status, stderr) = arith(&["--error-format=json"], src);
+    assert_eq!(status, 2);
+    // One error, on one line.
+    assert_eq!(stderr.lines().count(), 1);
+    let json = Json::parse(&stderr).unwrap();
+
+    let keys: Vec<_> = match &json {
+        Json::Object(fields) => fields.iter().map(|(k, _)| k.as_str()).collect(),
+        _ => panic!("{}", stderr),
+    };
+    assert_eq!(
+        keys,
+        ["message", "severity", "code", "spans", "notes", "helps", "rendered"]
+    );
+    assert_eq!(
+        json.get("message").unwrap().as_str(),
+        Some("Parameter type mismatch")
+    );
+    assert_eq!(json.get("severity").unwrap().as_str(), Some("error"));
+    assert_eq!(json.get("code").unwrap().as_str(), Some("A0017"));
+    assert_eq!(json.get("notes"), Some(&Json::Array(vec![])));
+    assert_eq!(json.get("helps"), Some(&Json::Array(vec![])));
+
+    let spans = match json.get("spans") {
+        Some(Json::Array(spans)) => spans,
+        _ => panic!("{}", stderr),
+    };
+    let field = |i: usize, key| spans[i].get(key).unwrap().clone();
+    // The primary span first, with offsets in bytes and one-based lines and
+    // columns counting characters.
+    let lo = src.find('"').unwrap();
+    assert_eq!(field(0, "file_name"), Json::from("<stdin>"));
+    assert_eq!(field(0, "lo"), Json::from(lo));
+    assert_eq!(field(0, "hi"), Json::from(src.len()));
+    assert_eq!(field(0, "line_start"), Json::from(2));
+    assert_eq!(field(0, "column_start"), Json::from(6));
+    assert_eq!(field(0, "line_end"), Json::from(2));
+    assert_eq!(
+        field(0, "column_end"),
+        Json::from(src.lines().nth(1).unwrap().chars().count() + 1)
+    );
+    assert_eq!(field(0, "primary"), Json::from(true));
+    assert_eq!(
+        field(0, "label"),
+        Json::from("expected `Nat`, found `String`")
+    );
+    // The function spans both lines.
+    assert_eq!(field(1, "lo"), Json::from(0));
+    assert_eq!(field(1, "line_start"), Json::from(1));
+    assert_eq!(field(1, "line_end"), Json::from(2));
+    assert_eq!(field(1, "column_end"), Json::from(5));
+    assert_eq!(field(1, "primary"), Json::from(false));
+
+    // The rendered text is what the human format prints, escapes and all.
+    let (_, human) = arith(&[], src);
+    assert_eq!(json.get("rendered").unwrap().as_str(), Some(human.as_str()));
+    assert!(stderr.contains(r#"\"tab\\t \\\"q\\\" é😀\""#), "{}", stderr);
+}
+
+#[test]
+fn quoting() {
+    assert_eq!(json::quote(""), r#""""#);
+    assert_eq!(json::quote("a\"b\\c"), r#""a\"b\\c""#);
+    assert_eq!(json::quote("\n\r\t"), r#""\n\r\t""#);
+    assert_eq!(json::quote("\u{1}\u{1f}"), r#""\u0001\u001f""#);
+    // Everything else is written as is.
+    assert_eq!(json::quote("é😀\u{7f}"), "\"é😀\u{7f}\"");
+    for s in ["", "a\"b\\c", "\u{0}\n\u{1f} é😀"] {
+        assert_eq!(Json::parse(&json::quote(s)), Ok(Json::from(s)));
+    }
+}
@@ -0,0 +1,83 @@
+use arith::{
+    err::Diagnostic,
+    parser::Parser,
+    source::SourceFile,
+    syntax::{Context, Eval, TyContext, TypeId},
+};
+use std::rc::Rc;
+
+fn parse_ty(tcx: &mut TyContext, src: &str) -> TypeId {
+    let src = Rc::new(SourceFile::new("<test>", src));
+    Parser::new(src).unwrap().parse_ty(tcx).unwrap()
+}
+
+fn kind_of(src: &str) -> String {
+    let tcx = &mut TyContext::new();
+    let ty = parse_ty(tcx, src);
+    tcx.kind_of(&Context::default(), ty).unwrap().to_string()
+}
+
+fn equal(s: &str, t: &str) -> bool {
+    let tcx = &mut TyContext::new();
+    let s = parse_ty(tcx, s);
+    let t = parse_ty(tcx, t);
+    tcx.equal(&Context::default(), s, t)
+}
+
+fn type_of(src: &str) -> Result<String, Diagnostic> {
+    let tcx = &mut TyContext::new();
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let term = Parser::new(src.clone())?.parse_expr(tcx)?;
+    let ty = Eval::new(src).type_of(&term, &Context::default(), tcx)?;
+    Ok(tcx.display(&Context::default(), ty))
+}
+
+/// The text under the primary label of the error `src` gives.
+fn ill_kinded(src: &str) -> String {
+    let d = type_of(src).unwrap_err();
+    assert_eq!(d.code(), Some("A0023"), "{}", src);
+    let label = d.labels().iter().find(|label| label.primary).unwrap();
+    src[label.span.lo..label.span.hi].to_owned()
+}
+
+#[test]
+fn kinds_of_type_operators() {
+    assert_eq!(kind_of("Nat"), "*");
+    assert_eq!(kind_of("\\X. X"), "* => *");
+    assert_eq!(kind_of("\\X. \\Y. {fst: X, snd: Y}"), "* => * => *");
+    assert_eq!(kind_of("\\F::* => *. F Nat"), "(* => *) => *");
+    assert_eq!(kind_of("(\\X. |X| X) Bool"), "*");
+}
+
+#[test]
+fn applied_operators_are_beta_equivalent_to_their_bodies() {
+    assert!(equal("(\\X. X) Nat", "Nat"));
+    assert!(equal(
+        "(\\X. \\Y. {fst: X, snd: Y}) Nat Bool",
+        "{fst: Nat, snd: Bool}"
+    ));
+    assert!(equal("(\\F::* => *. F Nat) (\\X. |X| X)", "|Nat| Nat"));
+    assert!(equal("|(\\X. X) Nat| Bool", "|Nat| Bool"));
+    assert!(!equal("(\\X. X) Nat", "Bool"));
+}
+
+#[test]
+fn terms_see_through_applied_operators() {
+    assert_eq!(
+        type_of("(|p: (\\X. {a: X}) Nat| p.a) {a = 0}").unwrap(),
+        "Nat"
+    );
+    assert_eq!(
+        type_of("(|X::* => *| |x: X Nat| x) [\\Y. |Y| Y] (|n: Nat| n) 0").unwrap(),
+        "Nat"
+    );
+}
+
+#[test]
+fn ill_kinded_annotations_are_labelled() {
+    assert_eq!(ill_kinded("|f: Nat Bool| f"), "Nat Bool");
+    assert_eq!(ill_kinded("(|F::* => *| |x: F| x)"), "F");
+    assert_eq!(ill_kinded("(|F::* => *| 0) [Nat]"), "Nat");
+    assert_eq!(ill_kinded("(|X <: Nat Bool| 0)"), "Nat Bool");
+    assert_eq!(ill_kinded("(|n: Nat| n) ((|x: \\X. X| 0) 0)"), "\\X. X");
+}
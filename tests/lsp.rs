@@ -0,0 +1,259 @@
+//! Drives `arith-lsp` over stdio the way an editor would.
+
+use arith::json::Json;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+const URI: &str = "file:///project/main.arith";
+
+struct Client {
+    server: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: usize,
+    /// Notifications from the server not looked at yet.
+    notifications: Vec<Json>,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_arith-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = server.stdin.take().unwrap();
+        let stdout = BufReader::new(server.stdout.take().unwrap());
+        Self {
+            server,
+            stdin,
+            stdout,
+            next_id: 1,
+            notifications: vec![],
+        }
+    }
+
+    fn send(&mut self, msg: Json) {
+        let body = msg.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Json {
+        let mut len = None;
+        loop {
+            let mut line = String::new();
+            assert!(
+                self.stdout.read_line(&mut line).unwrap() > 0,
+                "server hung up"
+            );
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                len = Some(value.trim().parse().unwrap());
+            }
+        }
+        let mut body = vec![0; len.expect("missing Content-Length")];
+        self.stdout.read_exact(&mut body).unwrap();
+        Json::parse(std::str::from_utf8(&body).unwrap()).unwrap()
+    }
+
+    /// Sends a request and returns the result of its response.
+    fn request(&mut self, method: &str, params: Json) -> Json {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id.into()),
+            ("method", method.into()),
+            ("params", params),
+        ]));
+        loop {
+            let msg = self.receive();
+            if msg.get("id").and_then(Json::as_u64) == Some(id as u64) {
+                assert_eq!(msg.get("error"), None, "{}", msg);
+                return msg.get("result").cloned().unwrap();
+            }
+            self.notifications.push(msg);
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]));
+    }
+
+    /// The diagnostics published next for `URI`.
+    fn diagnostics(&mut self) -> Vec<Json> {
+        let msg = if self.notifications.is_empty() {
+            self.receive()
+        } else {
+            self.notifications.remove(0)
+        };
+        assert_eq!(
+            msg.get("method").and_then(Json::as_str),
+            Some("textDocument/publishDiagnostics")
+        );
+        let params = msg.get("params").unwrap();
+        assert_eq!(params.get("uri").and_then(Json::as_str), Some(URI));
+        match params.get("diagnostics") {
+            Some(Json::Array(diagnostics)) => diagnostics.clone(),
+            _ => panic!("no diagnostics in {}", msg),
+        }
+    }
+
+    fn open(&mut self, text: &str) -> Vec<Json> {
+        self.notify(
+            "textDocument/didOpen",
+            Json::object([(
+                "textDocument",
+                Json::object([
+                    ("uri", URI.into()),
+                    ("languageId", "arith".into()),
+                    ("version", 1usize.into()),
+                    ("text", text.into()),
+                ]),
+            )]),
+        );
+        self.diagnostics()
+    }
+
+    fn change(&mut self, text: &str) -> Vec<Json> {
+        self.notify(
+            "textDocument/didChange",
+            Json::object([
+                (
+                    "textDocument",
+                    Json::object([("uri", URI.into()), ("version", 2usize.into())]),
+                ),
+                (
+                    "contentChanges",
+                    Json::Array(vec![Json::object([("text", text.into())])]),
+                ),
+            ]),
+        );
+        self.diagnostics()
+    }
+
+    fn at(&mut self, method: &str, line: usize, character: usize) -> Json {
+        self.request(
+            method,
+            Json::object([
+                ("textDocument", Json::object([("uri", URI.into())])),
+                (
+                    "position",
+                    Json::object([("line", line.into()), ("character", character.into())]),
+                ),
+            ]),
+        )
+    }
+
+    fn shutdown(mut self) {
+        assert_eq!(self.request("shutdown", Json::Null), Json::Null);
+        self.notify("exit", Json::Null);
+        assert_eq!(self.server.wait().unwrap().code(), Some(0));
+    }
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Json {
+    let position = |(line, character): (usize, usize)| {
+        Json::object([("line", line.into()), ("character", character.into())])
+    };
+    Json::object([("start", position(start)), ("end", position(end))])
+}
+
+#[test]
+fn a_session() {
+    let mut client = Client::start();
+    let caps = client.request(
+        "initialize",
+        Json::object([("capabilities", Json::object::<&str>([]))]),
+    );
+    let caps = caps.get("capabilities").unwrap();
+    for provider in [
+        "hoverProvider",
+        "definitionProvider",
+        "documentFormattingProvider",
+    ] {
+        assert_eq!(caps.get(provider), Some(&Json::Bool(true)), "{}", provider);
+    }
+    client.notify("initialized", Json::object::<&str>([]));
+
+    let diagnostics = client.open("(|n: Nat| succ succ n)\n  true\n");
+    assert_eq!(diagnostics.len(), 1);
+    let d = &diagnostics[0];
+    assert_eq!(d.get("code").and_then(Json::as_str), Some("A0017"));
+    assert_eq!(d.get("range"), Some(&range((1, 2), (1, 6))));
+
+    assert!(client.change("(|n: Nat|   succ succ n) 0\n").is_empty());
+
+    let hover = client.at("textDocument/hover", 0, 22);
+    let contents = hover.get("contents").unwrap();
+    assert_eq!(
+        contents.get("value").and_then(Json::as_str),
+        Some("```arith\nNat\n```")
+    );
+    assert_eq!(hover.get("range"), Some(&range((0, 22), (0, 23))));
+
+    let definition = client.at("textDocument/definition", 0, 22);
+    assert_eq!(definition.get("uri").and_then(Json::as_str), Some(URI));
+    assert_eq!(definition.get("range"), Some(&range((0, 2), (0, 3))));
+
+    let edits = client.request(
+        "textDocument/formatting",
+        Json::object([
+            ("textDocument", Json::object([("uri", URI.into())])),
+            (
+                "options",
+                Json::object([("tabSize", 2usize.into()), ("insertSpaces", true.into())]),
+            ),
+        ]),
+    );
+    let edits = match edits {
+        Json::Array(edits) => edits,
+        _ => panic!("expected text edits, got {}", edits),
+    };
+    assert_eq!(edits.len(), 1);
+    assert_eq!(
+        edits[0].get("newText").and_then(Json::as_str),
+        Some("(|n: Nat| succ succ n) 0\n")
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn hover_shows_doc_comments() {
+    let mut client = Client::start();
+    client.request(
+        "initialize",
+        Json::object([("capabilities", Json::object::<&str>([]))]),
+    );
+    let src = "/// Adds two.\n/// Twice `succ`.\ndef two = |n: Nat| succ succ n;\n\
+               // Not a doc comment.\ndef id = |n: Nat| n;\n\
+               two (id 0)\n";
+    assert!(client.open(src).is_empty());
+
+    let hover = |client: &mut Client, character| {
+        let hover = client.at("textDocument/hover", 5, character);
+        let value = hover.get("contents").and_then(|c| c.get("value")).cloned();
+        value.and_then(|value| value.as_str().map(str::to_owned))
+    };
+    assert_eq!(
+        hover(&mut client, 1).as_deref(),
+        Some("```arith\n|Nat| Nat\n```\n\nAdds two.\nTwice `succ`.")
+    );
+    assert_eq!(
+        hover(&mut client, 5).as_deref(),
+        Some("```arith\n|Nat| Nat\n```")
+    );
+    // Terms other than names have no docs.
+    assert_eq!(hover(&mut client, 4).as_deref(), Some("```arith\nNat\n```"));
+
+    client.shutdown();
+}
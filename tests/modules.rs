@@ -0,0 +1,216 @@
+//! Loads the programs in `tests/modules`, most of whose imports go wrong
+//! in different ways, and programs made of a single file.
+
+use arith::{
+    err::Diagnostic,
+    module, pretty,
+    source::SourceFile,
+    syntax::{Context, TyContext},
+};
+use std::rc::Rc;
+
+fn file(path: &str) -> Rc<SourceFile> {
+    let path = format!("{}/tests/modules/{}", env!("CARGO_MANIFEST_DIR"), path);
+    let text = std::fs::read_to_string(&path).unwrap();
+    Rc::new(SourceFile::new(path, text))
+}
+
+fn load(path: &str) -> Result<module::Program, Diagnostic> {
+    module::load(file(path), &mut TyContext::new(), false)
+}
+
+/// Type checks and evaluates the program starting from `src`, without the
+/// prelude, and returns `value : type`.
+fn run(src: Rc<SourceFile>) -> Result<String, Diagnostic> {
+    let tyctx = &mut TyContext::new();
+    let program = module::load(src, tyctx, false)?;
+    let ctx = program.check_defs(tyctx)?;
+    let body = program.body()?;
+    let ty = program.eval().type_of(body, &ctx, tyctx)?;
+    let ty = tyctx.display(&ctx, ty);
+    let ctx = &mut Context::default();
+    let value = program.eval().eval(&program.link(body, tyctx), ctx, tyctx);
+    let value = pretty::pretty(&value, ctx, tyctx, pretty::DEFAULT_WIDTH);
+    Ok(format!("{} : {}", value, ty))
+}
+
+fn run_str(src: &str) -> Result<String, Diagnostic> {
+    run(Rc::new(SourceFile::new("<test>", src)))
+}
+
+fn error(path: &str) -> Diagnostic {
+    match load(path) {
+        Ok(_) => panic!("{} loaded", path),
+        Err(d) => d,
+    }
+}
+
+/// The file the error is in, and the text under its labels, primary first.
+fn labels(d: &Diagnostic) -> (&str, Vec<&str>) {
+    let file = d.file();
+    let mut labels: Vec<_> = d.labels().iter().collect();
+    labels.sort_by_key(|label| !label.primary);
+    let name = file.name.rsplit("tests/modules/").next().unwrap();
+    let texts = labels
+        .iter()
+        .map(|label| &file.text[label.span.lo..label.span.hi])
+        .collect();
+    (name, texts)
+}
+
+#[test]
+fn an_import_cycle() {
+    let d = error("cycle/a.arith");
+    assert_eq!(d.code(), Some("A0026"));
+    assert_eq!(labels(&d), ("cycle/b.arith", vec!["import \"a.arith\";"]));
+    let note = d.notes().next().unwrap();
+    let cycle: Vec<_> = note
+        .split(" -> ")
+        .map(|path| path.rsplit("tests/modules/").next().unwrap())
+        .collect();
+    assert_eq!(cycle, ["cycle/a.arith", "cycle/b.arith", "cycle/a.arith"]);
+}
+
+#[test]
+fn a_missing_file() {
+    let d = error("missing.arith");
+    assert_eq!(d.code(), Some("A0027"));
+    assert!(d.message().ends_with("nowhere.arith`"), "{}", d.message());
+    assert_eq!(
+        labels(&d),
+        ("missing.arith", vec!["import \"nowhere.arith\";"])
+    );
+}
+
+#[test]
+fn a_name_the_module_does_not_define() {
+    let d = error("unknown_use.arith");
+    assert_eq!(d.code(), Some("A0028"));
+    assert_eq!(labels(&d), ("unknown_use.arith", vec!["three"]));
+}
+
+#[test]
+fn a_name_imported_twice() {
+    let d = error("import_twice.arith");
+    assert_eq!(d.code(), Some("A0030"));
+    assert_eq!(d.message(), "`one` is imported twice");
+    assert_eq!(
+        labels(&d),
+        (
+            "import_twice.arith",
+            vec!["use other::one;", "use lib::one;"]
+        )
+    );
+}
+
+#[test]
+fn the_same_definition_imported_twice() {
+    let program = load("same_def_twice.arith").unwrap_or_else(|d| panic!("{}", d.render()));
+    assert!(program.body.is_some());
+}
+
+#[test]
+fn a_definition_of_an_imported_name() {
+    let d = error("define_imported.arith");
+    assert_eq!(d.code(), Some("A0030"));
+    assert_eq!(d.message(), "`one` is already imported");
+    assert_eq!(
+        labels(&d),
+        ("define_imported.arith", vec!["one", "use lib::one;"])
+    );
+}
+
+#[test]
+fn a_name_defined_twice() {
+    let d = error("define_twice.arith");
+    assert_eq!(d.code(), Some("A0030"));
+    assert_eq!(d.message(), "`x` is defined twice");
+    assert_eq!(labels(&d), ("define_twice.arith", vec!["x", "x"]));
+    let spans: Vec<_> = d.labels().iter().map(|label| label.span.lo).collect();
+    assert_ne!(spans[0], spans[1]);
+}
+
+#[test]
+fn types_are_global() {
+    let program = file("type_without_use.arith");
+    assert_eq!(run(program).unwrap(), "0 : Nat");
+    // So a type name cannot be declared again in another file.
+    let d = error("redeclare_type.arith");
+    assert_eq!(d.code(), Some("A0010"));
+    assert_eq!(labels(&d), ("redeclare_type.arith", vec!["Point"]));
+}
+
+#[test]
+fn declared_types_of_definitions() {
+    // The declared type is the type of the definition from then on.
+    let src = "def r: {a: Nat} = {a = 0, b = true};
+r";
+    assert_eq!(run_str(src).unwrap(), "{a = 0, b = true} : {a: Nat}");
+    let d = run_str(
+        "def r: {a: Nat} = {a = 0, b = true};
+r.b",
+    )
+    .unwrap_err();
+    assert_eq!(d.code(), Some("A0022"));
+
+    let src = "def f: |Nat| Top = |n: Nat| succ n;
+def main: Bool = f 0;
+";
+    let d = run_str(src).unwrap_err();
+    assert_eq!(d.code(), Some("A0029"));
+    let labels: Vec<_> = d
+        .labels()
+        .iter()
+        .map(|label| &src[label.span.lo..label.span.hi])
+        .collect();
+    assert_eq!(labels, ["f 0", "Bool"]);
+    assert_eq!(d.labels()[0].msg, "expected `Bool`, found `Top`");
+}
+
+#[test]
+fn the_main_definition() {
+    assert_eq!(
+        run_str(
+            "def main = succ 0;
+"
+        )
+        .unwrap(),
+        "1 : Nat"
+    );
+    let src = "def one = succ 0;
+def main = succ one;
+def unused = true;
+";
+    assert_eq!(run_str(src).unwrap(), "2 : Nat");
+    // A term at the end of the file comes first.
+    assert_eq!(
+        run_str(
+            "def main = 0;
+succ succ main"
+        )
+        .unwrap(),
+        "2 : Nat"
+    );
+    // Definitions only see the ones before them.
+    let d = run_str(
+        "def main = one;
+def one = succ 0;
+",
+    )
+    .unwrap_err();
+    assert_eq!(d.code(), Some("A0008"));
+
+    let d = run_str(
+        "def one = succ 0;
+",
+    )
+    .unwrap_err();
+    assert_eq!(d.code(), Some("A0005"));
+    assert_eq!(
+        d.message(),
+        "Expected a term to evaluate or a `main` definition"
+    );
+    // Only the file the program starts from can define it.
+    let d = run(file("imported_main.arith")).unwrap_err();
+    assert_eq!(d.code(), Some("A0005"));
+}
@@ -0,0 +1,89 @@
+use arith::{
+    parser::Parser,
+    source::SourceFile,
+    syntax::{Context, Eval, TyContext, TypeId},
+};
+use std::rc::Rc;
+
+fn parse_ty(tcx: &mut TyContext, src: &str) -> TypeId {
+    let src = Rc::new(SourceFile::new("<test>", src));
+    Parser::new(src).unwrap().parse_ty(tcx).unwrap()
+}
+
+fn equal(s: &str, t: &str) -> bool {
+    let tcx = &mut TyContext::new();
+    let s = parse_ty(tcx, s);
+    let t = parse_ty(tcx, t);
+    tcx.equal(&Context::default(), s, t)
+}
+
+fn is_subtype(s: &str, t: &str) -> bool {
+    let tcx = &mut TyContext::new();
+    let s = parse_ty(tcx, s);
+    let t = parse_ty(tcx, t);
+    tcx.subtype(&Context::default(), s, t)
+}
+
+const STREAM: &str = "mu X. {head: Nat, tail: |Top| X}";
+
+#[test]
+fn folded_and_unfolded_types_are_equal() {
+    let unfolded = format!("{{head: Nat, tail: |Top| {}}}", STREAM);
+    assert!(equal(STREAM, &unfolded));
+    assert!(equal(&unfolded, STREAM));
+    let twice = format!("{{head: Nat, tail: |Top| {}}}", unfolded);
+    assert!(equal(STREAM, &twice));
+
+    // The name of the bound variable and where the cycle starts do not matter.
+    assert!(equal(STREAM, "mu Y. {head: Nat, tail: |Top| Y}"));
+    assert!(equal("mu X. |Nat| |Nat| X", "|Nat| mu X. |Nat| |Nat| X"));
+    assert!(equal("mu X. |Nat| X", "mu X. |Nat| |Nat| X"));
+
+    assert!(!equal(STREAM, "mu X. {head: Bool, tail: |Top| X}"));
+    assert!(!equal("mu X. |Nat| X", "|Nat| Nat"));
+}
+
+#[test]
+fn terms_use_both_views_of_a_recursive_type() {
+    let tcx = &mut TyContext::new();
+    let src = format!("|s: {}| ((s.tail 0).tail true).head", STREAM);
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let term = Parser::new(src.clone()).unwrap().parse_expr(tcx).unwrap();
+    let ty = Eval::new(src)
+        .type_of(&term, &Context::default(), tcx)
+        .unwrap();
+    let expected = parse_ty(tcx, &format!("|{}| Nat", STREAM));
+    assert!(tcx.equal(&Context::default(), ty, expected));
+}
+
+#[test]
+fn non_contractive_types_are_rejected() {
+    for src in ["mu X. X", "mu X. mu Y. X", "mu X. (X)", "|Nat| mu X. X"] {
+        let src = Rc::new(SourceFile::new("<test>", src));
+        let err = Parser::new(src.clone())
+            .unwrap()
+            .parse_ty(&mut TyContext::new())
+            .unwrap_err();
+        assert_eq!(err.code(), Some("A0012"), "{}", src.text);
+    }
+    // `X` may be used once a constructor guards it.
+    assert!(equal("mu X. mu Y. |X| Y", "mu X. |X| X"));
+}
+
+#[test]
+fn subtyping_through_mu_terminates() {
+    let wide = "mu X. {head: Nat, extra: Bool, tail: |Top| X}";
+    assert!(is_subtype(wide, STREAM));
+    assert!(!is_subtype(STREAM, wide));
+
+    // Both sides unfold forever; the assumption that the pair is related
+    // stops the recursion.
+    assert!(is_subtype("mu X. |Top| X", "mu Y. |Nat| |Nat| Y"));
+    // `X` is also a parameter type, so the pair is checked both ways round
+    // and the wider record does not help.
+    assert!(!is_subtype(
+        "mu X. |X| {a: Nat, b: Bool}",
+        "mu Y. |Y| {a: Nat}"
+    ));
+    assert!(!is_subtype("mu X. |Nat| X", "mu Y. |Top| Y"));
+}
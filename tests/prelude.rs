@@ -0,0 +1,68 @@
+use arith::{
+    err::Diagnostic,
+    module, pretty,
+    source::SourceFile,
+    syntax::{Context, TyContext},
+};
+use std::rc::Rc;
+
+/// Type checks and evaluates `src`, with the prelude if `prelude` is set,
+/// and returns `value : type`.
+fn run(src: &str, prelude: bool) -> Result<String, Diagnostic> {
+    let tyctx = &mut TyContext::new();
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let program = module::load(src, tyctx, prelude)?;
+    let ctx = program.check_defs(tyctx)?;
+    let body = program.body()?;
+    let ty = program.eval().type_of(body, &ctx, tyctx)?;
+    let ty = tyctx.display(&ctx, ty);
+    let ctx = &mut Context::default();
+    let value = program.eval().eval(&program.link(body, tyctx), ctx, tyctx);
+    let value = pretty::pretty(&value, ctx, tyctx, pretty::DEFAULT_WIDTH);
+    Ok(format!("{} : {}", value, ty))
+}
+
+#[test]
+fn prelude_definitions() {
+    let src = "{a = plus 2 3, b = times 2 3, c = eq 2 3, d = fact 4, e = not (leq 3 2)}";
+    assert_eq!(
+        run(src, true).unwrap(),
+        "{a = 5, b = 6, c = false, d = 24, e = true} : \
+         {a: Nat, b: Nat, c: Bool, d: Nat, e: Bool}"
+    );
+}
+
+#[test]
+fn without_the_prelude() {
+    let d = run("plus 2 3", false).unwrap_err();
+    assert_eq!(d.code(), Some("A0008"));
+    assert_eq!(d.message(), "Unbound variable: plus");
+    // Its names are free to define.
+    assert_eq!(
+        run("def plus = |m: Nat| m;\nplus 2", false).unwrap(),
+        "2 : Nat"
+    );
+    // And nothing of it is in the program.
+    let tyctx = &mut TyContext::new();
+    let src = Rc::new(SourceFile::new("<test>", "0"));
+    let program = module::load(src, tyctx, false).unwrap();
+    assert_eq!((program.defs.len(), program.prelude), (0, 0));
+    assert_eq!(program.files.len(), 1);
+}
+
+#[test]
+fn shadowing_a_prelude_name() {
+    // The program's `plus` replaces the prelude's in the program, even with
+    // another type.
+    let src = "def plus = |m: Nat| |n: Nat| 0;\nplus 2 3";
+    assert_eq!(run(src, true).unwrap(), "0 : Nat");
+    assert_eq!(run("def plus = true;\nplus", true).unwrap(), "true : Bool");
+    // But not in the prelude, whose `times` still adds with its own.
+    let src = "def plus = |m: Nat| |n: Nat| 0;\n{a = plus 2 3, b = times 2 3}";
+    assert_eq!(run(src, true).unwrap(), "{a = 0, b = 6} : {a: Nat, b: Nat}");
+    // Parameters shadow prelude names too.
+    assert_eq!(
+        run("(|plus: Bool| plus) true", true).unwrap(),
+        "true : Bool"
+    );
+}
@@ -0,0 +1,96 @@
+use arith::{
+    parser::Parser,
+    pretty,
+    source::SourceFile,
+    syntax::{Context, Eval, TyContext},
+};
+use std::rc::Rc;
+
+const SOURCES: &[&str] = &[
+    "succ pred 0",
+    "iszero (|n: Nat| n) 0",
+    "(|f: |Nat| Nat| |n: Nat| f (f n)) (|n: Nat| succ n) 0",
+    "(|n: Nat| if iszero n { {a = n, b = true} } else { {a = pred n, b = false} }).a",
+    "if (|b: Bool| b) true { (|x: Nat| x) } else { |y: Nat| succ y }",
+    "1.0 - (2.0 - 3.0) - 4.0 / (5.0 * 6.0) + 7.0 * 8.0",
+    "(1.0 + 2.0) * 3.0",
+    "(|r: {a: {b: Nat}}| r.a.b) {a = {b = 0}}",
+    "(|X <: {a: Nat}| |x: X| x.a) [{a: Nat, b: Bool}] {a = 0, b = true}",
+    "(|F::* => *| |x: F Nat| x) [\\Y. |Y| Y]",
+    "(|s: mu X. {head: Nat, tail: |Top| X}| (s.tail 0).head)",
+    r#"{s = "tab\t\"quoted\" \\ é😀 \u{1}", empty = "", e = {}}"#,
+];
+
+/// The syntax tree of `src` without spans.
+fn tree(src: &str) -> String {
+    let tcx = &mut TyContext::new();
+    let file = Rc::new(SourceFile::new("<test>", src));
+    let term = match Parser::new(file.clone()).and_then(|mut p| p.parse_expr(tcx)) {
+        Ok(term) => term,
+        Err(d) => panic!("{}\n{}", src, d.render()),
+    };
+    let buf = &mut String::new();
+    Eval::new(file).dump(&term, &Context::default(), tcx, buf);
+    buf.lines()
+        .map(|line| line.rsplit_once(' ').map_or(line, |(node, _)| node))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn pretty(src: &str, width: usize) -> String {
+    let tcx = &mut TyContext::new();
+    let file = Rc::new(SourceFile::new("<test>", src));
+    let term = Parser::new(file).unwrap().parse_expr(tcx).unwrap();
+    pretty::pretty(&term, &Context::default(), tcx, width)
+}
+
+#[test]
+fn printed_terms_parse_back_to_themselves() {
+    for src in SOURCES {
+        for width in [1, 20, 40, pretty::DEFAULT_WIDTH] {
+            let printed = pretty(src, width);
+            assert_eq!(tree(&printed), tree(src), "{}\n{}", src, printed);
+        }
+    }
+}
+
+#[test]
+fn parentheses_only_where_needed() {
+    assert_eq!(pretty("((succ (pred (0))))", 80), "succ pred 0");
+    assert_eq!(pretty("((|x: Nat| x) (0))", 80), "(|x: Nat| x) 0");
+    assert_eq!(
+        pretty("(1.0 + 2.0) + (3.0 * 4.0)", 80),
+        "1.0 + 2.0 + 3.0 * 4.0"
+    );
+    assert_eq!(pretty("1.0 - (2.0 - 3.0)", 80), "1.0 - (2.0 - 3.0)");
+    assert_eq!(pretty("(({a = 0}).a)", 80), "{a = 0}.a");
+}
+
+#[test]
+fn shadowed_names_are_renamed() {
+    let printed = pretty("|x: Nat| |x: Nat| {a = x}", 80);
+    assert_eq!(printed, "|x: Nat| |x1: Nat| {a = x1}");
+    let printed = pretty("|x: Nat| (|x: Bool| x) true", 80);
+    assert_eq!(printed, "|x: Nat| (|x1: Bool| x1) true");
+}
+
+#[test]
+fn output_wraps_at_the_width() {
+    let src = "(|f: |Nat| Nat| |n: Nat| f (f n)) (|n: Nat| succ n) 0";
+    assert_eq!(pretty(src, 80), src);
+    let printed = pretty(src, 30);
+    assert!(printed.lines().count() > 1, "{}", printed);
+    for width in [20, 30, 40] {
+        let printed = pretty(src, width);
+        for line in printed.lines() {
+            assert!(line.chars().count() <= width, "{}:\n{}", width, printed);
+        }
+    }
+
+    let src = "{first = 0, second = true, third = {nested = 1.5}}";
+    assert_eq!(pretty(src, 80), src);
+    assert_eq!(
+        pretty(src, 30),
+        "{\n  first = 0,\n  second = true,\n  third = {nested = 1.5}\n}"
+    );
+}
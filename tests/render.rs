@@ -0,0 +1,156 @@
+use arith::{
+    err::Diagnostic,
+    source::{self, Loc, SourceFile},
+    span::Span,
+};
+use std::rc::Rc;
+
+/// The span of the first occurrence of `needle` in `src`.
+fn span(src: &SourceFile, needle: &str) -> Span {
+    let lo = src.text.find(needle).unwrap();
+    Span {
+        lo,
+        hi: lo + needle.len(),
+        file: src.id,
+    }
+}
+
+/// An A0017 in `src` whose primary label is on `primary`.
+fn diagnostic(src: &str, primary: &str, msg: &str) -> (Rc<SourceFile>, Diagnostic) {
+    let src = Rc::new(SourceFile::new("main.arith", src));
+    let d = Diagnostic::new(
+        &src,
+        "Parameter type mismatch".to_owned(),
+        span(&src, primary),
+    )
+    .with_code("A0017")
+    .with_primary_label(msg);
+    (src, d)
+}
+
+const FOOTER: &str = "For more information about this error, try `arith --explain A0017`.\n";
+
+#[test]
+fn labels_on_one_line() {
+    let (src, d) = diagnostic("(|x: Nat| x) true", "true", "expected `Nat`, found `Bool`");
+    let d = d
+        .with_label(span(&src, "(|x: Nat| x)"), "this function")
+        .with_note("the parameter is declared here")
+        .with_help("pass a `Nat`");
+    let expected = "\
+error[A0017]: Parameter type mismatch
+ --> main.arith:1:14
+  |
+1 | (|x: Nat| x) true
+  | ------------ ^^^^ expected `Nat`, found `Bool`
+  | |
+  | this function
+  |
+  = note: the parameter is declared here
+  = help: pass a `Nat`
+";
+    assert_eq!(d.render(), expected.to_owned() + FOOTER);
+}
+
+#[test]
+fn labels_over_several_lines() {
+    let src = "let\n  (|x: Nat|\n     x)\n  true";
+    let (src, d) = diagnostic(src, "(|x: Nat|\n     x)", "this function");
+    let d = d.with_label(span(&src, "true"), "this argument");
+    let expected = "\
+error[A0017]: Parameter type mismatch
+ --> main.arith:2:3
+  |
+2 |     (|x: Nat|
+  |  ___^
+3 | |      x)
+  | |_______^ this function
+4 |     true
+  |     ---- this argument
+";
+    assert_eq!(d.render(), expected.to_owned() + FOOTER);
+
+    // Long labels only show their ends, and the gutter widens for line 11.
+    let src = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+    let (src, d) = diagnostic(src, "b\nc\nd\ne\nf\ng\nh", "long");
+    let d = d.with_label(span(&src, "k"), "end");
+    let expected = "\
+error[A0017]: Parameter type mismatch
+  --> main.arith:2:1
+   |
+ 2 |   b
+   |  _^
+ 3 | | c
+...
+ 7 | | g
+ 8 | | h
+   | |_^ long
+...
+11 |   k
+   |   - end
+";
+    assert_eq!(d.render(), expected.to_owned() + FOOTER);
+}
+
+#[test]
+fn wide_characters_and_tabs() {
+    // The location counts characters, the carets terminal cells: the tab is
+    // four cells and the CJK characters and the emoji two each.
+    let (_, d) = diagnostic("\t\"日本\" 😀 true", "true", "here");
+    let expected = "\
+error[A0017]: Parameter type mismatch
+ --> main.arith:1:9
+  |
+1 |     \"日本\" 😀 true
+  |               ^^^^ here
+";
+    assert_eq!(d.render(), expected.to_owned() + FOOTER);
+}
+
+#[test]
+fn empty_source() {
+    let (_, d) = diagnostic("", "", "here");
+    let expected = "\
+error[A0017]: Parameter type mismatch
+ --> main.arith:1:1
+  |
+1 | 
+  | ^ here
+";
+    assert_eq!(d.render(), expected.to_owned() + FOOTER);
+}
+
+#[test]
+fn columns() {
+    let src = SourceFile::new("<test>", "ab\n\t日😀x\r\nlast");
+    let loc = |line, col, display_col, utf16_col| Loc {
+        line,
+        col,
+        display_col,
+        utf16_col,
+    };
+    assert_eq!(src.loc(0), loc(0, 0, 0, 0));
+    assert_eq!(src.loc(2), loc(0, 2, 2, 2));
+    assert_eq!(src.loc(3), loc(1, 0, 0, 0));
+    assert_eq!(src.loc(4), loc(1, 1, 4, 1));
+    assert_eq!(src.loc(7), loc(1, 2, 6, 2));
+    // The emoji is two UTF-16 code units.
+    assert_eq!(src.loc(11), loc(1, 3, 8, 4));
+    // Offsets inside a character move back to its start.
+    assert_eq!(src.loc(9), loc(1, 2, 6, 2));
+    // Offsets past the end are the end of the last line.
+    assert_eq!(src.loc(100), loc(2, 4, 4, 4));
+
+    assert_eq!(src.offset(1, 0), 3);
+    assert_eq!(src.offset(1, 2), 7);
+    assert_eq!(src.offset(1, 4), 11);
+    // Lines and columns past the end are clamped.
+    assert_eq!(src.offset(0, 10), 2);
+    assert_eq!(src.offset(5, 0), src.text.len());
+
+    assert_eq!(src.line(1), "\t日😀x");
+    assert_eq!(src.line_count(), 3);
+    assert_eq!(SourceFile::new("<test>", "").line_count(), 1);
+    assert_eq!(source::display_width("\t日😀x"), 9);
+    assert_eq!(source::display_width("e\u{301}"), 1);
+}
@@ -0,0 +1,74 @@
+use arith::repl::Repl;
+
+#[test]
+fn stepping_through_a_binding() {
+    let mut repl = Repl::without_prelude();
+    repl.eval_line("let x = succ 0").unwrap();
+    let steps = repl.eval_line(":step pred (succ x)").unwrap();
+    assert_eq!(
+        steps,
+        "   pred succ 1\n-> pred 2  [E-Pred, E-SuccNat]\n-> 1  [E-PredSucc]"
+    );
+}
+
+#[test]
+fn bindings_persist() {
+    let mut repl = Repl::without_prelude();
+    assert_eq!(repl.eval_line("let one = succ 0").unwrap(), "one : Nat");
+    assert_eq!(
+        repl.eval_line("let inc = |n: Nat| succ n").unwrap(),
+        "inc : |Nat| Nat"
+    );
+    assert_eq!(repl.eval_line("inc (inc one)").unwrap(), "3 : Nat");
+    // Later bindings shadow earlier ones but see them.
+    assert_eq!(repl.eval_line("let one = inc one").unwrap(), "one : Nat");
+    assert_eq!(repl.eval_line("one").unwrap(), "2 : Nat");
+    assert_eq!(repl.eval_line("type P = {a: Nat};").unwrap(), "");
+    assert_eq!(repl.eval_line(":type |p: P| p.a").unwrap(), "|P| Nat");
+    assert_eq!(repl.eval_line("").unwrap(), "");
+}
+
+#[test]
+fn errors_leave_the_session_as_it_was() {
+    let mut repl = Repl::without_prelude();
+    repl.eval_line("let x = 0").unwrap();
+    for (input, code) in [
+        ("let x = (", "A0005"),
+        ("let x = succ true", "A0015"),
+        ("let x = y", "A0008"),
+        ("let x = succ 18446744073709551615", "A0031"),
+    ] {
+        let d = repl.eval_line(input).unwrap_err();
+        assert_eq!(d.code(), Some(code), "{}", input);
+        assert_eq!(repl.eval_line("x").unwrap(), "0 : Nat", "{}", input);
+    }
+    assert_eq!(
+        repl.eval_line(":frobnicate").unwrap().lines().next(),
+        Some("Unknown command: :frobnicate")
+    );
+    assert_eq!(repl.eval_line("succ x").unwrap(), "1 : Nat");
+}
+
+#[test]
+fn stuck_results_are_errors() {
+    let mut repl = Repl::without_prelude();
+    // An overflow neither prints the stuck term nor binds it.
+    let d = repl.eval_line("succ 18446744073709551615").unwrap_err();
+    assert_eq!(d.code(), Some("A0031"));
+    let d = repl
+        .eval_line("let big = succ 18446744073709551615")
+        .unwrap_err();
+    assert_eq!(d.code(), Some("A0031"));
+    assert_eq!(repl.eval_line("big").unwrap_err().code(), Some("A0008"));
+}
+
+#[test]
+fn the_prelude_is_in_scope() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.eval_line("plus 2 3").unwrap(), "5 : Nat");
+    let mut repl = Repl::without_prelude();
+    assert_eq!(
+        repl.eval_line("plus 2 3").unwrap_err().code(),
+        Some("A0008")
+    );
+}
@@ -0,0 +1,139 @@
+use arith::{err::Diagnostic, module, source::SourceFile, syntax::TyContext};
+use std::rc::Rc;
+
+fn check(src: &str) -> Diagnostic {
+    let tyctx = &mut TyContext::new();
+    let file = Rc::new(SourceFile::new("<test>", src));
+    let result = module::load(file, tyctx, false).and_then(|program| {
+        let ctx = program.check_defs(tyctx)?;
+        program.eval().type_of(program.body()?, &ctx, tyctx)
+    });
+    match result {
+        Ok(_) => panic!("{} type checks", src),
+        Err(d) => d,
+    }
+}
+
+/// The code of the error in `src` and its labels as the text under each
+/// and the message, the primary label first.
+fn labels(src: &str) -> (&'static str, Vec<(&str, String)>) {
+    let d = check(src);
+    let labels = d
+        .labels()
+        .iter()
+        .map(|label| (&src[label.span.lo..label.span.hi], label.msg.clone()))
+        .collect();
+    (d.code().unwrap(), labels)
+}
+
+fn label<'a>(text: &'a str, msg: &str) -> (&'a str, String) {
+    (text, msg.to_owned())
+}
+
+#[test]
+fn expected_comes_before_found() {
+    assert_eq!(
+        labels("if 0 { true } else { false }"),
+        ("A0013", vec![label("0", "expected `Bool`, found `Nat`")])
+    );
+    assert_eq!(
+        labels("succ true"),
+        ("A0015", vec![label("true", "expected `Nat`, found `Bool`")])
+    );
+    assert_eq!(
+        labels("1.5 + 0"),
+        ("A0016", vec![label("0", "expected `Float`, found `Nat`")])
+    );
+    assert_eq!(
+        labels("(|f: |Nat| Bool| f 0) (|n: Nat| n)"),
+        (
+            "A0017",
+            vec![
+                label("(|n: Nat| n)", "expected `|Nat| Bool`, found `|Nat| Nat`"),
+                label(
+                    "(|f: |Nat| Bool| f 0)",
+                    "this function has type `||Nat| Bool| Bool`"
+                ),
+            ]
+        )
+    );
+    assert_eq!(
+        labels("(|x: Nat| x) [Nat]"),
+        (
+            "A0019",
+            vec![label(
+                "(|x: Nat| x)",
+                "expected a universal type, found `|Nat| Nat`"
+            )]
+        )
+    );
+    assert_eq!(
+        labels("(succ 0).a"),
+        (
+            "A0021",
+            vec![label("(succ 0)", "expected a record, found `Nat`")]
+        )
+    );
+    assert_eq!(
+        labels("def one: Bool = succ 0;\none"),
+        (
+            "A0029",
+            vec![
+                label("succ 0", "expected `Bool`, found `Nat`"),
+                label("Bool", "declared here"),
+            ]
+        )
+    );
+}
+
+#[test]
+fn labels_are_on_the_offending_subterm() {
+    // Deep inside the program, not on the whole of it.
+    assert_eq!(
+        labels("|x: Nat| if iszero x { {a = succ x} } else { {a = iszero x} }"),
+        (
+            "A0014",
+            vec![
+                label("{a = iszero x}", "but this arm has type `{a: Bool}`"),
+                label("{a = succ x}", "this arm has type `{a: Nat}`"),
+            ]
+        )
+    );
+    assert_eq!(
+        labels("|b: Bool| succ (pred b)"),
+        ("A0015", vec![label("b", "expected `Nat`, found `Bool`")])
+    );
+    assert_eq!(
+        labels("0 true"),
+        (
+            "A0018",
+            vec![
+                label("0", "this has type `Nat`, which is not a function"),
+                label("true", "but is applied to this argument"),
+            ]
+        )
+    );
+    assert_eq!(
+        labels("(|X <: {a: Nat}| |x: X| x.a) [Nat]"),
+        (
+            "A0020",
+            vec![
+                label("Nat", "expected a subtype of `{a: Nat}`, found `Nat`"),
+                label(
+                    "(|X <: {a: Nat}| |x: X| x.a)",
+                    "this has type `forall X <: {a: Nat}. |X| Nat`"
+                ),
+            ]
+        )
+    );
+    assert_eq!(
+        labels("{a = 0}.b"),
+        (
+            "A0022",
+            vec![
+                label("b", "unknown field"),
+                label("{a = 0}", "this has type `{a: Nat}`"),
+            ]
+        )
+    );
+}
@@ -0,0 +1,102 @@
+use arith::{
+    module,
+    parser::Parser,
+    source::SourceFile,
+    syntax::{Context, Eval, TyContext, TypeMap},
+};
+use std::rc::Rc;
+
+/// The text of the innermost term around the last occurrence of `needle`
+/// in `src`, and its type.
+fn type_at(src: &str, needle: &str) -> (String, String) {
+    let tcx = &mut TyContext::new();
+    let file = Rc::new(SourceFile::new("<test>", src));
+    let term = Parser::new(file.clone()).unwrap().parse_expr(tcx).unwrap();
+    let types = &mut TypeMap::new();
+    Eval::new(file)
+        .type_map(&term, &Context::default(), tcx, types)
+        .unwrap();
+    let offset = src.rfind(needle).unwrap();
+    let (span, ty) = types.type_at(offset).unwrap();
+    let ty = tcx.display(types.context_at(offset).unwrap(), ty);
+    (src[span.lo..span.hi].to_owned(), ty)
+}
+
+fn at<'a>(text: &'a str, ty: &'a str) -> (String, String) {
+    (text.to_owned(), ty.to_owned())
+}
+
+#[test]
+fn innermost_terms() {
+    let src = "(|x: Nat| {a = succ x, b = iszero x}) (pred 0)";
+    assert_eq!(type_at(src, "x}"), at("x", "Nat"));
+    assert_eq!(type_at(src, "succ"), at("succ x", "Nat"));
+    assert_eq!(type_at(src, "iszero"), at("iszero x", "Bool"));
+    assert_eq!(
+        type_at(src, "{"),
+        at("{a = succ x, b = iszero x}", "{a: Nat, b: Bool}")
+    );
+    assert_eq!(
+        type_at(src, "|x"),
+        at(
+            "(|x: Nat| {a = succ x, b = iszero x})",
+            "|Nat| {a: Nat, b: Bool}"
+        )
+    );
+    assert_eq!(type_at(src, "0"), at("0", "Nat"));
+    assert_eq!(type_at(src, " (pred"), at(src, "{a: Nat, b: Bool}"));
+
+    // Types are shown in the context of the term, so type variables keep
+    // their names.
+    let src = "|X| |Y| |f: |X| Y| |x: X| f x";
+    assert_eq!(type_at(src, "f x"), at("f", "|X| Y"));
+    assert_eq!(type_at(src, "x"), at("x", "X"));
+}
+
+#[test]
+fn recording_types_does_not_change_them() {
+    for src in [
+        "(|x: Nat| {a = succ x, b = iszero x}) (pred 0)",
+        "(|X <: {a: Nat}| |x: X| x.a) [{a: Nat, b: Bool}] {a = 0, b = true}",
+        "(|s: mu X. {head: Nat, tail: |Top| X}| (s.tail 0).head)",
+        "(|F::* => *| |x: F Nat| x) [\\Y. |Y| Y]",
+        "if true { {a = 0, b = 0} } else { {a = 0, c = 0} }",
+        "1.5 * 2.0",
+        "{a = succ 0, b = succ true}",
+        "(|x: Nat| x) true",
+    ] {
+        let tcx = &mut TyContext::new();
+        let file = Rc::new(SourceFile::new("<test>", src));
+        let term = Parser::new(file.clone()).unwrap().parse_expr(tcx).unwrap();
+        let eval = Eval::new(file);
+        let ctx = &Context::default();
+        let types = &mut TypeMap::new();
+        match (
+            eval.type_of(&term, ctx, tcx),
+            eval.type_map(&term, ctx, tcx, types),
+        ) {
+            (Ok(a), Ok(b)) => assert!(tcx.equal(ctx, a, b), "{}", src),
+            (Err(a), Err(b)) => {
+                assert_eq!(a.render(), b.render(), "{}", src);
+                // The subterms checked before the error are kept.
+                assert!(!types.is_empty(), "{}", src);
+            }
+            (a, b) => panic!("{}: {:?} and {:?}", src, a.is_ok(), b.is_ok()),
+        }
+    }
+}
+
+#[test]
+fn only_the_terms_of_one_file() {
+    let tcx = &mut TyContext::new();
+    let src = "def three = plus 1 2;\nthree";
+    let file = Rc::new(SourceFile::new("<test>", src));
+    let program = module::load(file.clone(), tcx, true).unwrap();
+    let types = &mut TypeMap::for_file(file.id);
+    program.type_map(tcx, types).unwrap();
+    // `plus 1 2`, its callee and arguments, and the body `three`.
+    assert_eq!(types.len(), 6);
+    let (span, _) = types.type_at(src.find("plus").unwrap()).unwrap();
+    assert_eq!(&src[span.lo..span.hi], "plus");
+    assert_eq!(span.file, file.id);
+}
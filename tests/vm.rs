@@ -0,0 +1,153 @@
+//! Runs programs both on the bytecode VM and with `Eval::eval` and checks
+//! that they agree.
+
+use arith::{
+    err::Diagnostic,
+    module, pretty,
+    source::SourceFile,
+    syntax::{Context, TyContext},
+};
+use std::rc::Rc;
+
+/// Evaluates `src` with the prelude both ways and returns the results,
+/// VM first.
+fn run(src: &str) -> [Result<String, Diagnostic>; 2] {
+    run_in(src, true)
+}
+
+fn run_in(src: &str, prelude: bool) -> [Result<String, Diagnostic>; 2] {
+    let tyctx = &mut TyContext::new();
+    let src = Rc::new(SourceFile::new("<test>", src));
+    let program = module::load(src, tyctx, prelude).unwrap();
+    let eval = program.eval();
+    let ctx = &program.check_defs(tyctx).unwrap();
+    let body = program.body().unwrap();
+    eval.type_of(body, ctx, tyctx).unwrap();
+
+    let ctx = &mut Context::default();
+    let chunk = program.compile(body);
+    let vm = chunk
+        .run(&eval, tyctx)
+        .map(|value| value.to_term(&chunk, &eval, tyctx))
+        .map(|value| pretty::pretty(&value, ctx, tyctx, pretty::DEFAULT_WIDTH));
+    let value = eval.eval(&program.link(body, tyctx), ctx, tyctx);
+    let tree = if value.is_val(ctx) {
+        Ok(pretty::pretty(&value, ctx, tyctx, pretty::DEFAULT_WIDTH))
+    } else {
+        Err(eval.stuck(&value))
+    };
+    [vm, tree]
+}
+
+fn agree(src: &str, expected: &str) {
+    for value in run(src) {
+        match value {
+            Ok(value) => assert_eq!(value, expected, "{}", src),
+            Err(d) => panic!("{}\n{}", src, d.render()),
+        }
+    }
+}
+
+#[test]
+fn closures() {
+    agree("(|x: Nat| |y: Bool| x) 3", "|y: Bool| 3");
+    agree("def k = |x: Nat| |y: Nat| x;\nk (succ 1)", "|y: Nat| 2");
+    agree("(|f: |Nat| Nat| |x: Nat| f (f x)) (|n: Nat| succ n) 0", "2");
+}
+
+#[test]
+fn records() {
+    agree("{a = 1, b = true}", "{a = 1, b = true}");
+    agree("{a = fact 3, b = {c = iszero 0}}.b", "{c = true}");
+    agree("{f = |x: Nat| pred x}.f 5", "4");
+}
+
+#[test]
+fn type_application() {
+    agree("(|X| |x: X| x) [Nat] 4", "4");
+    agree("|X| |x: X| x", "|X| |x: X| x");
+    agree(
+        "(|X <: {a: Nat}| |x: X| {orig = x, asucc = succ x.a}) [{a: Nat, b: Bool}] {a = 0, b = true}",
+        "{orig = {a = 0, b = true}, asucc = 1}",
+    );
+}
+
+#[test]
+fn recursion() {
+    agree("fact 5", "120");
+    agree("fib 10", "55");
+    agree("plus 20 22", "42");
+    agree("leq 4 3", "false");
+}
+
+#[test]
+fn nat_overflow() {
+    for src in [
+        "succ 18446744073709551615",
+        "(|n: Nat| succ n) 18446744073709551615",
+    ] {
+        let [vm, tree] = run(src);
+        let (vm, tree) = match (vm, tree) {
+            (Err(vm), Err(tree)) => (vm, tree),
+            (vm, tree) => panic!("{}: {:?} {:?}", src, vm.ok(), tree.ok()),
+        };
+        assert_eq!(vm.code(), Some("A0031"), "{}", src);
+        assert_eq!(tree.code(), Some("A0031"), "{}", src);
+        let spans = |d: &Diagnostic| -> Vec<_> {
+            d.labels()
+                .iter()
+                .map(|label| (label.span.lo, label.span.hi))
+                .collect()
+        };
+        assert_eq!(spans(&vm), spans(&tree), "{}", src);
+    }
+}
+
+#[test]
+fn nat_operations_under_a_binder() {
+    agree("(|x: Nat| pred (succ x)) 5", "5");
+    agree("(|x: Nat| iszero (succ x)) 5", "false");
+    agree("(|x: Nat| pred (succ (pred x))) 0", "0");
+    agree(
+        "(|x: Nat| |y: Nat| pred (succ y)) 1",
+        "|y: Nat| pred succ y",
+    );
+    agree("def f = |x: Nat| iszero (pred (succ x));\nf 0", "true");
+}
+
+/// Nat terms over a variable `x`, built from `succ`, `pred`, `iszero` and
+/// `if` up to the given depth.
+fn nat_terms(depth: usize) -> Vec<String> {
+    let atoms = ["0", "x", "18446744073709551615"].map(String::from);
+    let mut terms = atoms.to_vec();
+    for _ in 0..depth {
+        let mut next = atoms.to_vec();
+        for t in &terms {
+            next.push(format!("succ ({})", t));
+            next.push(format!("pred ({})", t));
+            for (then, els) in [("0", "x"), ("x", "succ x")] {
+                next.push(format!(
+                    "if iszero ({}) {{ {} }} else {{ {} }}",
+                    t, then, els
+                ));
+            }
+            next.push(format!("(|y: Nat| {}) (pred x)", t));
+        }
+        terms = next;
+    }
+    terms
+}
+
+#[test]
+fn well_typed_programs_never_get_stuck() {
+    for body in nat_terms(3) {
+        for arg in ["0", "3"] {
+            let src = format!("(|x: Nat| {}) {}", body, arg);
+            let [vm, tree] = run_in(&src, false);
+            for d in [&vm, &tree].iter().filter_map(|r| r.as_ref().err()) {
+                assert_eq!(d.code(), Some("A0031"), "{}", src);
+            }
+            assert_eq!(vm.ok(), tree.ok(), "{}", src);
+        }
+    }
+}